@@ -1,38 +1,100 @@
-use anyhow;
+use anyhow::{self, Context};
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
     FromSample, Sample, SizedSample,
 };
 use std::any::TypeId;
-use std::sync::mpsc;
+use ringbuf::HeapRb;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use crate::{
-    graph::{NodeGraph, NodeKey},
-    node::QuadioNode,
+    device::AudioSettings,
+    graph::{NodeKey, SocketType},
+    monitor::{Limiter, OutputMonitor},
+    param::ParamValue,
+    patch::Patch,
     sample::QuadioSample,
 };
 
 use std::sync::{Arc, Mutex};
 
-type SharedGraph = Arc<Mutex<NodeGraph<Box<dyn QuadioNode>>>>;
+type SharedGraph = Arc<Mutex<Patch>>;
 
 pub struct AudioIO {
     #[allow(dead_code)] // just keeping it alive
     stream: cpal::Stream,
+    // the thread running the graph, to be woken up and stopped
+    renderer: std::thread::Thread,
+    pub stats: Arc<StreamStats>,
+    /// what it's playing through, e.g. "ALSA: default"
+    pub device: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub block_size: usize,
+    pub queue_depth: usize,
+}
+impl AudioIO {
+    /// Roughly how long it takes what the graph makes to come out of the
+    /// speakers: the queue of rendered blocks (which the renderer keeps
+    /// full), plus however long the device says it takes.
+    pub fn latency(&self) -> Duration {
+        let queued = (self.block_size * self.queue_depth) as f64 / self.sample_rate as f64;
+        Duration::from_secs_f64(queued) + self.stats.device_latency()
+    }
+}
+impl Drop for AudioIO {
+    fn drop(&mut self) {
+        self.stats.stop.store(true, Ordering::Relaxed);
+        self.renderer.unpark();
+    }
 }
 
+/// Shared between a stream's callback, its renderer and the UI.
+#[derive(Default)]
+pub struct StreamStats {
+    /// how many times the callback ran out of rendered audio, and played
+    /// (some) silence instead
+    pub underruns: AtomicU64,
+    // nanoseconds from the callback to its audio being played, as of the
+    // last callback
+    device_latency: AtomicU64,
+    // tells the renderer to finish up
+    stop: AtomicBool,
+}
+impl StreamStats {
+    pub fn device_latency(&self) -> Duration {
+        Duration::from_nanos(self.device_latency.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Default)]
 enum DfsState {
+    #[default]
     NotVisited,
     Visiting,
     Visited,
 }
 
+/// What the engine keeps for each node from block to block.
+#[derive(Default)]
+struct NodeBuffers {
+    state: DfsState,
+    outputs: Vec<Vec<QuadioSample>>,
+    // what each input reads while it's unconnected: its value, over and over
+    constants: Vec<Vec<QuadioSample>>,
+}
+
 pub struct AudioContext {
     pub sample_rate: f32
 }
 pub struct AudioEngine {
-    buffers: slotmap::SecondaryMap<NodeKey, (DfsState, Vec<Vec<QuadioSample>>)>,
-    zeroes_buf: Vec<QuadioSample>,
+    buffers: slotmap::SecondaryMap<NodeKey, NodeBuffers>,
+    // graph generation `buffers` was last pruned against
+    graph_generation: Option<u64>,
+
+    // room for the parameters `drive_params` drives, reused node to node
+    driven: Vec<DrivenParam>,
 
     block_size: usize,
     ctx: AudioContext
@@ -41,7 +103,8 @@ impl AudioEngine {
     pub fn new(sample_rate: f32, _channels: usize) -> AudioEngine {
         AudioEngine {
             buffers: slotmap::SecondaryMap::new(),
-            zeroes_buf: Vec::with_capacity(1024),
+            graph_generation: None,
+            driven: Vec::new(),
             block_size: 1024,
             ctx: AudioContext {
                 sample_rate
@@ -50,88 +113,166 @@ impl AudioEngine {
     }
 }
 
-pub fn audio_main(graph: SharedGraph) -> anyhow::Result<AudioIO> {
-    let host = cpal::default_host();
-
-    let device = host
-        .default_output_device()
-        .expect("failed to find output device");
-    println!("Output device: {}", device.name()?);
-
-    let config = device.default_output_config().unwrap();
-    println!("Default output config: {config:?}");
+/// Starts playing `graph` through the output device `settings` asks for,
+/// using the host's defaults for anything it leaves unset.
+pub fn audio_main(graph: SharedGraph, monitor: Arc<OutputMonitor>, settings: &AudioSettings) -> anyhow::Result<AudioIO> {
+    let host = match &settings.host {
+        Some(name) => {
+            let id = cpal::available_hosts()
+                .into_iter()
+                .find(|id| id.name() == name)
+                .with_context(|| format!("no audio host called {name}"))?;
+            cpal::host_from_id(id)?
+        }
+        None => cpal::default_host(),
+    };
 
-    match config.sample_format() {
-        cpal::SampleFormat::I8 => run::<i8>(graph, &device, &config.into()),
-        cpal::SampleFormat::I16 => run::<i16>(graph, &device, &config.into()),
-        // cpal::SampleFormat::I24 => run::<I24>(&device, &config.into()),
-        cpal::SampleFormat::I32 => run::<i32>(graph, &device, &config.into()),
-        // cpal::SampleFormat::I48 => run::<I48>(&device, &config.into()),
-        cpal::SampleFormat::I64 => run::<i64>(graph, &device, &config.into()),
-        cpal::SampleFormat::U8 => run::<u8>(graph, &device, &config.into()),
-        cpal::SampleFormat::U16 => run::<u16>(graph, &device, &config.into()),
-        // cpal::SampleFormat::U24 => run::<U24>(&device, &config.into()),
-        cpal::SampleFormat::U32 => run::<u32>(graph, &device, &config.into()),
-        // cpal::SampleFormat::U48 => run::<U48>(&device, &config.into()),
-        cpal::SampleFormat::U64 => run::<u64>(graph, &device, &config.into()),
-        cpal::SampleFormat::F32 => run::<f32>(graph, &device, &config.into()),
-        cpal::SampleFormat::F64 => run::<f64>(graph, &device, &config.into()),
-        sample_format => panic!("Unsupported sample format '{sample_format}'"),
+    let device = match &settings.device {
+        Some(name) => host
+            .output_devices()?
+            .find(|d| d.name().is_ok_and(|n| &n == name))
+            .with_context(|| format!("no output device called {name}"))?,
+        None => host.default_output_device().context("no output device")?,
+    };
+    let device_name = format!("{}: {}", host.id().name(), device.name()?);
+    println!("Output device: {device_name}");
+
+    let supported = match settings.sample_rate {
+        Some(rate) => {
+            let rate = cpal::SampleRate(rate);
+            let ranges: Vec<_> = device
+                .supported_output_configs()?
+                .filter(|c| c.min_sample_rate() <= rate && rate <= c.max_sample_rate())
+                .collect();
+            // f32 if we can, since that's what we make anyway
+            let range = ranges
+                .iter()
+                .find(|c| c.sample_format() == cpal::SampleFormat::F32)
+                .or(ranges.first())
+                .with_context(|| format!("{device_name} can't do {}Hz", rate.0))?;
+            range.clone().with_sample_rate(rate)
+        }
+        None => device.default_output_config()?,
+    };
+    let mut config: cpal::StreamConfig = supported.config();
+    if let Some(frames) = settings.buffer_size {
+        config.buffer_size = cpal::BufferSize::Fixed(frames);
     }
+    println!("Output config: {config:?}");
+
+    let stats: Arc<StreamStats> = Default::default();
+    let (stream, renderer) = match supported.sample_format() {
+        cpal::SampleFormat::I8 => run::<i8>(graph, monitor, &device, &config, settings, stats.clone()),
+        cpal::SampleFormat::I16 => run::<i16>(graph, monitor, &device, &config, settings, stats.clone()),
+        // cpal::SampleFormat::I24 => run::<I24>(&device, &config),
+        cpal::SampleFormat::I32 => run::<i32>(graph, monitor, &device, &config, settings, stats.clone()),
+        // cpal::SampleFormat::I48 => run::<I48>(&device, &config),
+        cpal::SampleFormat::I64 => run::<i64>(graph, monitor, &device, &config, settings, stats.clone()),
+        cpal::SampleFormat::U8 => run::<u8>(graph, monitor, &device, &config, settings, stats.clone()),
+        cpal::SampleFormat::U16 => run::<u16>(graph, monitor, &device, &config, settings, stats.clone()),
+        // cpal::SampleFormat::U24 => run::<U24>(&device, &config),
+        cpal::SampleFormat::U32 => run::<u32>(graph, monitor, &device, &config, settings, stats.clone()),
+        // cpal::SampleFormat::U48 => run::<U48>(&device, &config),
+        cpal::SampleFormat::U64 => run::<u64>(graph, monitor, &device, &config, settings, stats.clone()),
+        cpal::SampleFormat::F32 => run::<f32>(graph, monitor, &device, &config, settings, stats.clone()),
+        cpal::SampleFormat::F64 => run::<f64>(graph, monitor, &device, &config, settings, stats.clone()),
+        sample_format => anyhow::bail!("unsupported sample format '{sample_format}'"),
+    }?;
+
+    Ok(AudioIO {
+        stream,
+        renderer,
+        stats,
+        device: device_name,
+        sample_rate: config.sample_rate.0,
+        channels: config.channels,
+        block_size: settings.block_size,
+        queue_depth: settings.queue_depth,
+    })
 }
 
 fn run<T>(
     graph: SharedGraph,
+    monitor: Arc<OutputMonitor>,
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-) -> Result<AudioIO, anyhow::Error>
+    settings: &AudioSettings,
+    stats: Arc<StreamStats>,
+) -> Result<(cpal::Stream, std::thread::Thread), anyhow::Error>
 where
     T: SizedSample + FromSample<f32>,
 {
-    let block_queue_max = 2;
-    let (tx, rx) = mpsc::sync_channel::<Vec<f32>>(block_queue_max);
+    let block_size = settings.block_size;
+    // rendered samples waiting to be played; the renderer keeps it topped up
+    let (mut queue_in, mut queue_out) = HeapRb::<f32>::new(block_size * settings.queue_depth).split();
 
     let sample_rate = config.sample_rate.0 as f32;
     let channels = config.channels as usize;
 
     let err_fn = |err| eprintln!("an error occurred on stream: {err}");
 
-    let mut main_buf: Vec<_> = std::iter::repeat(0.0f32).take(1024).collect();
-    let mut cursor = main_buf.len() - 1;
     let mut engine = AudioEngine::new(sample_rate, channels);
-
-    std::thread::spawn(move || {
-        loop {
-            // fixme: reuse this or whatever
-            let mut prod_buf: Vec<_> = std::iter::repeat(0.0f32).take(1024).collect();
-
-            engine.run_graph(&mut graph.lock().unwrap(), &mut prod_buf);
-            // we will block here (backpressure)
-            tx.send(prod_buf).unwrap();
+    let mut limiter = Limiter::default();
+
+    let renderer_stats = stats.clone();
+    let renderer = std::thread::spawn(move || {
+        let mut block = vec![0.0f32; block_size];
+        let block_duration = Duration::from_secs_f32(block_size as f32 / sample_rate);
+        while !renderer_stats.stop.load(Ordering::Relaxed) {
+            if queue_in.free_len() < block_size {
+                // the callback wakes us up when it's made room
+                std::thread::park_timeout(block_duration / 2);
+                continue;
+            }
+            engine.run_graph(&mut graph.lock().unwrap(), &mut block);
+            limiter.process(&mut block, sample_rate, &monitor);
+            queue_in.push_slice(&block);
         }
     });
-    let mut next_value = move || {
-        if cursor == main_buf.len() {
-            main_buf = rx.recv().unwrap();
-            cursor = 0;
-        }
-
-        let rv = main_buf[cursor];
-        cursor += 1;
-        rv
-    };
+    let renderer = renderer.thread().clone();
 
+    let callback_renderer = renderer.clone();
+    let callback_stats = stats.clone();
+    // underruns while the queue first fills up don't count
+    let mut started = false;
     let stream = device.build_output_stream(
         config,
-        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-            write_data(data, channels, &mut next_value)
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            // never wait here: if the renderer's fallen behind, play silence
+            let mut short = false;
+            write_data(data, channels, &mut || {
+                queue_out.pop().unwrap_or_else(|| {
+                    short = true;
+                    0.0
+                })
+            });
+            if short && started {
+                callback_stats.underruns.fetch_add(1, Ordering::Relaxed);
+            }
+            started |= !short;
+
+            let timestamp = info.timestamp();
+            if let Some(latency) = timestamp.playback.duration_since(&timestamp.callback) {
+                callback_stats.device_latency.store(latency.as_nanos() as u64, Ordering::Relaxed);
+            }
+            callback_renderer.unpark();
         },
         err_fn,
         None,
-    )?;
-    stream.play()?;
-
-    Ok(AudioIO { stream })
+    );
+    let stream = stream.map_err(anyhow::Error::from).and_then(|stream| {
+        stream.play()?;
+        Ok(stream)
+    });
+    match stream {
+        Ok(stream) => Ok((stream, renderer)),
+        Err(e) => {
+            // nothing's going to play what it renders
+            stats.stop.store(true, Ordering::Relaxed);
+            renderer.unpark();
+            Err(e)
+        }
+    }
 }
 
 fn write_data<T>(output: &mut [T], channels: usize, next_sample: &mut dyn FnMut() -> f32)
@@ -146,29 +287,72 @@ where
     }
 }
 
-impl AudioEngine {
-    fn run_graph(&mut self, graph: &mut NodeGraph<Box<dyn QuadioNode>>, output: &mut [f32]) {
-        self.block_size = output.len();
+/// The shortest piece `run_graph_node` splits a block into for parameters
+/// that can't follow per-sample values, so wobbling one of those at audio
+/// rate doesn't mean calling `process` for every sample.
+const MIN_PIECE: usize = 32;
+
+/// `buf`'s part of `range` of the block: that stretch for full-rate
+/// buffers, the whole thing for control-rate ones.
+fn piece(buf: &[QuadioSample], range: std::ops::Range<usize>, block_size: usize) -> &[QuadioSample] {
+    if buf.len() == block_size {
+        &buf[range]
+    } else {
+        buf
+    }
+}
+fn piece_mut(buf: &mut [QuadioSample], range: std::ops::Range<usize>, block_size: usize) -> &mut [QuadioSample] {
+    if buf.len() == block_size {
+        &mut buf[range]
+    } else {
+        buf
+    }
+}
 
-        self.zeroes_buf
-            .resize(self.block_size, QuadioSample::from(0.0));
+/// How many samples a buffer for a socket of type `ty` holds per block.
+fn buffer_len(ty: SocketType, block_size: usize) -> usize {
+    match ty {
+        SocketType::Complex | SocketType::Real | SocketType::Event => block_size,
+        SocketType::Control => 1,
+    }
+}
 
-        for (node_key, _) in graph.nodes() {
-            if !self.buffers.contains_key(node_key) {
-                self.buffers
-                    .insert(node_key, (DfsState::NotVisited, Vec::new()));
-            }
+impl AudioEngine {
+    /// Renders one block of the main graph into `output`, and moves its
+    /// automation along.
+    pub fn run_graph(&mut self, graph: &mut Patch, output: &mut [f32]) {
+        let started = Instant::now();
+        self.render_block(graph, output);
+
+        let block_seconds = self.block_size as f64 / self.ctx.sample_rate as f64;
+        graph.profile_mut().record_block(started.elapsed(), block_seconds);
+        graph.automation_mut().advance(block_seconds);
+    }
+
+    /// Runs one block of a graph that isn't the main one (a subgraph's
+    /// insides), pulling on `sinks` instead of the output node, and moves its
+    /// automation along. What the sinks do with their inputs is up to them.
+    pub fn run_sinks(
+        &mut self,
+        ctx: &AudioContext,
+        graph: &mut Patch,
+        sinks: &[NodeKey],
+        block_size: usize,
+    ) {
+        self.ctx.sample_rate = ctx.sample_rate;
+        self.block_size = block_size;
+        self.prepare(graph);
+        for &sink in sinks {
+            self.run_graph_node(graph, sink);
         }
+        self.tap_probe(graph);
 
-        for (node_key, (dfs_state, bufs)) in self.buffers.iter_mut() {
-            *dfs_state = DfsState::NotVisited;
+        graph.automation_mut().advance(block_size as f64 / ctx.sample_rate as f64);
+    }
 
-            let num_outputs = graph.node_descriptor(node_key).output_sockets.len();
-            bufs.resize(num_outputs, Vec::new());
-            for buf in bufs {
-                buf.resize(self.block_size, QuadioSample::from(0.0));
-            }
-        }
+    fn render_block(&mut self, graph: &mut Patch, output: &mut [f32]) {
+        self.block_size = output.len();
+        self.prepare(graph);
 
         let output_node = {
             let mut outputs = graph.nodes().filter(|(_key, node)| {
@@ -186,6 +370,7 @@ impl AudioEngine {
         };
 
         self.run_graph_node(graph, output_node);
+        self.tap_probe(graph);
 
         let Some((src_node, src_idx)) = graph.src_for_dest(output_node, 0) else {
                      // unconnected output, no audio
@@ -193,7 +378,7 @@ impl AudioEngine {
                      return;
         };
 
-        for (computed_sample, output_sample) in self.buffers[src_node].1[src_idx]
+        for (computed_sample, output_sample) in self.buffers[src_node].outputs[src_idx]
             .iter()
             .zip(output.iter_mut())
         {
@@ -201,54 +386,402 @@ impl AudioEngine {
         }
     }
 
-    fn run_graph_node(&mut self, graph: &mut NodeGraph<Box<dyn QuadioNode>>, node: NodeKey) {
-        let (state, _) = &mut self.buffers[node];
+    /// Hands the probed output's buffer to the probe, if something's being
+    /// probed and it ran this block.
+    fn tap_probe(&self, graph: &mut Patch) {
+        let Some((node, idx)) = graph.probe().target() else {
+            return;
+        };
+        if let Some(NodeBuffers { state: DfsState::Visited, outputs, .. }) = self.buffers.get(node) {
+            if let Some(buf) = outputs.get(idx) {
+                graph.probe_mut().tap(buf);
+            }
+        }
+    }
+
+    /// Gets buffers ready for a block: forgets removed nodes, makes room for
+    /// new ones, and sizes everything for the current descriptors. Nothing
+    /// gets allocated here unless something about the graph has changed.
+    fn prepare(&mut self, graph: &Patch) {
+        if self.graph_generation != Some(graph.generation()) {
+            // drop buffers belonging to nodes that have since been removed
+            self.buffers.retain(|node_key, _| graph.contains_node(node_key));
+            self.graph_generation = Some(graph.generation());
+        }
 
-        match state {
-            DfsState::NotVisited => {
-                self.buffers[node].0 = DfsState::Visiting;
+        for (node_key, _) in graph.nodes() {
+            if !self.buffers.contains_key(node_key) {
+                self.buffers.insert(node_key, NodeBuffers::default());
+            }
+        }
 
-                let num_inputs = graph.node_descriptor(node).input_sockets.len();
-                let num_outputs = graph.node_descriptor(node).output_sockets.len();
+        for (node_key, bufs) in self.buffers.iter_mut() {
+            bufs.state = DfsState::NotVisited;
 
-                let mut inputs_ind: Vec<_> = std::iter::repeat(None).take(num_inputs).collect();
+            let descriptor = graph.node_descriptor(node_key);
+            bufs.outputs.resize_with(descriptor.output_sockets.len(), Vec::new);
+            for (buf, socket) in bufs.outputs.iter_mut().zip(&descriptor.output_sockets) {
+                buf.resize(buffer_len(socket.ty, self.block_size), QuadioSample::from(0.0));
+            }
 
-                for (i, input) in inputs_ind.iter_mut().enumerate() {
-                    if let Some((src_node, src_idx)) = graph.src_for_dest(node, i) {
-                        self.run_graph_node(graph, src_node);
-                        *input = Some((src_node, src_idx));
-                    }
+            bufs.constants.resize_with(descriptor.input_sockets.len(), Vec::new);
+            for (i, (buf, socket)) in bufs.constants.iter_mut().zip(&descriptor.input_sockets).enumerate() {
+                let value = graph.input_value(node_key, i);
+                buf.resize(buffer_len(socket.ty, self.block_size), value);
+                // it's all one value, so only refill when that's changed
+                if buf[0] != value {
+                    buf.fill(value);
                 }
+            }
+        }
+    }
+
+    fn run_graph_node(&mut self, graph: &mut Patch, node: NodeKey) {
+        match self.buffers[node].state {
+            DfsState::NotVisited => (),
+            DfsState::Visiting => {
+                eprintln!("cycle... uh...oh...");
+                return;
+            }
+            DfsState::Visited => {
+                // nop!
+                return;
+            }
+        }
+        self.buffers[node].state = DfsState::Visiting;
+
+        let num_inputs = graph.node_descriptor(node).input_sockets.len();
+        for i in 0..num_inputs {
+            if let Some((src_node, _)) = graph.src_for_dest(node, i) {
+                self.run_graph_node(graph, src_node);
+            }
+        }
 
-                let inputs: Vec<_> = inputs_ind
-                    .into_iter()
-                    .map(|x| {
-                        if let Some((n, i)) = x {
-                            &self.buffers[n].1[i]
-                        } else {
-                            &self.zeroes_buf
-                        }
-                        .as_slice()
-                    })
+        // taken out of the map while the node writes them, since its inputs
+        // are borrowed from there (and can't include its own outputs)
+        let mut outputs = std::mem::take(&mut self.buffers[node].outputs);
+        let mut driven = std::mem::take(&mut self.driven);
+        for buf in &mut outputs {
+            buf.fill(QuadioSample::from(0.0));
+        }
+        let inputs: Vec<&[QuadioSample]> = (0..num_inputs)
+            .map(|i| match graph.src_for_dest(node, i) {
+                Some((src_node, src_idx)) => self.buffers[src_node].outputs[src_idx].as_slice(),
+                None => self.buffers[node].constants[i].as_slice(),
+            })
+            .collect();
+
+        let (own_inputs, modulation_bufs) = inputs.split_at(graph.modulation_inputs(node).start);
+
+        let started = Instant::now();
+        let num_driven = self.drive_params(graph, node, modulation_bufs, &mut driven);
+        let node_mut = graph.get_node_mut(node);
+        if driven[..num_driven].iter().all(|d| d.followed) {
+            let mut outputs: Vec<_> = outputs.iter_mut().map(Vec::as_mut_slice).collect();
+            node_mut.process(&self.ctx, own_inputs, &mut outputs);
+        } else {
+            let mut start = 0;
+            while start < self.block_size {
+                let end = next_split(&driven[..num_driven], start, self.block_size);
+                for d in driven[..num_driven].iter().filter(|d| !d.followed) {
+                    node_mut.set_param(d.idx, d.values[start]);
+                }
+                let inputs: Vec<_> = own_inputs
+                    .iter()
+                    .map(|buf| piece(buf, start..end, self.block_size))
                     .collect();
-                let mut outputs_all: Vec<QuadioSample> = std::iter::repeat(QuadioSample::from(0.0))
-                    .take(self.block_size * num_outputs)
+                let mut outputs: Vec<_> = outputs
+                    .iter_mut()
+                    .map(|buf| piece_mut(buf, start..end, self.block_size))
                     .collect();
-                let mut outputs: Vec<_> = outputs_all.chunks_mut(self.block_size).collect();
+                node_mut.process(&self.ctx, &inputs, &mut outputs);
+                start = end;
+            }
+        }
+        for d in &driven[..num_driven] {
+            node_mut.set_param(d.idx, d.settle);
+        }
+        let block_seconds = self.block_size as f64 / self.ctx.sample_rate as f64;
+        graph.profile_mut().record_node(node, started.elapsed(), block_seconds);
+
+        for (i, buf) in outputs.iter_mut().enumerate() {
+            if graph.node_descriptor(node).output_sockets[i].ty == SocketType::Real {
+                // keep real sockets honest
+                buf.iter_mut().for_each(|x| x.im = 0.0);
+            }
+            if let Some(fault) = crate::monitor::sanitize(buf) {
+                // logged later, from the UI
+                graph.faults_mut().report(node, i, fault);
+            }
+        }
+        self.buffers[node].outputs = outputs;
+        self.buffers[node].state = DfsState::Visited;
+        self.driven = driven;
+    }
 
-                graph.get_node_mut(node).process(&self.ctx, &inputs, &mut outputs);
+    /// Works out, sample by sample, the values of a node's automated and
+    /// exposed parameters for the block (the lane's value, plus depth times
+    /// the modulating input) into `driven`, and hands them to the node.
+    /// Returns how many of `driven` are in use. Each one's `settle` is what
+    /// it's left at once the block's done: where automation put it, or its
+    /// base value, so the UI follows the automation but never sees the
+    /// modulation wiggling.
+    fn drive_params(
+        &self,
+        graph: &mut Patch,
+        node_key: NodeKey,
+        modulation_bufs: &[&[QuadioSample]],
+        driven: &mut Vec<DrivenParam>,
+    ) -> usize {
+        if modulation_bufs.is_empty() && !graph.automation().drives(node_key) {
+            return 0;
+        }
 
-                for (i, output_tmp) in outputs.into_iter().enumerate() {
-                    self.buffers[node].1[i].copy_from_slice(output_tmp)
+        // reuses what's already in `driven` (and the room in its `values`)
+        fn claim<'a>(driven: &'a mut Vec<DrivenParam>, n: &mut usize, idx: usize, settle: ParamValue) -> &'a mut DrivenParam {
+            if driven.len() == *n {
+                driven.push(DrivenParam { idx, values: vec![], followed: false, settle });
+            }
+            let d = &mut driven[*n];
+            *n += 1;
+            d.idx = idx;
+            d.settle = settle;
+            d.values.clear();
+            d
+        }
+
+        let node = graph.get_node(node_key);
+        // skipping lanes and modulations of params that don't exist (anymore)
+        let mut n = 0;
+        let automation = graph.automation();
+        let sample_seconds = 1.0 / self.ctx.sample_rate as f64;
+        let time_of = |t: usize| automation.position() + t as f64 * sample_seconds;
+        for lane in automation.playing_lanes(node_key) {
+            // settles where the next block starts
+            let (Some(idx), Some(settle)) = (node.param_index(&lane.param), lane.value_at(time_of(self.block_size))) else {
+                continue;
+            };
+            let d = claim(driven, &mut n, idx, settle);
+            d.values.extend((0..self.block_size).map(|t| lane.value_at(time_of(t)).unwrap_or(settle)));
+        }
+        for (m, &buf) in graph.modulations(node_key).iter().zip(modulation_bufs) {
+            let Some(idx) = node.param_index(&m.param).filter(|_| !buf.is_empty()) else {
+                continue;
+            };
+            let d = match driven[..n].iter().position(|d| d.idx == idx) {
+                Some(i) => &mut driven[i],
+                None => {
+                    let base = node.param(idx);
+                    let d = claim(driven, &mut n, idx, base);
+                    d.values.resize(self.block_size, base);
+                    d
                 }
-                self.buffers[node].0 = DfsState::Visited;
+            };
+            for (t, value) in d.values.iter_mut().enumerate() {
+                // a control-rate input has the one value for the whole block
+                *value = value.modulated(m.depth, buf[t.min(buf.len() - 1)]);
             }
-            DfsState::Visiting => {
-                eprintln!("cycle... uh...oh...");
+        }
+
+        let descriptors = node.param_descriptors();
+        for d in &mut driven[..n] {
+            let descriptor = &descriptors[d.idx];
+            d.settle = descriptor.clamp(d.settle);
+            for value in &mut d.values {
+                *value = descriptor.clamp(*value);
             }
-            DfsState::Visited => {
-                // nop!
+        }
+        let node = graph.get_node_mut(node_key);
+        for d in &mut driven[..n] {
+            d.followed = node.modulate_param(d.idx, &d.values);
+        }
+        n
+    }
+}
+
+/// Where the piece of the block starting at `start` should end: just before
+/// the next sample where a driven parameter the node can't follow changes,
+/// but no sooner than `MIN_PIECE` samples in.
+fn next_split(driven: &[DrivenParam], start: usize, block_size: usize) -> usize {
+    let changes_at = |t: usize| {
+        driven
+            .iter()
+            .any(|d| !d.followed && d.values[t] != d.values[t - 1])
+    };
+    let change = (start + 1..block_size).find(|&t| changes_at(t)).unwrap_or(block_size);
+    change.max(start + MIN_PIECE).min(block_size)
+}
+
+/// A parameter `drive_params` is driving this block.
+struct DrivenParam {
+    idx: usize,
+    /// its value for each sample of the block
+    values: Vec<ParamValue>,
+    /// whether the node plays `values` out itself (see `Params::modulate_param`)
+    followed: bool,
+    /// what it's left at once the block's done
+    settle: ParamValue,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::Fault;
+    use crate::graph::{self, NodeDescriptor, SocketDescriptor};
+    use crate::node::{LinearNode, OutputNode, QuadioNode};
+    use crate::param::ParamDescriptor;
+
+    /// counts samples, for something to modulate with
+    #[derive(Default)]
+    struct CountNode {
+        t: f32,
+    }
+    impl graph::Node for CountNode {
+        fn get_descriptor(&self) -> NodeDescriptor {
+            NodeDescriptor {
+                input_sockets: vec![],
+                output_sockets: vec![SocketDescriptor::default()],
+            }
+        }
+    }
+    crate::params!(CountNode {});
+    impl QuadioNode for CountNode {
+        fn show_ui(&mut self, _ui: &mut egui::Ui) {}
+        fn process(&mut self, _ctx: &AudioContext, _inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+            for out in outputs[0].iter_mut() {
+                *out = QuadioSample::from(self.t);
+                self.t += 1.0;
+            }
+        }
+    }
+
+    /// puts out its `level`, which (not being `Smoothed`) can't follow modulation by itself
+    #[derive(Default)]
+    struct LevelNode {
+        level: f32,
+    }
+    impl graph::Node for LevelNode {
+        fn get_descriptor(&self) -> NodeDescriptor {
+            NodeDescriptor {
+                input_sockets: vec![],
+                output_sockets: vec![SocketDescriptor::default()],
             }
         }
     }
+    crate::params!(LevelNode {
+        level => ParamDescriptor::real("level", 0.0..=1024.0),
+    });
+    impl QuadioNode for LevelNode {
+        fn show_ui(&mut self, _ui: &mut egui::Ui) {}
+        fn process(&mut self, _ctx: &AudioContext, _inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+            outputs[0].fill(QuadioSample::from(self.level));
+        }
+    }
+
+    #[test]
+    fn modulation_follows_the_input_every_sample() {
+        let mut graph = Patch::default();
+        let count = graph.add_node(Box::new(CountNode::default()));
+        let linear = graph.add_node(Box::new(LinearNode::default()));
+        let output = graph.add_node(Box::new(OutputNode));
+        graph.connect((linear, 0), (output, 0)).unwrap();
+        // nothing on In, so what comes out is just b
+        graph.expose_param(linear, "b");
+        graph.connect((count, 0), (linear, 1)).unwrap();
+
+        let mut engine = AudioEngine::new(48000.0, 1);
+        let mut block = vec![0.0; 64];
+        engine.run_graph(&mut graph, &mut block);
+        let expected: Vec<f32> = (0..64).map(|t| t as f32).collect();
+        assert_eq!(block, expected);
+
+        // the modulation doesn't stick
+        let b = graph.get_node(linear).param_index("b").unwrap();
+        assert_eq!(graph.get_node(linear).param(b), ParamValue::Complex(QuadioSample::new(0.0, 0.0)));
+
+        engine.run_graph(&mut graph, &mut block);
+        let expected: Vec<f32> = (64..128).map(|t| t as f32).collect();
+        assert_eq!(block, expected);
+    }
+
+    #[test]
+    fn plain_params_are_modulated_piece_by_piece() {
+        let mut graph = Patch::default();
+        let count = graph.add_node(Box::new(CountNode::default()));
+        let level = graph.add_node(Box::new(LevelNode::default()));
+        let output = graph.add_node(Box::new(OutputNode));
+        graph.connect((level, 0), (output, 0)).unwrap();
+        graph.expose_param(level, "level");
+        graph.connect((count, 0), (level, 0)).unwrap();
+
+        let mut engine = AudioEngine::new(48000.0, 1);
+        let mut block = vec![0.0; 3 * MIN_PIECE];
+        engine.run_graph(&mut graph, &mut block);
+        // it changes every sample, so each piece is as short as they get
+        let expected: Vec<f32> = (0..3 * MIN_PIECE).map(|t| (t / MIN_PIECE * MIN_PIECE) as f32).collect();
+        assert_eq!(block, expected);
+        assert_eq!(graph.get_node(level).param(0), ParamValue::Real(0.0));
+    }
+
+    #[test]
+    fn automation_lands_mid_block() {
+        let mut graph = Patch::default();
+        let linear = graph.add_node(Box::new(LinearNode::default()));
+        let level = graph.add_node(Box::new(LevelNode::default()));
+        let output = graph.add_node(Box::new(OutputNode));
+        let sample_rate = 48000.0;
+        let at = |t: f64| t / sample_rate as f64;
+
+        let automation = graph.automation_mut();
+        automation.set_recording(true);
+        // b holds at 0 until sample 32, then ramps up 16 over the next 16
+        for (time, value) in [(0.0, 0.0), (32.0, 0.0), (48.0, 16.0)] {
+            automation.set_position(at(time));
+            automation.record(linear, "b", ParamValue::Complex(QuadioSample::new(value, 0.0)));
+        }
+        // and level steps up to 5 at sample 40
+        for (time, value) in [(0.0, 0.0), (39.5, 0.0), (39.6, 5.0)] {
+            automation.set_position(at(time));
+            automation.record(level, "level", ParamValue::Real(value));
+        }
+        automation.set_recording(false);
+        automation.set_position(0.0);
+        automation.set_playing(true);
+
+        let mut engine = AudioEngine::new(sample_rate, 1);
+        let mut block = vec![0.0; 64];
+        graph.connect((linear, 0), (output, 0)).unwrap();
+        engine.run_graph(&mut graph, &mut block);
+        for (t, &x) in block.iter().enumerate() {
+            let expected = (t as f32 - 32.0).clamp(0.0, 16.0);
+            assert!((x - expected).abs() < 1e-3, "sample {t}: {block:?}");
+        }
+
+        graph.automation_mut().set_position(0.0);
+        graph.connect((level, 0), (output, 0)).unwrap();
+        engine.run_graph(&mut graph, &mut block);
+        let expected: Vec<f32> = (0..64).map(|t| if t < 40 { 0.0 } else { 5.0 }).collect();
+        assert_eq!(block, expected);
+    }
+
+    #[test]
+    fn faults_wait_for_the_ui() {
+        let mut graph = Patch::default();
+        let linear = graph.add_node(Box::new(LinearNode::default()));
+        let output = graph.add_node(Box::new(OutputNode));
+        graph.connect((linear, 0), (output, 0)).unwrap();
+        graph.set_input_value(linear, 0, QuadioSample::new(f32::NAN, 0.0));
+
+        let mut engine = AudioEngine::new(48000.0, 1);
+        let mut block = vec![0.0; 64];
+        engine.run_graph(&mut graph, &mut block);
+        engine.run_graph(&mut graph, &mut block);
+        assert!(block.iter().all(|&x| x == 0.0), "{block:?}");
+
+        // the second block isn't news
+        let unlogged: Vec<_> = graph.faults_mut().take_unlogged().collect();
+        assert_eq!(unlogged, vec![(linear, 0, Fault::NaN)]);
+        assert_eq!(graph.faults().recent(linear), Some(Fault::NaN));
+        assert_eq!(graph.faults_mut().take_unlogged().count(), 0);
+    }
 }
@@ -0,0 +1,400 @@
+//! Automation: parameter changes recorded over time and played back.
+//!
+//! While recording, every edit made in the node UI lands as a breakpoint in
+//! that parameter's lane, at the current transport position. While playing,
+//! the engine reads automated parameters off their lanes for every sample,
+//! so breakpoints land where they are whatever the block size (see
+//! `Params::modulate_param` for how the values reach the node).
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{NodeKey};
+use crate::patch::Patch;
+use crate::param::{ParamDescriptor, ParamKind, ParamType, ParamValue};
+
+use num_complex::Complex32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Breakpoint {
+    /// seconds since the start of the timeline
+    pub time: f64,
+    pub value: ParamValue,
+}
+
+/// The breakpoints of one parameter. Values are interpolated in between for
+/// reals, complexes (in polar form, see `param::interpolate`) and ints, and
+/// held for everything else.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Lane {
+    pub node: NodeKey,
+    /// the parameter's name (see `ParamDescriptor::name`)
+    pub param: String,
+    /// sorted by time
+    pub points: Vec<Breakpoint>,
+
+    // set once this lane's been written to during the current recording
+    // pass; from then on, recording owns it and playback leaves it alone
+    #[serde(skip)]
+    latched_at: Option<f64>,
+}
+impl Lane {
+    pub fn new(node: NodeKey, param: impl Into<String>) -> Lane {
+        Lane {
+            node,
+            param: param.into(),
+            points: vec![],
+            latched_at: None,
+        }
+    }
+
+    /// What the parameter should be at `time`; `None` for an empty lane.
+    pub fn value_at(&self, time: f64) -> Option<ParamValue> {
+        let next = self.points.partition_point(|p| p.time <= time);
+        let Some(a) = next.checked_sub(1).map(|i| self.points[i]) else {
+            return self.points.first().map(|p| p.value);
+        };
+        let Some(b) = self.points.get(next) else {
+            return Some(a.value);
+        };
+        let t = ((time - a.time) / (b.time - a.time)) as f32;
+        Some(match (a.value, b.value) {
+            (ParamValue::Real(_), ParamValue::Real(_))
+            | (ParamValue::Complex(_), ParamValue::Complex(_))
+            | (ParamValue::Int(_), ParamValue::Int(_)) => crate::param::interpolate(a.value, b.value, t),
+            // steps
+            _ => a.value,
+        })
+    }
+
+    /// Adds a breakpoint, keeping them sorted.
+    pub fn insert(&mut self, point: Breakpoint) {
+        let idx = self.points.partition_point(|p| p.time <= point.time);
+        self.points.insert(idx, point);
+    }
+}
+
+/// All the lanes in a graph, plus the transport they play along to.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Automation {
+    lanes: Vec<Lane>,
+
+    #[serde(skip)]
+    playing: bool,
+    #[serde(skip)]
+    recording: bool,
+    /// seconds
+    #[serde(skip)]
+    position: f64,
+}
+impl Automation {
+    pub fn lanes(&self) -> &[Lane] {
+        &self.lanes
+    }
+    pub fn lanes_mut(&mut self) -> &mut [Lane] {
+        &mut self.lanes
+    }
+    pub fn remove_lane(&mut self, idx: usize) {
+        self.lanes.remove(idx);
+    }
+    /// drops every lane belonging to `node`
+    pub fn remove_node(&mut self, node: NodeKey) {
+        self.lanes.retain(|lane| lane.node != node);
+    }
+
+    /// The lanes playback should apply to `node` right now.
+    pub fn playing_lanes(&self, node: NodeKey) -> impl Iterator<Item = &Lane> {
+        self.lanes.iter().filter(move |lane| {
+            self.playing && lane.node == node && !lane.points.is_empty() && lane.latched_at.is_none()
+        })
+    }
+    pub fn drives(&self, node: NodeKey) -> bool {
+        self.playing_lanes(node).next().is_some()
+    }
+
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+    pub fn recording(&self) -> bool {
+        self.recording
+    }
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+        if !recording {
+            for lane in &mut self.lanes {
+                lane.latched_at = None;
+            }
+        }
+    }
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+    pub fn set_position(&mut self, position: f64) {
+        self.position = position.max(0.0);
+    }
+    /// called by the engine after every block
+    pub fn advance(&mut self, seconds: f64) {
+        if self.playing {
+            self.position += seconds;
+        }
+    }
+
+    /// Writes `value` into `param`'s lane at the current position (if we're
+    /// recording), replacing whatever this pass had already gone over.
+    pub fn record(&mut self, node: NodeKey, param: &str, value: ParamValue) {
+        if !self.recording {
+            return;
+        }
+        let idx = match self.lanes.iter().position(|l| l.node == node && l.param == param) {
+            Some(idx) => idx,
+            None => {
+                self.lanes.push(Lane::new(node, param));
+                self.lanes.len() - 1
+            }
+        };
+        let lane = &mut self.lanes[idx];
+        let now = self.position;
+        let since = lane.latched_at.unwrap_or(now);
+        lane.points.retain(|p| !(p.time > since && p.time <= now) && p.time != now);
+        lane.insert(Breakpoint { time: now, value });
+        lane.latched_at = Some(now);
+    }
+}
+
+/// where `value` sits between the bottom (0) and top (1) of a lane
+fn to_unit(descriptor: &ParamDescriptor, value: ParamValue) -> f32 {
+    let unit = match &descriptor.kind {
+        ParamKind::Real { range } => {
+            (f32::from_value(value) - range.start()) / (range.end() - range.start())
+        }
+        ParamKind::Complex { magnitude } => {
+            (Complex32::from_value(value).norm() - magnitude.start()) / (magnitude.end() - magnitude.start())
+        }
+        ParamKind::Int { range } => {
+            (i32::from_value(value) - range.start()) as f32 / (range.end() - range.start()).max(1) as f32
+        }
+        ParamKind::Enum { variants } => {
+            usize::from_value(value) as f32 / variants.len().saturating_sub(1).max(1) as f32
+        }
+        ParamKind::Bool => bool::from_value(value) as u8 as f32,
+    };
+    if unit.is_finite() {
+        unit.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// the inverse of `to_unit`; complexes keep `like`'s angle
+fn from_unit(descriptor: &ParamDescriptor, unit: f32, like: ParamValue) -> ParamValue {
+    let value = match &descriptor.kind {
+        ParamKind::Real { range } => ParamValue::Real(range.start() + unit * (range.end() - range.start())),
+        ParamKind::Complex { magnitude } => {
+            let r = magnitude.start() + unit * (magnitude.end() - magnitude.start());
+            ParamValue::Complex(Complex32::from_polar(r, Complex32::from_value(like).arg()))
+        }
+        ParamKind::Int { range } => {
+            ParamValue::Int(range.start() + (unit * (range.end() - range.start()) as f32).round() as i32)
+        }
+        ParamKind::Enum { variants } => {
+            ParamValue::Enum((unit * variants.len().saturating_sub(1) as f32).round() as usize)
+        }
+        ParamKind::Bool => ParamValue::Bool(unit >= 0.5),
+    };
+    descriptor.clamp(value)
+}
+
+/// The transport controls and every lane, with draggable breakpoints:
+/// drag to move, double-click to add, right-click to delete.
+pub fn timeline_ui(ui: &mut egui::Ui, graph: &mut Patch) {
+    let id = ui.id().with("timeline");
+    // seconds visible, and which breakpoint is being dragged
+    let (mut span, mut dragging) = ui
+        .data_mut(|d| d.get_temp::<(f64, Option<(usize, usize)>)>(id))
+        .unwrap_or((10.0, None));
+
+    ui.horizontal(|ui| {
+        let automation = graph.automation_mut();
+        if ui.button("|<").clicked() {
+            automation.set_position(0.0);
+        }
+        let play = if automation.playing() { "Stop" } else { "Play" };
+        if ui.button(play).clicked() {
+            let playing = !automation.playing();
+            automation.set_playing(playing);
+            if !playing {
+                automation.set_recording(false);
+            }
+        }
+        let record = egui::RichText::new("Rec").color(if automation.recording() {
+            ui.visuals().error_fg_color
+        } else {
+            ui.visuals().text_color()
+        });
+        if ui.button(record).clicked() {
+            let recording = !automation.recording();
+            automation.set_recording(recording);
+            if recording {
+                automation.set_playing(true);
+            }
+        }
+        ui.monospace(format!("{:8.2}s", automation.position()));
+        ui.add(egui::DragValue::new(&mut span).speed(0.1).clamp_range(1.0..=600.0).suffix("s visible"));
+    });
+
+    // what we need from the nodes, fetched before borrowing the lanes
+    let lane_info: Vec<_> = graph
+        .automation()
+        .lanes()
+        .iter()
+        .map(|lane| {
+            if !graph.contains_node(lane.node) {
+                return (lane.param.clone(), None);
+            }
+            let node = graph.get_node(lane.node);
+            let node_name = graph
+                .layout(lane.node)
+                .title
+                .clone()
+                .or_else(|| crate::registry::type_of(&**node).map(|ty| ty.name.to_owned()))
+                .unwrap_or_default();
+            let descriptor = node
+                .param_descriptors()
+                .iter()
+                .find(|d| d.name == lane.param)
+                .cloned();
+            (format!("{node_name}.{}", lane.param), descriptor)
+        })
+        .collect();
+
+    let automation = graph.automation_mut();
+    let position = automation.position();
+    // scroll along once the playhead goes past the end
+    let start = (position - span * 0.75).max(0.0);
+
+    let mut removed = None;
+    for (lane_idx, (lane, (name, descriptor))) in automation.lanes_mut().iter_mut().zip(&lane_info).enumerate() {
+        ui.horizontal(|ui| {
+            if ui.small_button("x").clicked() {
+                removed = Some(lane_idx);
+            }
+            ui.monospace(name);
+        });
+        let Some(descriptor) = descriptor else {
+            ui.label("(no such parameter)");
+            continue;
+        };
+
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width(), 48.0),
+            egui::Sense::click_and_drag(),
+        );
+        let to_screen = |p: &Breakpoint| {
+            egui::pos2(
+                rect.left() + ((p.time - start) / span) as f32 * rect.width(),
+                rect.bottom() - to_unit(descriptor, p.value) * rect.height(),
+            )
+        };
+        let from_screen = |pos: egui::Pos2| {
+            let time = start + ((pos.x - rect.left()) / rect.width()) as f64 * span;
+            let unit = (rect.bottom() - pos.y) / rect.height();
+            (time.max(0.0), unit.clamp(0.0, 1.0))
+        };
+        let near = |pos: egui::Pos2| {
+            lane.points
+                .iter()
+                .position(|p| to_screen(p).distance(pos) < 6.0)
+        };
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.drag_started() {
+                dragging = near(pos).map(|i| (lane_idx, i));
+            }
+            if response.double_clicked() {
+                let (time, unit) = from_screen(pos);
+                let like = lane.value_at(time).unwrap_or(ParamValue::Real(0.0));
+                lane.insert(Breakpoint {
+                    time,
+                    value: from_unit(descriptor, unit, like),
+                });
+            } else if response.secondary_clicked() {
+                if let Some(i) = near(pos) {
+                    lane.points.remove(i);
+                }
+            }
+        }
+        if let Some((_, i)) = dragging.filter(|&(l, i)| l == lane_idx && i < lane.points.len()) {
+            if let Some(pos) = response.interact_pointer_pos().filter(|_| response.dragged()) {
+                let (time, unit) = from_screen(pos);
+                // stay between the neighbours, so the order holds
+                let lo = i.checked_sub(1).map_or(0.0, |j| lane.points[j].time);
+                let hi = lane.points.get(i + 1).map_or(f64::INFINITY, |p| p.time);
+                let point = &mut lane.points[i];
+                point.time = time.clamp(lo, hi);
+                point.value = from_unit(descriptor, unit, point.value);
+            }
+        }
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let stroke = ui.visuals().widgets.inactive.fg_stroke;
+        let stepped = !matches!(
+            descriptor.kind,
+            ParamKind::Real { .. } | ParamKind::Complex { .. } | ParamKind::Int { .. }
+        );
+        let mut line = vec![];
+        for (i, point) in lane.points.iter().enumerate() {
+            let pos = to_screen(point);
+            if i == 0 {
+                line.push(egui::pos2(rect.left(), pos.y));
+            } else if stepped {
+                line.push(egui::pos2(pos.x, line.last().map_or(pos.y, |p: &egui::Pos2| p.y)));
+            }
+            line.push(pos);
+        }
+        if let Some(&last) = line.last() {
+            line.push(egui::pos2(rect.right(), last.y));
+        }
+        painter.add(egui::Shape::line(line, stroke));
+        for point in &lane.points {
+            painter.circle_filled(to_screen(point), 3.0, stroke.color);
+        }
+        let playhead_x = rect.left() + ((position - start) / span) as f32 * rect.width();
+        painter.vline(playhead_x, rect.y_range(), egui::Stroke::new(1.0, ui.visuals().error_fg_color));
+    }
+    if let Some(idx) = removed {
+        automation.remove_lane(idx);
+        dragging = None;
+    }
+    if automation.lanes().is_empty() {
+        ui.label("arm Rec and move some knobs to record automation");
+    }
+
+    if !ui.input(|i| i.pointer.any_down()) {
+        dragging = None;
+    }
+    ui.data_mut(|d| d.insert_temp(id, (span, dragging)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complex_lanes_keep_their_magnitude() {
+        let mut lane = Lane::new(NodeKey::default(), "m");
+        for (time, value) in [(0.0, Complex32::new(1.0, 0.0)), (1.0, Complex32::new(0.0, 1.0))] {
+            lane.insert(Breakpoint {
+                time,
+                value: ParamValue::Complex(value),
+            });
+        }
+        let Some(ParamValue::Complex(half)) = lane.value_at(0.5) else {
+            panic!("not complex");
+        };
+        assert!((half.norm() - 1.0).abs() < 1e-5, "{half}");
+        assert!((half.arg() - std::f32::consts::FRAC_PI_4).abs() < 1e-5, "{half}");
+    }
+}
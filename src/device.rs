@@ -0,0 +1,342 @@
+//! Picking the audio host, output device, sample rate and buffer size.
+//!
+//! The choice is saved to `audio.ron` whenever it's applied, and tried
+//! again on the next run. If it (or the default device) can't be opened,
+//! Quadio carries on without audio rather than giving up.
+
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{audio_main, AudioIO};
+use crate::patch::Patch;
+use crate::monitor::OutputMonitor;
+
+/// What to open; `None` means whatever the host's default is.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub host: Option<String>,
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// the device's, in frames
+    pub buffer_size: Option<u32>,
+
+    /// how many samples the graph is run for at a time
+    pub block_size: usize,
+    /// how many rendered blocks can be waiting to be played
+    pub queue_depth: usize,
+}
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            host: None,
+            device: None,
+            sample_rate: None,
+            buffer_size: None,
+            block_size: 1024,
+            queue_depth: 2,
+        }
+    }
+}
+impl AudioSettings {
+    fn path() -> PathBuf {
+        PathBuf::from("audio.ron")
+    }
+
+    /// The saved settings, or the defaults if there aren't any (or they're
+    /// unreadable).
+    pub fn load() -> AudioSettings {
+        let path = AudioSettings::path();
+        let Ok(s) = std::fs::read_to_string(&path) else {
+            return AudioSettings::default();
+        };
+        let settings: AudioSettings = ron::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("ignoring {}: {e}", path.display());
+            AudioSettings::default()
+        });
+        // a hand-edited file can say anything, and the engine can't run with
+        // no blocks or zero-sized ones
+        AudioSettings {
+            block_size: settings.block_size.clamp(*BLOCK_SIZES.start(), *BLOCK_SIZES.end()),
+            queue_depth: settings.queue_depth.clamp(*QUEUE_DEPTHS.start(), *QUEUE_DEPTHS.end()),
+            ..settings
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = AudioSettings::path();
+        let s = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(&path, s).with_context(|| format!("couldn't write {}", path.display()))
+    }
+}
+
+/// What `block_size` and `queue_depth` can be set to (the block size
+/// picker offers the powers of two in there).
+const BLOCK_SIZES: std::ops::RangeInclusive<usize> = 32..=8192;
+const QUEUE_DEPTHS: std::ops::RangeInclusive<usize> = 1..=16;
+
+const COMMON_SAMPLE_RATES: [u32; 8] = [22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000];
+
+/// An output device, and what it can do.
+struct DeviceInfo {
+    name: String,
+    sample_rates: Vec<u32>,
+    buffer_sizes: Vec<u32>,
+}
+
+/// The output devices of one host, or why they couldn't be listed.
+struct DeviceList {
+    host: Option<String>,
+    devices: Result<Vec<DeviceInfo>, String>,
+}
+
+fn list_devices(host_name: Option<&str>) -> anyhow::Result<Vec<DeviceInfo>> {
+    let host = match host_name {
+        Some(name) => {
+            let id = cpal::available_hosts()
+                .into_iter()
+                .find(|id| id.name() == name)
+                .with_context(|| format!("no audio host called {name}"))?;
+            cpal::host_from_id(id)?
+        }
+        None => cpal::default_host(),
+    };
+
+    let mut devices = vec![];
+    for device in host.output_devices()? {
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let ranges: Vec<_> = device.supported_output_configs().map(Iterator::collect).unwrap_or_default();
+        let sample_rates = COMMON_SAMPLE_RATES
+            .into_iter()
+            .filter(|&rate| {
+                ranges.iter().any(|c| c.min_sample_rate().0 <= rate && rate <= c.max_sample_rate().0)
+            })
+            .collect();
+        let buffer_sizes = (5..=13)
+            .map(|bits| 1 << bits)
+            .filter(|&frames| {
+                ranges.iter().any(|c| match c.buffer_size() {
+                    cpal::SupportedBufferSize::Range { min, max } => *min <= frames && frames <= *max,
+                    cpal::SupportedBufferSize::Unknown => false,
+                })
+            })
+            .collect();
+        devices.push(DeviceInfo {
+            name,
+            sample_rates,
+            buffer_sizes,
+        });
+    }
+    Ok(devices)
+}
+
+fn combo<T: Clone + PartialEq>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut Option<T>,
+    options: impl IntoIterator<Item = T>,
+    show: impl Fn(&T) -> String,
+) {
+    let selected = value.as_ref().map_or_else(|| "default".to_owned(), &show);
+    egui::ComboBox::from_label(label).selected_text(selected).show_ui(ui, |ui| {
+        ui.selectable_value(value, None, "default");
+        for option in options {
+            let text = show(&option);
+            ui.selectable_value(value, Some(option), text);
+        }
+    });
+}
+
+/// The running stream (if any), and the settings panel that replaces it.
+pub struct AudioPanel {
+    graph: Arc<Mutex<Patch>>,
+    monitor: Arc<OutputMonitor>,
+
+    audio: Option<AudioIO>,
+    // what `audio` was opened with
+    applied: AudioSettings,
+    // what's being picked in the panel
+    editing: AudioSettings,
+    // why there's no audio, if there isn't
+    error: Option<String>,
+
+    // enumerating devices can be slow, so it's only done when asked
+    devices: Option<DeviceList>,
+}
+impl AudioPanel {
+    /// Starts audio with the saved settings, falling back to the defaults,
+    /// and then to no audio at all.
+    pub fn start(graph: Arc<Mutex<Patch>>, monitor: Arc<OutputMonitor>) -> AudioPanel {
+        let saved = AudioSettings::load();
+        let mut panel = AudioPanel {
+            graph,
+            monitor,
+            audio: None,
+            applied: saved.clone(),
+            editing: saved.clone(),
+            error: None,
+            devices: None,
+        };
+        panel.apply(saved.clone());
+        let fallback = AudioSettings {
+            block_size: saved.block_size,
+            queue_depth: saved.queue_depth,
+            ..Default::default()
+        };
+        if panel.audio.is_none() && saved != fallback {
+            eprintln!("audio: {}; trying the default device", panel.error.as_deref().unwrap_or("?"));
+            panel.apply(fallback);
+        }
+        if let Some(e) = &panel.error {
+            eprintln!("audio: {e}; carrying on without");
+        }
+        panel
+    }
+
+    /// Stops whatever's playing, and starts again with `settings`. The graph
+    /// carries on as it was.
+    fn apply(&mut self, settings: AudioSettings) {
+        // one device at a time
+        self.audio = None;
+        match audio_main(self.graph.clone(), self.monitor.clone(), &settings) {
+            Ok(audio) => {
+                self.audio = Some(audio);
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("{e:#}")),
+        }
+        self.editing = settings.clone();
+        self.applied = settings;
+    }
+
+    /// e.g. "2ch 48000Hz, 45.3ms", for the top of the side panel.
+    pub fn summary(&self) -> String {
+        match &self.audio {
+            Some(audio) => format!(
+                "{}ch {}Hz, {:.1}ms",
+                audio.channels,
+                audio.sample_rate,
+                audio.latency().as_secs_f64() * 1000.0
+            ),
+            None => "no audio".to_owned(),
+        }
+    }
+
+    /// How many times the device ran out of audio, since the stream started
+    /// (or the count was reset).
+    pub fn underruns(&self) -> u64 {
+        self.audio.as_ref().map_or(0, |audio| audio.stats.underruns.load(Ordering::Relaxed))
+    }
+
+    /// Latency and underruns of the running stream.
+    fn stream_ui(ui: &mut egui::Ui, audio: &AudioIO) {
+        let queued = (audio.block_size * audio.queue_depth) as f64 / audio.sample_rate as f64;
+        ui.monospace(format!(
+            "latency {:.1}ms ({:.1}ms queued, {:.1}ms device)",
+            audio.latency().as_secs_f64() * 1000.0,
+            queued * 1000.0,
+            audio.stats.device_latency().as_secs_f64() * 1000.0,
+        ));
+        ui.horizontal(|ui| {
+            let underruns = audio.stats.underruns.load(Ordering::Relaxed);
+            let text = format!("{underruns} underruns");
+            if underruns == 0 {
+                ui.monospace(text);
+            } else {
+                ui.colored_label(ui.visuals().error_fg_color, text);
+                if ui.small_button("reset").clicked() {
+                    audio.stats.underruns.store(0, Ordering::Relaxed);
+                }
+            }
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        match (&self.audio, &self.error) {
+            (Some(audio), _) => {
+                ui.label(&audio.device);
+                AudioPanel::stream_ui(ui, audio);
+            }
+            (None, Some(e)) => {
+                ui.colored_label(ui.visuals().error_fg_color, format!("no audio: {e}"));
+            }
+            (None, None) => {
+                ui.weak("no audio");
+            }
+        }
+
+        let hosts: Vec<String> = cpal::available_hosts().iter().map(|id| id.name().to_owned()).collect();
+        let host = self.editing.host.clone();
+        combo(ui, "host", &mut self.editing.host, hosts, String::clone);
+        if self.editing.host != host {
+            // another host's devices are a different bunch
+            self.editing.device = None;
+            self.editing.sample_rate = None;
+            self.editing.buffer_size = None;
+        }
+
+        let stale = self.devices.as_ref().is_none_or(|list| list.host != self.editing.host);
+        let refresh = ui.small_button("refresh devices").clicked();
+        if stale || refresh {
+            self.devices = Some(DeviceList {
+                host: self.editing.host.clone(),
+                devices: list_devices(self.editing.host.as_deref()).map_err(|e| format!("{e:#}")),
+            });
+        }
+        let Some(list) = &self.devices else {
+            return;
+        };
+        let devices = match &list.devices {
+            Ok(devices) => devices.as_slice(),
+            Err(e) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+                &[]
+            }
+        };
+
+        combo(ui, "device", &mut self.editing.device, devices.iter().map(|d| d.name.clone()), String::clone);
+        // the default device's abilities aren't known without opening it, so offer the usual suspects
+        let device = self.editing.device.as_ref().and_then(|name| devices.iter().find(|d| &d.name == name));
+        let sample_rates = device.map_or(COMMON_SAMPLE_RATES.to_vec(), |d| d.sample_rates.clone());
+        let buffer_sizes = device.map_or_else(|| (5..=13).map(|bits| 1 << bits).collect(), |d| d.buffer_sizes.clone());
+        combo(ui, "sample rate", &mut self.editing.sample_rate, sample_rates, |rate| format!("{rate}Hz"));
+        combo(ui, "buffer", &mut self.editing.buffer_size, buffer_sizes, |frames| format!("{frames} frames"));
+
+        // these are ours rather than the device's, so they're always up for grabs
+        egui::ComboBox::from_label("block")
+            .selected_text(format!("{} samples", self.editing.block_size))
+            .show_ui(ui, |ui| {
+                for block_size in (5..=13).map(|bits| 1 << bits) {
+                    ui.selectable_value(&mut self.editing.block_size, block_size, format!("{block_size} samples"));
+                }
+            });
+        ui.add(
+            egui::DragValue::new(&mut self.editing.queue_depth)
+                .clamp_range(QUEUE_DEPTHS)
+                .prefix("queue ")
+                .suffix(" blocks"),
+        );
+
+        ui.horizontal(|ui| {
+            let changed = self.editing != self.applied;
+            if ui.add_enabled(changed || self.audio.is_none(), egui::Button::new("Apply")).clicked() {
+                self.apply(self.editing.clone());
+                if self.audio.is_some() {
+                    if let Err(e) = self.applied.save() {
+                        eprintln!("audio: {e:#}");
+                    }
+                }
+            }
+            if changed && ui.button("Revert").clicked() {
+                self.editing = self.applied.clone();
+            }
+        });
+    }
+}
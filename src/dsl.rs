@@ -0,0 +1,1074 @@
+//! A little text language for describing patches, for when clicking things
+//! together gets old:
+//!
+//! ```text
+//! # comments start with a hash
+//! osc = phasor(f_mul=2)            # a named node, with a parameter
+//! mix = sum(osc, inputs=3)         # unnamed arguments feed the inputs in order
+//! mix.B <- phasor(f_mul=3)         # or any input, by label or index
+//! mix.C = 0.25                     # unconnected inputs can be given values
+//! out <- linear(mix, m=0.5+0.5i)   # feed a node's first input
+//! lfo = phasor(f_div=100)
+//! osc.~f_mul <- lfo                # wiring to ~param exposes the parameter
+//! ```
+//!
+//! Node types go by their registry ids (`phase_scale`, `re_im_split`, ...).
+//! Inside a call, unnamed arguments
+//! feed the inputs in order, and named ones set parameters (or inputs, by
+//! label); passing a node to a parameter exposes it as an input. Nodes that
+//! aren't given a name get one like `linear_1`. `out` is an output node,
+//! made for you if you use it without defining it.
+//!
+//! Applying a program to a running graph only changes what differs, so the
+//! nodes that survive keep their state (oscillator phases and so on). Nodes
+//! the program stops mentioning are only removed if an earlier program made
+//! them; replacing the graph with a program removes everything else too.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use num_complex::Complex32;
+
+use crate::graph::{NodeKey, NodeLayout, SocketDescriptor};
+use crate::node::{node_name, QuadioNode};
+use crate::param::{ParamDescriptor, ParamKind, ParamValue};
+use crate::patch::Patch;
+use crate::registry::NodeType;
+use crate::sample::QuadioSample;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DslError {
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for DslError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for DslError {}
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T, DslError> {
+    Err(DslError {
+        line,
+        message: message.into(),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f32),
+    Imaginary(f32),
+    Eq,
+    Arrow,
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+    Plus,
+    Minus,
+    /// `;` or a newline
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "{name:?}"),
+            Token::Number(x) => write!(f, "{x}"),
+            Token::Imaginary(x) => write!(f, "{x}i"),
+            Token::Eq => write!(f, "`=`"),
+            Token::Arrow => write!(f, "`<-`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Dot => write!(f, "`.`"),
+            Token::Plus => write!(f, "`+`"),
+            Token::Minus => write!(f, "`-`"),
+            Token::End => write!(f, "the end of the statement"),
+        }
+    }
+}
+
+fn describe(token: Option<Token>) -> String {
+    token.map_or_else(|| "the end".to_owned(), |t| t.to_string())
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '~'
+}
+
+fn lex(source: &str) -> Result<Vec<(Token, usize)>, DslError> {
+    let mut tokens = vec![];
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let line = line.split('#').next().unwrap();
+        let mut chars = line.char_indices().peekable();
+
+        while let Some((start, c)) = chars.next() {
+            let token = match c {
+                c if c.is_whitespace() => continue,
+                ';' => Token::End,
+                '=' => Token::Eq,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                '.' => Token::Dot,
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                '<' if chars.peek().map(|&(_, c)| c) == Some('-') => {
+                    chars.next();
+                    Token::Arrow
+                }
+                c if c.is_ascii_digit() => {
+                    let mut end = start + c.len_utf8();
+                    let mut prev = c;
+                    while let Some(&(i, c)) = chars.peek() {
+                        let exponent_sign = (c == '-' || c == '+') && (prev == 'e' || prev == 'E');
+                        if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign) {
+                            break;
+                        }
+                        end = i + c.len_utf8();
+                        prev = c;
+                        chars.next();
+                    }
+                    let Ok(x) = line[start..end].parse::<f32>() else {
+                        return error(line_no, format!("bad number {:?}", &line[start..end]));
+                    };
+                    if chars.peek().map(|&(_, c)| c) == Some('i') {
+                        chars.next();
+                        Token::Imaginary(x)
+                    } else {
+                        Token::Number(x)
+                    }
+                }
+                c if is_ident_char(c) => {
+                    let mut end = start + c.len_utf8();
+                    while let Some(&(i, c)) = chars.peek().filter(|&&(_, c)| is_ident_char(c)) {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    }
+                    Token::Ident(line[start..end].to_owned())
+                }
+                other => return error(line_no, format!("unexpected {other:?}")),
+            };
+            tokens.push((token, line_no));
+        }
+        tokens.push((Token::End, line_no));
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Socket {
+    Index(usize),
+    Label(String),
+}
+impl Socket {
+    fn resolve(&self, sockets: &[SocketDescriptor]) -> Option<usize> {
+        match self {
+            Socket::Index(idx) => Some(*idx).filter(|&idx| idx < sockets.len()),
+            Socket::Label(label) => sockets.iter().position(|s| &s.label == label),
+        }
+    }
+}
+impl fmt::Display for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Socket::Index(idx) => write!(f, "#{idx}"),
+            Socket::Label(label) => write!(f, "{label}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Call { ty: String, args: Vec<Arg>, line: usize },
+    Ref { node: String, socket: Option<Socket>, line: usize },
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(Complex32),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug)]
+enum Arg {
+    Positional(Expr),
+    Named(String, Value),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    /// `name = type(...)`
+    Bind { name: String, expr: Expr, line: usize },
+    /// `node[.socket] <- expr`
+    Feed { node: String, socket: Option<Socket>, expr: Expr, line: usize },
+    /// `node.field = value`
+    Set { node: String, field: String, value: Value, line: usize },
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |&(_, line)| line)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        token
+    }
+    fn expect(&mut self, expected: Token) -> Result<(), DslError> {
+        let line = self.line();
+        match self.next() {
+            Some(t) if t == expected => Ok(()),
+            other => error(line, format!("expected {expected}, found {}", describe(other))),
+        }
+    }
+    fn ident(&mut self) -> Result<String, DslError> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            other => error(line, format!("expected a name, found {}", describe(other))),
+        }
+    }
+    fn socket(&mut self) -> Result<Option<Socket>, DslError> {
+        if self.peek() != Some(&Token::Dot) {
+            return Ok(None);
+        }
+        self.next();
+        let line = self.line();
+        match self.next() {
+            Some(Token::Ident(label)) => Ok(Some(Socket::Label(label))),
+            Some(Token::Number(idx)) if idx.fract() == 0.0 && idx >= 0.0 => Ok(Some(Socket::Index(idx as usize))),
+            other => error(line, format!("expected a socket label or index, found {}", describe(other))),
+        }
+    }
+
+    fn program(&mut self) -> Result<Vec<Stmt>, DslError> {
+        let mut stmts = vec![];
+        while let Some(token) = self.peek() {
+            if *token == Token::End {
+                self.next();
+                continue;
+            }
+            stmts.push(self.stmt()?);
+            let line = self.line();
+            match self.next() {
+                None | Some(Token::End) => (),
+                Some(other) => return error(line, format!("expected the end of the statement, found {}", describe(Some(other)))),
+            }
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, DslError> {
+        let line = self.line();
+        let node = self.ident()?;
+        let socket = self.socket()?;
+        match (self.next(), socket) {
+            (Some(Token::Arrow), socket) => Ok(Stmt::Feed {
+                node,
+                socket,
+                expr: self.expr()?,
+                line,
+            }),
+            (Some(Token::Eq), None) => Ok(Stmt::Bind {
+                name: node,
+                expr: self.expr()?,
+                line,
+            }),
+            (Some(Token::Eq), Some(Socket::Label(field))) => Ok(Stmt::Set {
+                node,
+                field,
+                value: self.value()?,
+                line,
+            }),
+            (Some(Token::Eq), Some(Socket::Index(_))) => error(line, "inputs are set by label"),
+            (other, _) => error(line, format!("expected `=` or `<-`, found {}", describe(other))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, DslError> {
+        let line = self.line();
+        let name = self.ident()?;
+        if self.peek() != Some(&Token::LParen) {
+            return Ok(Expr::Ref {
+                node: name,
+                socket: self.socket()?,
+                line,
+            });
+        }
+
+        self.next();
+        let mut args = vec![];
+        while self.peek() != Some(&Token::RParen) {
+            let named = matches!(self.peek(), Some(Token::Ident(_)))
+                && self.tokens.get(self.pos + 1).map(|(t, _)| t) == Some(&Token::Eq);
+            if named {
+                let name = self.ident()?;
+                self.expect(Token::Eq)?;
+                args.push(Arg::Named(name, self.value()?));
+            } else {
+                args.push(Arg::Positional(self.expr()?));
+            }
+            if self.peek() != Some(&Token::RParen) {
+                self.expect(Token::Comma)?;
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(Expr::Call { ty: name, args, line })
+    }
+
+    /// a complex literal like `-0.5`, `2i` or `1-0.5i`, or an expression
+    fn value(&mut self) -> Result<Value, DslError> {
+        if matches!(self.peek(), Some(Token::Ident(_))) {
+            return Ok(Value::Expr(self.expr()?));
+        }
+
+        let mut total = Complex32::new(0.0, 0.0);
+        let mut first = true;
+        loop {
+            let line = self.line();
+            let sign = match self.peek() {
+                Some(Token::Minus) => {
+                    self.next();
+                    -1.0
+                }
+                Some(Token::Plus) => {
+                    self.next();
+                    1.0
+                }
+                _ if first => 1.0,
+                _ => break,
+            };
+            match self.next() {
+                Some(Token::Number(x)) => total.re += sign * x,
+                Some(Token::Imaginary(x)) => total.im += sign * x,
+                other => return error(line, format!("expected a number, found {}", describe(other))),
+            }
+            first = false;
+        }
+        Ok(Value::Number(total))
+    }
+}
+
+/// One node of a `Plan`: everything about it the program says, plus
+/// defaults for everything it doesn't.
+struct PlannedNode {
+    name: String,
+    ty: NodeType,
+    params: Vec<ParamValue>,
+    exposed: Vec<String>,
+    input_values: Vec<(Socket, QuadioSample, usize)>,
+}
+
+struct PlannedWire {
+    src: (String, Socket),
+    dst: (String, Socket),
+    line: usize,
+}
+
+/// A parsed and checked program, ready to `apply`. Sockets are only
+/// resolved then, since they can depend on parameters.
+pub struct Plan {
+    nodes: Vec<PlannedNode>,
+    wires: Vec<PlannedWire>,
+}
+
+fn literal_param(descriptor: &ParamDescriptor, value: Complex32) -> ParamValue {
+    let value = match descriptor.kind {
+        ParamKind::Real { .. } => ParamValue::Real(value.re),
+        ParamKind::Complex { .. } => ParamValue::Complex(value),
+        ParamKind::Int { .. } => ParamValue::Int(value.re.round() as i32),
+        ParamKind::Enum { .. } => ParamValue::Enum(value.re.max(0.0).round() as usize),
+        ParamKind::Bool => ParamValue::Bool(value.re != 0.0),
+    };
+    descriptor.clamp(value)
+}
+
+/// an enum variant or `true`/`false`, written as a bare name
+fn named_param(descriptor: &ParamDescriptor, name: &str) -> Option<ParamValue> {
+    match &descriptor.kind {
+        ParamKind::Enum { variants } => variants
+            .iter()
+            .position(|v| v.eq_ignore_ascii_case(name))
+            .map(ParamValue::Enum),
+        ParamKind::Bool => match name {
+            "true" => Some(ParamValue::Bool(true)),
+            "false" => Some(ParamValue::Bool(false)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+struct Planner {
+    nodes: Vec<PlannedNode>,
+    wires: Vec<PlannedWire>,
+    bound: HashSet<String>,
+    anonymous_counts: HashMap<String, usize>,
+    uses_out: bool,
+}
+impl Planner {
+    fn node_mut(&mut self, name: &str) -> Option<&mut PlannedNode> {
+        self.nodes.iter_mut().find(|n| n.name == name)
+    }
+
+    fn is_node(&self, name: &str) -> bool {
+        self.bound.contains(name) || name == "out"
+    }
+
+    fn check_ref(&mut self, name: &str, line: usize) -> Result<(), DslError> {
+        if !self.is_node(name) {
+            return error(line, format!("no node named {name:?}"));
+        }
+        self.uses_out |= name == "out";
+        Ok(())
+    }
+
+    fn fresh_name(&mut self, ty: &str) -> String {
+        let count = self.anonymous_counts.entry(ty.to_owned()).or_insert(0);
+        loop {
+            *count += 1;
+            let name = format!("{ty}_{count}");
+            if !self.bound.contains(&name) {
+                return name;
+            }
+        }
+    }
+
+    /// returns the output the expression stands for
+    fn expr(&mut self, expr: &Expr) -> Result<(String, Socket), DslError> {
+        match expr {
+            Expr::Call { ty, args, line } => {
+                let name = self.fresh_name(ty);
+                self.call(name.clone(), ty, args, *line)?;
+                Ok((name, Socket::Index(0)))
+            }
+            Expr::Ref { node, socket, line } => {
+                self.check_ref(node, *line)?;
+                Ok((node.clone(), socket.clone().unwrap_or(Socket::Index(0))))
+            }
+        }
+    }
+
+    fn call(&mut self, name: String, ty: &str, args: &[Arg], line: usize) -> Result<(), DslError> {
+        let Some(node_type) = crate::registry::registry().types().find(|t| t.id == ty).copied() else {
+            return error(line, format!("no node type {ty:?}"));
+        };
+        let prototype = node_type.make();
+        let params = (0..prototype.param_descriptors().len()).map(|idx| prototype.param(idx)).collect();
+        self.nodes.push(PlannedNode {
+            name: name.clone(),
+            ty: node_type,
+            params,
+            exposed: vec![],
+            input_values: vec![],
+        });
+
+        let mut positional = 0;
+        for arg in args {
+            match arg {
+                Arg::Positional(expr) => {
+                    let src = self.expr(expr)?;
+                    self.wires.push(PlannedWire {
+                        src,
+                        dst: (name.clone(), Socket::Index(positional)),
+                        line,
+                    });
+                    positional += 1;
+                }
+                Arg::Named(field, value) => self.set(&name, &*prototype, field, value, line)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// `field = value`, either in a call or as its own statement
+    fn set(
+        &mut self,
+        name: &str,
+        prototype: &dyn QuadioNode,
+        field: &str,
+        value: &Value,
+        line: usize,
+    ) -> Result<(), DslError> {
+        let descriptors = prototype.param_descriptors();
+        if let Some(idx) = descriptors.iter().position(|d| d.name == field) {
+            let descriptor = &descriptors[idx];
+            let param_value = match value {
+                Value::Number(x) => Some(literal_param(descriptor, *x)),
+                Value::Expr(Expr::Ref { node, socket: None, .. }) if !self.is_node(node) => {
+                    match named_param(descriptor, node) {
+                        Some(v) => Some(v),
+                        None => return error(line, format!("{node:?} isn't a node or a value of {field}")),
+                    }
+                }
+                Value::Expr(_) => None,
+            };
+            match (param_value, value) {
+                (Some(v), _) => self.node_mut(name).unwrap().params[idx] = v,
+                (None, Value::Expr(expr)) => {
+                    // a signal: the parameter gets exposed and wired up
+                    let src = self.expr(expr)?;
+                    let node = self.node_mut(name).unwrap();
+                    if !node.exposed.iter().any(|p| p == field) {
+                        node.exposed.push(field.to_owned());
+                    }
+                    self.wires.push(PlannedWire {
+                        src,
+                        dst: (name.to_owned(), Socket::Label(format!("~{field}"))),
+                        line,
+                    });
+                }
+                (None, Value::Number(_)) => unreachable!(),
+            }
+            return Ok(());
+        }
+
+        // otherwise it's an input, which can only be checked once the
+        // parameters are in (they can change the sockets)
+        match value {
+            Value::Number(x) => {
+                self.node_mut(name)
+                    .unwrap()
+                    .input_values
+                    .push((Socket::Label(field.to_owned()), *x, line));
+            }
+            Value::Expr(expr) => {
+                let src = self.expr(expr)?;
+                self.wires.push(PlannedWire {
+                    src,
+                    dst: (name.to_owned(), Socket::Label(field.to_owned())),
+                    line,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn prototype(&self, name: &str) -> Box<dyn QuadioNode> {
+        let node = self.nodes.iter().find(|n| n.name == name).unwrap();
+        node.ty.make()
+    }
+
+    fn ensure_out(&mut self) {
+        if self.uses_out && !self.nodes.iter().any(|n| n.name == "out") {
+            self.bound.insert("out".to_owned());
+            self.call("out".to_owned(), "output", &[], 0).unwrap();
+        }
+    }
+}
+
+/// Parses and checks `source`.
+pub fn parse(source: &str) -> Result<Plan, DslError> {
+    let stmts = Parser {
+        tokens: lex(source)?,
+        pos: 0,
+    }
+    .program()?;
+
+    let mut planner = Planner {
+        nodes: vec![],
+        wires: vec![],
+        bound: HashSet::new(),
+        anonymous_counts: HashMap::new(),
+        uses_out: false,
+    };
+    for stmt in &stmts {
+        if let Stmt::Bind { name, line, .. } = stmt {
+            if !planner.bound.insert(name.clone()) {
+                return error(*line, format!("{name} is defined twice"));
+            }
+        }
+    }
+
+    // bindings first, so everything else can refer to any of them
+    for stmt in &stmts {
+        if let Stmt::Bind { name, expr, line } = stmt {
+            let Expr::Call { ty, args, .. } = expr else {
+                return error(*line, format!("{name} should be a node, like `{name} = linear()`"));
+            };
+            planner.call(name.clone(), ty, args, *line)?;
+        }
+    }
+    planner.ensure_out();
+
+    for stmt in &stmts {
+        match stmt {
+            Stmt::Bind { .. } => (),
+            Stmt::Feed { node, socket, expr, line } => {
+                planner.check_ref(node, *line)?;
+                planner.ensure_out();
+                let socket = socket.clone().unwrap_or(Socket::Index(0));
+                if let Socket::Label(label) = &socket {
+                    if let Some(param) = label.strip_prefix('~') {
+                        let prototype = planner.prototype(node);
+                        if prototype.param_index(param).is_none() {
+                            return error(*line, format!("{node} has no parameter {param:?}"));
+                        }
+                        let planned = planner.node_mut(node).unwrap();
+                        if !planned.exposed.iter().any(|p| p == param) {
+                            planned.exposed.push(param.to_owned());
+                        }
+                    }
+                }
+                let src = planner.expr(expr)?;
+                planner.wires.push(PlannedWire {
+                    src,
+                    dst: (node.clone(), socket),
+                    line: *line,
+                });
+            }
+            Stmt::Set { node, field, value, line } => {
+                planner.check_ref(node, *line)?;
+                planner.ensure_out();
+                let prototype = planner.prototype(node);
+                planner.set(node, &*prototype, field, value, *line)?;
+            }
+        }
+    }
+    planner.ensure_out();
+
+    Ok(Plan {
+        nodes: planner.nodes,
+        wires: planner.wires,
+    })
+}
+
+/// Makes `graph` match `plan`, touching only what differs: nodes with the
+/// same name and type are kept (along with their state), and the rest are
+/// added. Nodes the plan doesn't name are left alone, unless they're in
+/// `created`, which is where the nodes this adds get recorded. Returns
+/// whatever couldn't be done (bad sockets, refused connections); everything
+/// else is applied regardless.
+pub fn apply(graph: &mut Patch, plan: &Plan, created: &mut HashSet<NodeKey>) -> Vec<DslError> {
+    apply_removing(graph, plan, created, false)
+}
+
+/// Like `apply`, but removes every node the plan doesn't name, whoever made
+/// it.
+pub fn replace(graph: &mut Patch, plan: &Plan, created: &mut HashSet<NodeKey>) -> Vec<DslError> {
+    apply_removing(graph, plan, created, true)
+}
+
+fn apply_removing(graph: &mut Patch, plan: &Plan, created: &mut HashSet<NodeKey>, remove_all: bool) -> Vec<DslError> {
+    let mut problems = vec![];
+    created.retain(|&key| graph.contains_node(key));
+
+    let existing: HashMap<String, NodeKey> = graph.nodes().map(|(key, _)| (node_name(graph, key), key)).collect();
+    let mut keys: HashMap<&str, NodeKey> = HashMap::new();
+    for (i, planned) in plan.nodes.iter().enumerate() {
+        let reusable = existing
+            .get(&planned.name)
+            .copied()
+            .filter(|&key| crate::registry::type_of(&**graph.get_node(key)).is_some_and(|ty| ty.id == planned.ty.id));
+        let key = match reusable {
+            Some(key) => key,
+            None => {
+                if let Some(&old) = existing.get(&planned.name) {
+                    graph.remove_node(old);
+                    created.remove(&old);
+                }
+                // somewhere out of the way; the user can tidy up
+                let pos = [20.0 + 180.0 * (i % 5) as f32, 40.0 + 160.0 * (i / 5) as f32];
+                let layout = NodeLayout {
+                    title: Some(planned.name.clone()),
+                    ..NodeLayout::at(pos)
+                };
+                let key = graph.add_node_with_layout(planned.ty.make(), layout);
+                created.insert(key);
+                key
+            }
+        };
+        keys.insert(&planned.name, key);
+    }
+    let keep: HashSet<NodeKey> = keys.values().copied().collect();
+    let unwanted: Vec<NodeKey> = graph
+        .nodes()
+        .map(|(key, _)| key)
+        .filter(|key| !keep.contains(key) && (remove_all || created.contains(key)))
+        .collect();
+    for key in unwanted {
+        graph.remove_node(key);
+        created.remove(&key);
+    }
+
+    for planned in &plan.nodes {
+        let key = keys[planned.name.as_str()];
+        let node = graph.get_node_mut(key);
+        for (idx, &value) in planned.params.iter().enumerate() {
+            if node.param(idx) != value {
+                crate::param::set_param_clamped(&mut **node, idx, value);
+            }
+        }
+
+        let exposed: Vec<String> = graph.modulations(key).iter().map(|m| m.param.clone()).collect();
+        for param in exposed.iter().filter(|p| !planned.exposed.contains(p)) {
+            graph.unexpose_param(key, param);
+        }
+        for param in planned.exposed.iter().filter(|p| !exposed.contains(p)) {
+            graph.expose_param(key, param);
+        }
+    }
+    graph.refresh_descriptors();
+
+    for planned in &plan.nodes {
+        let key = keys[planned.name.as_str()];
+        let num_inputs = graph.node_descriptor(key).input_sockets.len();
+        let mut values: Vec<QuadioSample> = graph
+            .node_descriptor(key)
+            .input_sockets
+            .iter()
+            .map(|s| s.default)
+            .collect();
+        for (socket, value, line) in &planned.input_values {
+            match socket.resolve(&graph.node_descriptor(key).input_sockets) {
+                Some(idx) => values[idx] = *value,
+                None => problems.push(DslError {
+                    line: *line,
+                    message: format!("{} has no input {socket}", planned.name),
+                }),
+            }
+        }
+        for (idx, value) in values.into_iter().enumerate().take(num_inputs) {
+            graph.set_input_value(key, idx, value);
+        }
+    }
+
+    let mut wanted = HashMap::new();
+    for wire in &plan.wires {
+        let src_key = keys[wire.src.0.as_str()];
+        let dst_key = keys[wire.dst.0.as_str()];
+        let src_idx = wire.src.1.resolve(&graph.node_descriptor(src_key).output_sockets);
+        let dst_idx = wire.dst.1.resolve(&graph.node_descriptor(dst_key).input_sockets);
+        match (src_idx, dst_idx) {
+            (Some(src_idx), Some(dst_idx)) => {
+                wanted.insert((dst_key, dst_idx), ((src_key, src_idx), wire.line));
+            }
+            (None, _) => problems.push(DslError {
+                line: wire.line,
+                message: format!("{} has no output {}", wire.src.0, wire.src.1),
+            }),
+            (_, None) => problems.push(DslError {
+                line: wire.line,
+                message: format!("{} has no input {}", wire.dst.0, wire.dst.1),
+            }),
+        }
+    }
+    let stale: Vec<_> = graph
+        .wires()
+        .filter(|(dst, src)| wanted.get(dst).map(|(s, _)| s) != Some(src))
+        .map(|(dst, _)| dst)
+        .collect();
+    for (node, idx) in stale {
+        graph.disconnect(node, crate::graph::SocketDirection::Input, idx);
+    }
+    for (&dst, &(src, line)) in &wanted {
+        if graph.src_for_dest(dst.0, dst.1) == Some(src) {
+            continue;
+        }
+        if let Err(e) = graph.connect(src, dst) {
+            problems.push(DslError {
+                line,
+                message: e.to_string(),
+            });
+        }
+    }
+
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+fn format_value(descriptor: &ParamDescriptor, value: ParamValue) -> String {
+    match (value, &descriptor.kind) {
+        (ParamValue::Enum(i), ParamKind::Enum { variants }) => match variants.get(i) {
+            Some(v) if v.chars().all(is_ident_char) => v.to_lowercase(),
+            _ => i.to_string(),
+        },
+        (ParamValue::Real(x), _) => x.to_string(),
+        (ParamValue::Complex(c), _) => format!("{}{:+}i", c.re, c.im),
+        (ParamValue::Int(i), _) => i.to_string(),
+        (ParamValue::Enum(i), _) => i.to_string(),
+        (ParamValue::Bool(b), _) => b.to_string(),
+    }
+}
+
+fn format_socket(node: &str, sockets: &[SocketDescriptor], idx: usize) -> String {
+    match sockets.get(idx) {
+        _ if idx == 0 => node.to_owned(),
+        Some(s) if s.label.chars().all(is_ident_char) && !s.label.starts_with(|c: char| c.is_ascii_digit()) => {
+            format!("{node}.{}", s.label)
+        }
+        _ => format!("{node}.{idx}"),
+    }
+}
+
+/// Writes `graph` out as a program that `parse`s back into it (give or take
+/// layout, and exposed parameters that aren't wired to anything).
+pub fn export(graph: &Patch) -> String {
+    let mut out = String::new();
+
+    for (key, node) in graph.nodes() {
+        let Some(ty) = crate::registry::type_of(&**node) else {
+            continue;
+        };
+        let prototype = ty.make();
+        let params: Vec<String> = node
+            .param_descriptors()
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| prototype.param(idx) != node.param(idx))
+            .map(|(idx, descriptor)| format!("{}={}", descriptor.name, format_value(descriptor, node.param(idx))))
+            .collect();
+        out += &format!("{} = {}({})\n", node_name(graph, key), ty.id, params.join(", "));
+    }
+
+    let mut wires: Vec<String> = graph
+        .wires()
+        .map(|(dst, src)| {
+            let dst_sockets = &graph.node_descriptor(dst.0).input_sockets;
+            let src_sockets = &graph.node_descriptor(src.0).output_sockets;
+            format!(
+                "{} <- {}\n",
+                format_socket(&node_name(graph, dst.0), dst_sockets, dst.1),
+                format_socket(&node_name(graph, src.0), src_sockets, src.1)
+            )
+        })
+        .collect();
+    wires.sort();
+    if !wires.is_empty() {
+        out += "\n";
+    }
+    out.extend(wires);
+
+    let mut values = vec![];
+    for (key, _) in graph.nodes() {
+        for (idx, socket) in graph.node_descriptor(key).input_sockets.iter().enumerate() {
+            let value = graph.input_value(key, idx);
+            if graph.src_for_dest(key, idx).is_none() && value != socket.default && !socket.label.starts_with('~') {
+                values.push(format!("{}.{} = {}{:+}i\n", node_name(graph, key), socket.label, value.re, value.im));
+            }
+        }
+    }
+    if !values.is_empty() {
+        out += "\n";
+    }
+    out.extend(values);
+    out
+}
+
+/// The side-panel editor: a text box, plus buttons to apply it to the graph
+/// or to replace it with the graph as it is now.
+#[derive(Default)]
+pub struct DslEditor {
+    source: String,
+    /// apply on every edit that parses
+    live: bool,
+    problems: Vec<DslError>,
+    /// what applying the text has added, and so may take away again
+    created: HashSet<NodeKey>,
+}
+impl DslEditor {
+    fn apply(&mut self, graph: &mut Patch) {
+        self.problems = match parse(&self.source) {
+            Ok(plan) => apply(graph, &plan, &mut self.created),
+            Err(e) => vec![e],
+        };
+    }
+
+    fn replace(&mut self, graph: &mut Patch) {
+        self.problems = match parse(&self.source) {
+            Ok(plan) => replace(graph, &plan, &mut self.created),
+            Err(e) => vec![e],
+        };
+    }
+
+    /// Returns whether the text was applied to the graph.
+    pub fn ui(&mut self, ui: &mut egui::Ui, graph: &mut Patch) -> bool {
+        let mut applied = false;
+        ui.horizontal(|ui| {
+            ui.label("Patch text");
+            if ui.button("Apply").clicked() {
+                self.apply(graph);
+                applied = true;
+            }
+            if ui
+                .button("Replace")
+                .on_hover_text("apply, and remove every node the text doesn't mention")
+                .clicked()
+            {
+                self.replace(graph);
+                applied = true;
+            }
+            if ui.button("Export").clicked() {
+                self.source = export(graph);
+                self.problems.clear();
+            }
+            ui.checkbox(&mut self.live, "Live");
+        });
+
+        let edited = ui
+            .add(
+                egui::TextEdit::multiline(&mut self.source)
+                    .code_editor()
+                    .desired_rows(8)
+                    .desired_width(f32::INFINITY),
+            )
+            .changed();
+        if edited && self.live {
+            // half-typed lines don't parse, so they leave the graph alone
+            self.apply(graph);
+            applied = true;
+        }
+
+        for problem in &self.problems {
+            ui.colored_label(ui.visuals().error_fg_color, problem.to_string());
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    fn run(graph: &mut Patch, source: &str, created: &mut HashSet<NodeKey>) {
+        let plan = parse(source).unwrap();
+        assert_eq!(apply(graph, &plan, created), vec![]);
+    }
+
+    /// every node by name, and every wire by names and socket labels
+    fn summary(graph: &Patch) -> (BTreeSet<String>, BTreeSet<String>) {
+        let nodes = graph.nodes().map(|(key, _)| node_name(graph, key)).collect();
+        let wires = graph
+            .wires()
+            .map(|(dst, src)| {
+                format!(
+                    "{}.{} <- {}.{}",
+                    node_name(graph, dst.0),
+                    graph.node_descriptor(dst.0).input_sockets[dst.1].label,
+                    node_name(graph, src.0),
+                    graph.node_descriptor(src.0).output_sockets[src.1].label,
+                )
+            })
+            .collect();
+        (nodes, wires)
+    }
+
+    #[test]
+    fn lexes_numbers_and_names() {
+        let tokens: Vec<Token> = lex("a.~f_mul = -1.5e-2+2i; b").unwrap().into_iter().map(|(t, _)| t).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("a".to_owned()),
+                Token::Dot,
+                Token::Ident("~f_mul".to_owned()),
+                Token::Eq,
+                Token::Minus,
+                Token::Number(1.5e-2),
+                Token::Plus,
+                Token::Imaginary(2.0),
+                Token::End,
+                Token::Ident("b".to_owned()),
+                Token::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors_say_where() {
+        let line_of = |source: &str| parse(source).err().map(|e| e.line);
+        assert_eq!(line_of("a = phasor()\nb = nope()"), Some(2));
+        assert_eq!(line_of("a = phasor()\n\na = phasor()"), Some(3));
+        assert_eq!(line_of("out <- missing"), Some(1));
+        assert_eq!(line_of("a = phasor(f_mul=)"), Some(1));
+        assert_eq!(line_of("a = phasor(\n"), Some(1));
+        assert_eq!(line_of("a.0 = 1"), Some(1));
+        assert!(parse("# nothing\n\n").is_ok());
+    }
+
+    #[test]
+    fn programs_build_graphs() {
+        let mut graph = Patch::default();
+        let mut created = HashSet::new();
+        run(
+            &mut graph,
+            "osc = phasor(f_mul=2)\nlfo = phasor(f_div=100)\nosc.~f_mul <- lfo\nout <- linear(osc, m=0.5+0.5i)",
+            &mut created,
+        );
+        let (nodes, wires) = summary(&graph);
+        assert_eq!(nodes, BTreeSet::from(["osc", "lfo", "out", "linear_1"].map(str::to_owned)));
+        assert_eq!(
+            wires,
+            BTreeSet::from(
+                ["osc.~f_mul <- lfo.Out", "linear_1.In <- osc.Out", "out.Out <- linear_1.Out"].map(str::to_owned)
+            )
+        );
+        assert_eq!(created.len(), 4);
+    }
+
+    #[test]
+    fn export_parses_back() {
+        let mut graph = Patch::default();
+        let mut created = HashSet::new();
+        run(
+            &mut graph,
+            "osc = phasor(f_mul=2)\nlfo = phasor(f_div=100)\nosc.~f_mul <- lfo\nout <- linear(osc, m=0.5-0.5i)\nosc.Mod = 0.25",
+            &mut created,
+        );
+        // a name that'd lex as a number if it went out as is
+        let hand_made = graph.add_node_with_layout(
+            crate::registry::lookup("quantize").unwrap().make(),
+            NodeLayout {
+                title: Some("2nd stage".to_owned()),
+                ..Default::default()
+            },
+        );
+        let (linear, _) = graph.nodes().find(|&(key, _)| node_name(&graph, key) == "linear_1").unwrap();
+        graph.connect((hand_made, 0), (linear, 0)).unwrap();
+
+        let exported = export(&graph);
+        let mut copy = Patch::default();
+        run(&mut copy, &exported, &mut HashSet::new());
+        assert_eq!(summary(&copy), summary(&graph));
+        assert_eq!(export(&copy), exported);
+
+        // and applying it to where it came from changes nothing
+        let before = summary(&graph);
+        run(&mut graph, &exported, &mut created);
+        assert_eq!(summary(&graph), before);
+    }
+
+    #[test]
+    fn only_removes_what_it_made() {
+        let mut graph = Patch::default();
+        let mut created = HashSet::new();
+        let hand_made = graph.add_node(crate::registry::lookup("phasor").unwrap().make());
+        run(&mut graph, "a = phasor()\nb = phasor()", &mut created);
+        assert_eq!(graph.nodes().count(), 3);
+
+        // dropping b takes it away, but not the node made by hand
+        run(&mut graph, "a = phasor()", &mut created);
+        assert_eq!(summary(&graph).0, BTreeSet::from([node_name(&graph, hand_made), "a".to_owned()]));
+
+        // unless asked to
+        assert_eq!(replace(&mut graph, &parse("a = phasor()").unwrap(), &mut created), vec![]);
+        assert_eq!(summary(&graph).0, BTreeSet::from(["a".to_owned()]));
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use super::{NodeKey, SocketDirection, SocketType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    MissingNode(NodeKey),
+    SocketOutOfRange {
+        node: NodeKey,
+        direction: SocketDirection,
+        index: usize,
+    },
+    /// the socket exists, but on the other side of the node
+    WrongDirection {
+        node: NodeKey,
+        expected: SocketDirection,
+        index: usize,
+    },
+    SelfConnection(NodeKey),
+    WouldCreateCycle {
+        src: (NodeKey, usize),
+        dst: (NodeKey, usize),
+    },
+    /// the sockets carry different kinds of signal; see `SocketType::connects_to`
+    TypeMismatch {
+        src_type: SocketType,
+        dst_type: SocketType,
+    },
+    /// the node and its side tables (descriptor, layout) disagree
+    InconsistentNode(NodeKey),
+    /// the wire's sockets changed (or went away) since it was made
+    StaleWire {
+        src: (NodeKey, usize),
+        dst: (NodeKey, usize),
+    },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::MissingNode(node) => write!(f, "no such node {node:?}"),
+            GraphError::SocketOutOfRange {
+                node,
+                direction,
+                index,
+            } => write!(f, "node {node:?} has no {direction:?} socket #{index}"),
+            GraphError::WrongDirection {
+                node,
+                expected,
+                index,
+            } => write!(
+                f,
+                "socket #{index} of node {node:?} is not an {expected:?} socket"
+            ),
+            GraphError::SelfConnection(node) => {
+                write!(f, "can't connect node {node:?} to itself")
+            }
+            GraphError::WouldCreateCycle { src, dst } => write!(
+                f,
+                "connecting {src:?} to {dst:?} would create a cycle"
+            ),
+            GraphError::TypeMismatch { src_type, dst_type } => {
+                write!(f, "can't connect a {src_type:?} output to a {dst_type:?} input")
+            }
+            GraphError::InconsistentNode(node) => {
+                write!(f, "side tables for node {node:?} are out of sync")
+            }
+            GraphError::StaleWire { src, dst } => write!(
+                f,
+                "the sockets of the wire from {src:?} to {dst:?} have changed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
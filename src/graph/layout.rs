@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Where and how a node is drawn. This lives in the graph (rather than in
+/// egui's memory) so it gets saved and loaded along with everything else.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct NodeLayout {
+    /// top-left corner of the node frame, relative to the graph's origin
+    pub pos: [f32; 2],
+    pub collapsed: bool,
+    /// overrides the default title if set
+    pub title: Option<String>,
+    /// tints the node frame if set (sRGB)
+    pub color: Option<[u8; 3]>,
+}
+
+impl NodeLayout {
+    pub fn at(pos: [f32; 2]) -> NodeLayout {
+        NodeLayout {
+            pos,
+            ..Default::default()
+        }
+    }
+}
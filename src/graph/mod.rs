@@ -3,84 +3,322 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use slotmap::new_key_type;
 
+use crate::sample::QuadioSample;
+
+mod error;
+mod layout;
+mod modulation;
 mod node;
-pub use node::{Node, NodeDescriptor, SocketDescriptor};
+pub use error::GraphError;
+pub use layout::NodeLayout;
+pub use modulation::ParamModulation;
+pub use node::{Node, NodeDescriptor, SocketDescriptor, SocketType};
 
 new_key_type! {
     pub struct NodeKey;
 }
 
+/// Whatever else an application keeps per graph, keyed by node (and saved
+/// along with it): the graph just holds on to it and says when a node goes.
+pub trait SideTables: Default {
+    fn remove_node(&mut self, node: NodeKey);
+}
+impl SideTables for () {
+    fn remove_node(&mut self, _node: NodeKey) {}
+}
+
 #[derive(Serialize, Deserialize)]
-pub struct NodeGraph<N: Node> {
+pub struct NodeGraph<N: Node, T: SideTables = ()> {
     nodes: slotmap::SlotMap<NodeKey, N>,
+    // saved, but only trusted as far as `repair` can check them against the
+    // nodes; they're what wires get matched up by if sockets have changed
+    #[serde(default)]
     descriptors: slotmap::secondary::SecondaryMap<NodeKey, NodeDescriptor>,
+    #[serde(default)]
+    layouts: slotmap::secondary::SecondaryMap<NodeKey, NodeLayout>,
 
     // each input socket has only one thing connected
     // so we can use that :)
     wires_by_destination: HashMap<(NodeKey, usize), (NodeKey, usize)>,
+    // user-set values for unconnected inputs, overriding the descriptor's default
+    #[serde(default)]
+    input_values: HashMap<(NodeKey, usize), QuadioSample>,
+    // parameters exposed as inputs; their sockets come after the node's own
+    #[serde(default)]
+    modulations: slotmap::secondary::SecondaryMap<NodeKey, Vec<ParamModulation>>,
+    #[serde(default)]
+    tables: T,
+
+    /// if false, `connect` refuses wires that would close a loop
+    #[serde(default)]
+    allow_cycles: bool,
+
+    /// bumped on every structural change (nodes or wires added/removed), so
+    /// anyone caching per-node state knows when to go prune it
+    #[serde(skip)]
+    generation: u64,
 }
-impl<N: Node> Default for NodeGraph<N> {
+impl<N: Node, T: SideTables> Default for NodeGraph<N, T> {
     fn default() -> Self {
         NodeGraph {
             nodes: Default::default(),
             descriptors: Default::default(),
+            layouts: Default::default(),
             wires_by_destination: Default::default(),
+            input_values: Default::default(),
+            modulations: Default::default(),
+            tables: Default::default(),
+            allow_cycles: false,
+            generation: 0,
         }
     }
 }
-impl<N: Node> NodeGraph<N> {
-    #[doc(hidden)]
-    pub fn validate_wires(&self) {
-        for (dst, src) in &self.wires_by_destination {
-            // FIXME: we must also check the input/output name
-            assert!(self.nodes.contains_key(src.0));
-            assert!(self.nodes.contains_key(dst.0));
+impl<N: Node, T: SideTables> NodeGraph<N, T> {
+    /// Checks every wire against the nodes and their descriptors, returning
+    /// everything that's wrong (an empty vec means the graph is fine).
+    pub fn validate(&self) -> Vec<GraphError> {
+        let mut problems = vec![];
+
+        // every node has exactly one descriptor and one layout, and vice versa
+        let side_table_keys = self
+            .descriptors
+            .keys()
+            .chain(self.layouts.keys())
+            .chain(self.modulations.keys());
+        for node in self.nodes.keys().chain(side_table_keys) {
+            let consistent = self.nodes.contains_key(node)
+                && self.descriptors.contains_key(node)
+                && self.layouts.contains_key(node);
+            if !consistent && !problems.contains(&GraphError::InconsistentNode(node)) {
+                problems.push(GraphError::InconsistentNode(node));
+            }
+        }
+
+        for (&dst, &src) in &self.wires_by_destination {
+            let checked = self
+                .check_socket(src, SocketDirection::Output)
+                .and_then(|_| self.check_socket(dst, SocketDirection::Input))
+                .and_then(|_| self.check_types(src, dst));
+            if let Err(e) = checked {
+                problems.push(e);
+            }
+        }
+        problems
+    }
+
+    fn check_socket(
+        &self,
+        (node, index): (NodeKey, usize),
+        direction: SocketDirection,
+    ) -> Result<(), GraphError> {
+        let Some(descriptor) = self.descriptors.get(node).filter(|_| self.nodes.contains_key(node)) else {
+            return Err(GraphError::MissingNode(node));
+        };
+
+        let (sockets, other_sockets) = match direction {
+            SocketDirection::Input => (&descriptor.input_sockets, &descriptor.output_sockets),
+            SocketDirection::Output => (&descriptor.output_sockets, &descriptor.input_sockets),
+        };
+        if index < sockets.len() {
+            Ok(())
+        } else if index < other_sockets.len() {
+            Err(GraphError::WrongDirection {
+                node,
+                expected: direction,
+                index,
+            })
+        } else {
+            Err(GraphError::SocketOutOfRange {
+                node,
+                direction,
+                index,
+            })
+        }
+    }
 
-            let src_descriptor = &self.descriptors[src.0];
-            let dst_descriptor = &self.descriptors[dst.0];
+    fn check_types(&self, src: (NodeKey, usize), dst: (NodeKey, usize)) -> Result<(), GraphError> {
+        let src_type = self.descriptors[src.0].output_sockets[src.1].ty;
+        let dst_type = self.descriptors[dst.0].input_sockets[dst.1].ty;
+        if src_type.connects_to(dst_type) {
+            Ok(())
+        } else {
+            Err(GraphError::TypeMismatch { src_type, dst_type })
+        }
+    }
+
+    /// is `target` upstream of (or the same as) `node`?
+    fn is_upstream(&self, target: NodeKey, node: NodeKey) -> bool {
+        let mut stack = vec![node];
+        let mut seen = std::collections::HashSet::new();
+        while let Some(n) = stack.pop() {
+            if n == target {
+                return true;
+            }
+            if !seen.insert(n) {
+                continue;
+            }
+            let num_inputs = self.descriptors.get(n).map_or(0, |d| d.input_sockets.len());
+            stack.extend((0..num_inputs).filter_map(|i| self.src_for_dest(n, i)).map(|(src, _)| src));
+        }
+        false
+    }
+
+    /// Brings a graph that came from outside (a loaded patch, say) back in
+    /// line with its nodes. Every descriptor is rebuilt from its node and
+    /// modulations, with wires following their sockets by label as in
+    /// `refresh_descriptor`; missing layouts are filled in; and side table
+    /// entries, wires and input values that still don't fit anything are
+    /// dropped. Returns what was wrong.
+    pub fn repair(&mut self) -> Vec<GraphError> {
+        let mut problems = vec![];
+        let mut inconsistent = |node| {
+            if !problems.contains(&GraphError::InconsistentNode(node)) {
+                problems.push(GraphError::InconsistentNode(node));
+            }
+        };
 
-            assert!(src_descriptor.output_sockets.get(src.1).is_some());
-            assert!(dst_descriptor.input_sockets.get(dst.1).is_some());
+        let nodes = &self.nodes;
+        let orphans = self
+            .descriptors
+            .keys()
+            .chain(self.layouts.keys())
+            .chain(self.modulations.keys())
+            .filter(|&key| !nodes.contains_key(key));
+        orphans.for_each(&mut inconsistent);
+        self.descriptors.retain(|key, _| nodes.contains_key(key));
+        self.layouts.retain(|key, _| nodes.contains_key(key));
+        self.modulations.retain(|key, _| nodes.contains_key(key));
+
+        let keys: Vec<NodeKey> = self.nodes.keys().collect();
+        for &key in &keys {
+            if !self.layouts.contains_key(key) {
+                inconsistent(key);
+                self.layouts.insert(key, NodeLayout::default());
+            }
+            if !self.descriptors.contains_key(key) {
+                // nothing to match the wires up by, so they stay where they are
+                inconsistent(key);
+                let descriptor = self.fresh_descriptor(key);
+                self.descriptors.insert(key, descriptor);
+            }
+        }
+
+        // anything going to or from nodes that aren't there can go right away
+        let wires_before = self.wires_by_destination.clone();
+        self.wires_by_destination
+            .retain(|dst, src| nodes.contains_key(dst.0) && nodes.contains_key(src.0));
+        for &key in &keys {
+            self.refresh_descriptor(key);
+        }
+
+        for (dst, src) in wires_before {
+            if self.wires_by_destination.get(&dst) == Some(&src) {
+                continue;
+            }
+            let checked = self
+                .check_socket(src, SocketDirection::Output)
+                .and_then(|_| self.check_socket(dst, SocketDirection::Input));
+            problems.push(match checked {
+                // still there, but where it went has changed
+                Ok(()) => GraphError::StaleWire { src, dst },
+                Err(e) => e,
+            });
         }
+        let bad_wires: Vec<_> = self
+            .wires()
+            .filter_map(|(dst, src)| {
+                let checked = self
+                    .check_socket(src, SocketDirection::Output)
+                    .and_then(|_| self.check_socket(dst, SocketDirection::Input))
+                    .and_then(|_| self.check_types(src, dst));
+                checked.err().map(|e| (dst, e))
+            })
+            .collect();
+        for (dst, e) in bad_wires {
+            self.wires_by_destination.remove(&dst);
+            problems.push(e);
+        }
+        let descriptors = &self.descriptors;
+        self.input_values
+            .retain(|&(node, idx), _| descriptors.get(node).is_some_and(|d| idx < d.input_sockets.len()));
+
+        self.generation += 1;
+        problems
+    }
+
+    /// Swaps in a whole new graph (e.g. a freshly loaded patch) while making
+    /// sure the generation still moves forward.
+    pub fn replace(&mut self, other: NodeGraph<N, T>) {
+        let generation = self.generation;
+        *self = other;
+        self.generation = generation + 1;
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn allow_cycles(&self) -> bool {
+        self.allow_cycles
+    }
+    pub fn set_allow_cycles(&mut self, allow_cycles: bool) {
+        self.allow_cycles = allow_cycles;
     }
 
     pub fn add_node(&mut self, node: N) -> NodeKey {
+        self.add_node_with_layout(node, NodeLayout::default())
+    }
+
+    pub fn add_node_with_layout(&mut self, node: N, layout: NodeLayout) -> NodeKey {
         // more convenient to do this now before we stick `node` into self.nodes
         let descriptor = node.get_descriptor();
 
         let key = self.nodes.insert(node);
         self.descriptors.insert(key, descriptor);
-
-        self.validate_wires();
+        self.layouts.insert(key, layout);
+        self.generation += 1;
 
         key
     }
 
     pub fn remove_node(&mut self, node_key: NodeKey) -> Option<N> {
+        let rv = self.nodes.remove(node_key)?;
+
         // remove any wires to or from this node's sockets
         self.wires_by_destination.retain(|dst, src| {
             // i.e. keep only those which are unrelated to the removed node
             dst.0 != node_key && src.0 != node_key
         });
+        self.input_values.retain(|(node, _), _| *node != node_key);
+        self.modulations.remove(node_key);
+        self.tables.remove_node(node_key);
+        self.descriptors.remove(node_key);
+        self.layouts.remove(node_key);
+        self.generation += 1;
 
-        let rv = self.nodes.remove(node_key);
-
-        self.validate_wires();
-
-        rv
+        Some(rv)
     }
 
+    /// Wires output socket `src` to input socket `dst`, replacing whatever
+    /// was connected to `dst` before (which is returned).
     pub fn connect(
         &mut self,
         src: (NodeKey, usize),
         dst: (NodeKey, usize),
-    ) -> Option<(NodeKey, usize)> {
-        // FIXME: validate args here to avoid confusing blowups later!
-        let rv = self.wires_by_destination.insert(dst, src);
+    ) -> Result<Option<(NodeKey, usize)>, GraphError> {
+        self.check_socket(src, SocketDirection::Output)?;
+        self.check_socket(dst, SocketDirection::Input)?;
 
-        self.validate_wires();
+        if src.0 == dst.0 {
+            return Err(GraphError::SelfConnection(src.0));
+        }
+        self.check_types(src, dst)?;
+        if !self.allow_cycles && self.is_upstream(dst.0, src.0) {
+            return Err(GraphError::WouldCreateCycle { src, dst });
+        }
 
-        rv
+        self.generation += 1;
+        Ok(self.wires_by_destination.insert(dst, src))
     }
     pub fn disconnect(&mut self, node: NodeKey, dir: SocketDirection, idx: usize) {
         match dir {
@@ -93,10 +331,149 @@ impl<N: Node> NodeGraph<N> {
                 self.wires_by_destination.retain(|_, v| *v != (node, idx));
             }
         }
+        self.generation += 1;
+    }
+    /// Re-fetches the descriptor of every node that says its sockets changed.
+    pub fn refresh_descriptors(&mut self) {
+        let changed: Vec<_> = self
+            .nodes
+            .iter_mut()
+            .filter_map(|(k, node)| node.take_descriptor_changed().then_some(k))
+            .collect();
+
+        for node in changed {
+            self.refresh_descriptor(node);
+        }
+    }
+
+    /// What `node`'s descriptor should be: its own sockets, then one for each
+    /// exposed parameter.
+    fn fresh_descriptor(&self, node: NodeKey) -> NodeDescriptor {
+        let mut descriptor = self.nodes[node].get_descriptor();
+        descriptor
+            .input_sockets
+            .extend(self.modulations(node).iter().map(ParamModulation::socket));
+        descriptor
+    }
+
+    /// Re-fetches `node`'s descriptor. Wires to or from sockets that still
+    /// exist (matched by label) follow them to their new index; the rest, and
+    /// any whose socket changed to an incompatible type, are dropped.
+    pub fn refresh_descriptor(&mut self, node: NodeKey) {
+        let new_descriptor = self.fresh_descriptor(node);
+        let old_descriptor = &self.descriptors[node];
+
+        let remap = |old: &[SocketDescriptor], new: &[SocketDescriptor], idx: usize| {
+            let label = &old.get(idx)?.label;
+            if new.get(idx).is_some_and(|socket| &socket.label == label) {
+                // hasn't moved (this also keeps sockets with the same label apart)
+                return Some(idx);
+            }
+            new.iter().position(|socket| &socket.label == label)
+        };
+
+        self.wires_by_destination = std::mem::take(&mut self.wires_by_destination)
+            .into_iter()
+            .filter_map(|(mut dst, mut src)| {
+                if dst.0 == node {
+                    dst.1 = remap(
+                        &old_descriptor.input_sockets,
+                        &new_descriptor.input_sockets,
+                        dst.1,
+                    )?;
+                }
+                if src.0 == node {
+                    src.1 = remap(
+                        &old_descriptor.output_sockets,
+                        &new_descriptor.output_sockets,
+                        src.1,
+                    )?;
+                }
+                Some((dst, src))
+            })
+            .collect();
+        self.input_values = std::mem::take(&mut self.input_values)
+            .into_iter()
+            .filter_map(|(mut dst, value)| {
+                if dst.0 == node {
+                    dst.1 = remap(
+                        &old_descriptor.input_sockets,
+                        &new_descriptor.input_sockets,
+                        dst.1,
+                    )?;
+                }
+                Some((dst, value))
+            })
+            .collect();
+
+        self.descriptors[node] = new_descriptor;
+        let descriptors = &self.descriptors;
+        self.wires_by_destination.retain(|dst, src| {
+            if dst.0 != node && src.0 != node {
+                return true;
+            }
+            let src_type = descriptors.get(src.0).and_then(|d| d.output_sockets.get(src.1));
+            let dst_type = descriptors.get(dst.0).and_then(|d| d.input_sockets.get(dst.1));
+            matches!((src_type, dst_type), (Some(s), Some(d)) if s.ty.connects_to(d.ty))
+        });
+        self.generation += 1;
+    }
+
+    /// The parameters of `node` exposed as inputs, in socket order.
+    pub fn modulations(&self, node: NodeKey) -> &[ParamModulation] {
+        self.modulations.get(node).map_or(&[], Vec::as_slice)
     }
+    /// Which of `node`'s input sockets belong to exposed parameters (they're
+    /// always at the end).
+    pub fn modulation_inputs(&self, node: NodeKey) -> std::ops::Range<usize> {
+        let num_inputs = self.descriptors[node].input_sockets.len();
+        num_inputs - self.modulations(node).len()..num_inputs
+    }
+    /// Adds an input socket driving `node`'s parameter `param`.
+    pub fn expose_param(&mut self, node: NodeKey, param: &str) {
+        if !self.nodes.contains_key(node) || self.modulations(node).iter().any(|m| m.param == param) {
+            return;
+        }
+        self.modulations
+            .entry(node)
+            .unwrap()
+            .or_default()
+            .push(ParamModulation::new(param));
+        self.refresh_descriptor(node);
+    }
+    /// Takes the socket added by `expose_param` away again, along with its wire.
+    pub fn unexpose_param(&mut self, node: NodeKey, param: &str) {
+        let Some(modulations) = self.modulations.get_mut(node) else {
+            return;
+        };
+        modulations.retain(|m| m.param != param);
+        if modulations.is_empty() {
+            self.modulations.remove(node);
+        }
+        self.refresh_descriptor(node);
+    }
+    pub fn set_modulation_depth(&mut self, node: NodeKey, param: &str, depth: f32) {
+        let modulation = self
+            .modulations
+            .get_mut(node)
+            .and_then(|mods| mods.iter_mut().find(|m| m.param == param));
+        if let Some(modulation) = modulation {
+            modulation.depth = depth;
+        }
+    }
+
     pub fn node_descriptor(&self, node: NodeKey) -> &NodeDescriptor {
         &self.descriptors[node]
     }
+    pub fn contains_node(&self, node: NodeKey) -> bool {
+        self.nodes.contains_key(node)
+    }
+    pub fn layout(&self, node: NodeKey) -> &NodeLayout {
+        &self.layouts[node]
+    }
+    pub fn layout_mut(&mut self, node: NodeKey) -> &mut NodeLayout {
+        &mut self.layouts[node]
+    }
     pub fn nodes(&self) -> impl Iterator<Item = (NodeKey, &N)> {
         self.nodes.iter()
     }
@@ -105,8 +482,33 @@ impl<N: Node> NodeGraph<N> {
             .iter_mut()
             .map(|(k, v)| (k, v, &self.descriptors[k]))
     }
+    /// like `nodes_mut`, but also hands out each node's (mutable) layout
+    pub fn nodes_with_layout_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (NodeKey, &mut N, &NodeDescriptor, &mut NodeLayout)> {
+        // both maps should hold exactly the same keys already (see
+        // `add_node_with_layout`, `remove_node` and `repair`), but a node
+        // that somehow lost its layout just gets a default one rather than
+        // taking the UI down
+        let nodes = &self.nodes;
+        self.layouts.retain(|k, _| nodes.contains_key(k));
+        for k in self.nodes.keys() {
+            if !self.layouts.contains_key(k) {
+                self.layouts.insert(k, NodeLayout::default());
+            }
+        }
+        // now they iterate in the same slot order, so can be walked side by side
+        self.nodes
+            .iter_mut()
+            .zip(self.layouts.iter_mut())
+            .map(|((k, v), (layout_k, layout))| {
+                debug_assert_eq!(k, layout_k, "node and layout out of step");
+                (k, v, &self.descriptors[k], layout)
+            })
+    }
 
-    /// returns iterator over (src, dest). dest is globally unique
+    /// returns iterator over (dest, src), since that's how wires are keyed:
+    /// dest is globally unique
     pub fn wires(&self) -> impl Iterator<Item = ((NodeKey, usize), (NodeKey, usize))> + '_ {
         self.wires_by_destination.iter().map(|(&k, &v)| (k, v))
     }
@@ -115,13 +517,206 @@ impl<N: Node> NodeGraph<N> {
         self.wires_by_destination.get(&(node, idx)).copied()
     }
 
+    /// The value input `idx` of `node` reads while unconnected.
+    pub fn input_value(&self, node: NodeKey, idx: usize) -> QuadioSample {
+        self.input_values
+            .get(&(node, idx))
+            .copied()
+            .unwrap_or(self.descriptors[node].input_sockets[idx].default)
+    }
+    pub fn set_input_value(&mut self, node: NodeKey, idx: usize, value: QuadioSample) {
+        let Some(socket) = self.descriptors.get(node).and_then(|d| d.input_sockets.get(idx)) else {
+            return;
+        };
+        if value == socket.default {
+            self.input_values.remove(&(node, idx));
+        } else {
+            self.input_values.insert((node, idx), value);
+        }
+    }
+
+    pub fn tables(&self) -> &T {
+        &self.tables
+    }
+    pub fn tables_mut(&mut self) -> &mut T {
+        &mut self.tables
+    }
+
+    pub fn get_node(&self, node: NodeKey) -> &N {
+        &self.nodes[node]
+    }
     pub fn get_node_mut(&mut self, node: NodeKey) -> &mut N {
         &mut self.nodes[node]
     }
 }
 
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum SocketDirection {
     Input,
     Output,
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// just sockets, nothing else
+    struct TestNode {
+        inputs: usize,
+        outputs: usize,
+    }
+    impl Node for TestNode {
+        fn get_descriptor(&self) -> NodeDescriptor {
+            let sockets = |n: usize| {
+                (0..n)
+                    .map(|i| SocketDescriptor {
+                        label: i.to_string(),
+                        ..Default::default()
+                    })
+                    .collect()
+            };
+            NodeDescriptor {
+                input_sockets: sockets(self.inputs),
+                output_sockets: sockets(self.outputs),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Add { inputs: usize, outputs: usize },
+        // indices into whatever nodes are alive at the time
+        Remove(usize),
+        Connect { src: (usize, usize), dst: (usize, usize) },
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            2 => (0..4usize, 0..4usize).prop_map(|(inputs, outputs)| Op::Add { inputs, outputs }),
+            1 => any::<usize>().prop_map(Op::Remove),
+            // sometimes past the end, to exercise the argument checks
+            4 => (any::<usize>(), 0..5usize, any::<usize>(), 0..5usize)
+                .prop_map(|(a, i, b, j)| Op::Connect { src: (a, i), dst: (b, j) }),
+        ]
+    }
+
+    fn nth_node(graph: &NodeGraph<TestNode>, n: usize) -> Option<NodeKey> {
+        let count = graph.nodes().count();
+        (count > 0).then(|| graph.nodes().nth(n % count).unwrap().0)
+    }
+
+    fn apply(graph: &mut NodeGraph<TestNode>, op: &Op) {
+        match *op {
+            Op::Add { inputs, outputs } => {
+                graph.add_node(TestNode { inputs, outputs });
+            }
+            Op::Remove(n) => {
+                if let Some(node) = nth_node(graph, n) {
+                    graph.remove_node(node);
+                }
+            }
+            Op::Connect { src, dst } => {
+                if let (Some(a), Some(b)) = (nth_node(graph, src.0), nth_node(graph, dst.0)) {
+                    // refusals are fine, as long as the graph stays sound
+                    let _ = graph.connect((a, src.1), (b, dst.1));
+                }
+            }
+        }
+    }
+
+    /// Kahn's algorithm: true if the nodes can't all be put in order
+    fn has_cycle(graph: &NodeGraph<TestNode>) -> bool {
+        let mut inputs_left: HashMap<NodeKey, usize> = graph.nodes().map(|(k, _)| (k, 0)).collect();
+        for (dst, _) in graph.wires() {
+            *inputs_left.get_mut(&dst.0).unwrap() += 1;
+        }
+        let mut ready: Vec<_> = inputs_left.iter().filter(|(_, &n)| n == 0).map(|(&k, _)| k).collect();
+        let mut ordered = 0;
+        while let Some(node) = ready.pop() {
+            ordered += 1;
+            for (dst, src) in graph.wires() {
+                if src.0 == node {
+                    let left = inputs_left.get_mut(&dst.0).unwrap();
+                    *left -= 1;
+                    if *left == 0 {
+                        ready.push(dst.0);
+                    }
+                }
+            }
+        }
+        ordered != inputs_left.len()
+    }
+
+    proptest! {
+        #[test]
+        fn random_edits_keep_the_graph_sound(ops in prop::collection::vec(op(), 0..64)) {
+            let mut graph = NodeGraph::default();
+            for op in &ops {
+                let generation = graph.generation();
+                apply(&mut graph, op);
+                prop_assert!(graph.generation() >= generation);
+
+                prop_assert_eq!(graph.validate(), vec![]);
+                prop_assert!(!has_cycle(&graph), "{:?} closed a loop", op);
+            }
+        }
+
+        #[test]
+        fn remove_node_leaves_nothing_behind(ops in prop::collection::vec(op(), 0..64), victim: usize) {
+            let mut graph = NodeGraph::default();
+            for op in &ops {
+                apply(&mut graph, op);
+            }
+            let Some(node) = nth_node(&graph, victim) else {
+                return Ok(());
+            };
+            let generation = graph.generation();
+            graph.remove_node(node);
+
+            prop_assert!(graph.generation() > generation);
+            prop_assert!(!graph.contains_node(node));
+            prop_assert!(graph.wires().all(|(dst, src)| dst.0 != node && src.0 != node));
+            prop_assert!(!graph.descriptors.contains_key(node));
+            prop_assert!(!graph.layouts.contains_key(node));
+            prop_assert!(!graph.input_values.keys().any(|&(n, _)| n == node));
+            prop_assert_eq!(graph.validate(), vec![]);
+        }
+
+        #[test]
+        fn cycles_only_when_allowed(ops in prop::collection::vec(op(), 0..64)) {
+            let mut graph = NodeGraph::default();
+            graph.set_allow_cycles(true);
+            for op in &ops {
+                apply(&mut graph, op);
+            }
+            // loops may have been made, but every wire still has to check out
+            prop_assert_eq!(graph.validate(), vec![]);
+
+            graph.set_allow_cycles(false);
+            let wires: Vec<_> = graph.wires().collect();
+            let mut rebuilt = NodeGraph::default();
+            let keys: HashMap<_, _> = graph
+                .nodes()
+                .map(|(k, n)| (k, rebuilt.add_node(TestNode { inputs: n.inputs, outputs: n.outputs })))
+                .collect();
+            for (dst, src) in wires {
+                let _ = rebuilt.connect((keys[&src.0], src.1), (keys[&dst.0], dst.1));
+            }
+            prop_assert!(!has_cycle(&rebuilt));
+        }
+    }
+
+    #[test]
+    fn nodes_without_layouts_get_a_default() {
+        let mut graph: NodeGraph<TestNode> = NodeGraph::default();
+        let a = graph.add_node(TestNode { inputs: 1, outputs: 1 });
+        let b = graph.add_node(TestNode { inputs: 1, outputs: 1 });
+        graph.layout_mut(b).pos = [5.0, 5.0];
+        graph.layouts.remove(a);
+
+        let seen: Vec<_> = graph.nodes_with_layout_mut().map(|(k, _, _, layout)| (k, layout.pos)).collect();
+        assert_eq!(seen, vec![(a, [0.0, 0.0]), (b, [5.0, 5.0])]);
+    }
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+use super::{SocketDescriptor, SocketType};
+
+/// A node parameter that's been exposed as an extra input socket, so some
+/// other node can drive it. While it's being modulated the parameter reads
+/// `base + depth * input`, where the base is whatever the parameter itself is
+/// set to. That's worked out for every sample: smoothed parameters follow it
+/// exactly, anything else gets set wherever it changes, with the block split
+/// there (though never into pieces shorter than a few dozen samples).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ParamModulation {
+    /// the parameter's name (see `ParamDescriptor::name`)
+    pub param: String,
+    pub depth: f32,
+}
+
+impl ParamModulation {
+    pub fn new(param: impl Into<String>) -> ParamModulation {
+        ParamModulation {
+            param: param.into(),
+            depth: 1.0,
+        }
+    }
+
+    /// the socket this gets appended to the node's inputs as
+    pub fn socket(&self) -> SocketDescriptor {
+        SocketDescriptor {
+            // prefixed so it can't collide with the node's own inputs
+            label: format!("~{}", self.param),
+            ty: SocketType::Complex,
+            ..Default::default()
+        }
+    }
+}
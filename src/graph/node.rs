@@ -1,9 +1,38 @@
 use serde::{Deserialize, Serialize};
 
+use crate::sample::QuadioSample;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// What kind of signal travels through a socket.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SocketType {
+    /// full-rate complex (quadrature) audio
+    #[default]
+    Complex,
+    /// full-rate audio with the imaginary part always zero
+    Real,
+    /// one value per block, for slow stuff like LFOs and knobs
+    Control,
+    /// sample-accurate triggers (gates, notes): a nonzero sample is an event,
+    /// and its value is the event's payload
+    Event,
+}
+impl SocketType {
+    /// Can an output of this type feed an input of type `dst` as-is?
+    /// Anything else needs a conversion node in between.
+    pub fn connects_to(self, dst: SocketType) -> bool {
+        // a real signal is just a complex one that happens to sit on the axis
+        self == dst || (self == SocketType::Real && dst == SocketType::Complex)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct SocketDescriptor {
     pub label: String,
+    #[serde(default)]
+    pub ty: SocketType,
+    /// what an input reads when nothing is wired to it (ignored for outputs)
+    #[serde(default)]
+    pub default: QuadioSample,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -14,4 +43,11 @@ pub struct NodeDescriptor {
 
 pub trait Node {
     fn get_descriptor(&self) -> NodeDescriptor;
+
+    /// Nodes whose sockets can change return true here (once per change) so
+    /// the graph knows to call `get_descriptor` again.
+    /// See `NodeGraph::refresh_descriptors`.
+    fn take_descriptor_changed(&mut self) -> bool {
+        false
+    }
 }
@@ -1,7 +1,16 @@
-use crate::graph::NodeGraph;
+use crate::graph::GraphError;
 use crate::graph::NodeKey;
+use crate::graph::NodeLayout;
+use crate::graph::ParamModulation;
 use crate::graph::SocketDirection;
+use crate::graph::SocketType;
+use crate::patch::Patch;
+use crate::monitor::Fault;
 use crate::node::QuadioNode;
+use crate::palette::Palette;
+use crate::preset::PresetLibrary;
+use crate::registry::NodeType;
+use crate::sample::QuadioSample;
 
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -22,6 +31,24 @@ enum ConnectionEvent {
 struct GraphMemory {
     selection: Option<Selection>,
     socket_positions: HashMap<(NodeKey, SocketDirection, usize), egui::Pos2>,
+    // as of last frame, so new nodes can be put next to the selected one
+    node_sizes: HashMap<NodeKey, egui::Vec2>,
+    // where each node's area was left last frame, in screen space; the area
+    // is only moved by hand when the layout no longer agrees (e.g. the patch
+    // was reloaded), so it stays free to be dragged
+    node_positions: HashMap<NodeKey, egui::Pos2>,
+    // where the background context menu was opened, relative to the origin
+    add_pos: Option<egui::Vec2>,
+    // what's typed into the Add menu's search box
+    add_search: String,
+    palette: Palette,
+    // why the last attempted connection was refused, if it was
+    connection_error: Option<GraphError>,
+    // name typed into the presets menu, and what went wrong loading/saving
+    preset_name: String,
+    preset_error: Option<String>,
+    // graph generation the tables above were last pruned against
+    graph_generation: Option<u64>,
 }
 impl GraphMemory {
     fn is_node_selected(&self, node: NodeKey) -> bool {
@@ -32,60 +59,263 @@ impl GraphMemory {
             _ => false,
         }
     }
+
+    /// Forgets everything we know about nodes (and sockets) that no longer exist.
+    fn prune(&mut self, graph: &Patch) {
+        if self.graph_generation == Some(graph.generation()) {
+            return;
+        }
+        self.graph_generation = Some(graph.generation());
+
+        self.socket_positions.retain(|(node, dir, idx), _| {
+            if !graph.contains_node(*node) {
+                return false;
+            }
+            let descriptor = graph.node_descriptor(*node);
+            match dir {
+                SocketDirection::Input => *idx < descriptor.input_sockets.len(),
+                SocketDirection::Output => *idx < descriptor.output_sockets.len(),
+            }
+        });
+        self.node_sizes.retain(|node, _| graph.contains_node(*node));
+        self.node_positions.retain(|node, _| graph.contains_node(*node));
+        match self.selection {
+            Some(Selection::Node(node)) | Some(Selection::Socket(node, _, _))
+                if !graph.contains_node(node) =>
+            {
+                self.selection = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Picks a spot (relative to the graph origin) for a node added from the
+    /// Add menu: right next to the selected node, or cascading from the
+    /// top-left corner so new nodes don't pile up on top of each other.
+    fn new_node_pos(&self, graph: &Patch) -> [f32; 2] {
+        let selected = match self.selection {
+            Some(Selection::Node(node)) | Some(Selection::Socket(node, _, _)) => Some(node),
+            None => None,
+        };
+        if let Some(node) = selected.filter(|&node| graph.contains_node(node)) {
+            let [x, y] = graph.layout(node).pos;
+            let width = self.node_sizes.get(&node).map_or(96.0, |size| size.x);
+            return [x + width + 32.0, y];
+        }
+
+        let step = 24.0 * (graph.nodes().count() % 16) as f32;
+        [16.0 + step, 16.0 + step]
+    }
+}
+
+fn socket_color(ty: SocketType) -> egui::Color32 {
+    match ty {
+        SocketType::Complex => egui::Color32::from_rgb(0x00, 0xD3, 0xED),
+        SocketType::Real => egui::Color32::from_rgb(0xED, 0x9A, 0x00),
+        SocketType::Control => egui::Color32::from_rgb(0x6C, 0xD4, 0x3A),
+        SocketType::Event => egui::Color32::from_rgb(0xE0, 0x4C, 0xC8),
+    }
+}
+
+fn edit_input_value(ui: &mut egui::Ui, ty: SocketType, value: &mut QuadioSample) {
+    match ty {
+        SocketType::Complex => {
+            ui.add(egui::DragValue::new(&mut value.re).speed(0.01));
+            ui.add(egui::DragValue::new(&mut value.im).speed(0.01).suffix("i"));
+        }
+        SocketType::Real | SocketType::Control => {
+            ui.add(egui::DragValue::new(&mut value.re).speed(0.01));
+        }
+        SocketType::Event => {
+            // a constant event stream doesn't make much sense
+        }
+    }
+}
+
+fn add_node_menu(ui: &mut egui::Ui, graph: &mut Patch, pos: [f32; 2], search: &mut String) {
+    let mut chosen = None;
+    let mut add_button = |ui: &mut egui::Ui, ty: &NodeType| {
+        if ui.button(ty.name).on_hover_text(ty.description).clicked() {
+            chosen = Some(*ty);
+        }
+    };
+
+    let search_response = ui.add(egui::TextEdit::singleline(search).hint_text("search").desired_width(120.0));
+    search_response.request_focus();
+    // copied out, since making a node can need the registry too
+    let types: Vec<NodeType> = crate::registry::registry().types().copied().collect();
+    if search.is_empty() {
+        for category in crate::registry::registry().categories() {
+            ui.menu_button(category, |ui| {
+                for ty in types.iter().filter(|ty| ty.category == category) {
+                    add_button(ui, ty);
+                }
+            });
+        }
+    } else {
+        let needle = search.to_lowercase();
+        let matches: Vec<&NodeType> = types
+            .iter()
+            .filter(|ty| {
+                [ty.name, ty.id, ty.category, ty.description]
+                    .iter()
+                    .any(|s| s.to_lowercase().contains(&needle))
+            })
+            .collect();
+        if matches.is_empty() {
+            ui.weak("nothing matches");
+        }
+        for ty in matches {
+            add_button(ui, ty);
+        }
+    }
+    if let Some(ty) = chosen {
+        graph.add_node_with_layout(ty.make(), NodeLayout::at(pos));
+        search.clear();
+        ui.close_menu();
+    }
+
+    ui.separator();
+    ui.menu_button("Saved subgraphs", |ui| {
+        let library = crate::subgraph::SubgraphLibrary::default();
+        let names = library.list();
+        if names.is_empty() {
+            ui.weak("(none yet)");
+        }
+        for name in names {
+            if ui.button(&name).clicked() {
+                match library.load(&name) {
+                    Ok(subgraph) => {
+                        graph.add_node_with_layout(Box::new(subgraph), NodeLayout::at(pos));
+                    }
+                    Err(e) => eprintln!("couldn't load subgraph {name}: {e:#}"),
+                }
+                ui.close_menu();
+            }
+        }
+    });
+}
+
+/// Adds a node picked from the palette, wiring it to the selected socket if
+/// there is one: its first input that fits a selected output, or its first
+/// output that fits a selected input.
+fn add_from_palette(graph: &mut Patch, memory: &mut GraphMemory, ty: NodeType, pos: [f32; 2]) {
+    let node = graph.add_node_with_layout(ty.make(), NodeLayout::at(pos));
+
+    if let Some(Selection::Socket(other, dir, idx)) = memory.selection {
+        let descriptor = graph.node_descriptor(node);
+        let wire = match dir {
+            SocketDirection::Output => {
+                let src_ty = graph.node_descriptor(other).output_sockets[idx].ty;
+                let sockets = &descriptor.input_sockets;
+                let fits = sockets.iter().position(|s| src_ty.connects_to(s.ty));
+                fits.or((!sockets.is_empty()).then_some(0)).map(|i| ((other, idx), (node, i)))
+            }
+            SocketDirection::Input => {
+                let dst_ty = graph.node_descriptor(other).input_sockets[idx].ty;
+                let sockets = &descriptor.output_sockets;
+                let fits = sockets.iter().position(|s| s.ty.connects_to(dst_ty));
+                fits.or((!sockets.is_empty()).then_some(0)).map(|i| ((node, i), (other, idx)))
+            }
+        };
+        if let Some((src, dst)) = wire {
+            memory.connection_error = crate::node::connect_with_conversion(graph, src, dst).err();
+        }
+    }
+    memory.selection = Some(Selection::Node(node));
+}
+
+fn presets_menu(ui: &mut egui::Ui, node: &mut dyn QuadioNode, name: &mut String, error: &mut Option<String>) {
+    let Some(ty) = crate::registry::type_of(node) else {
+        return;
+    };
+    let type_id = ty.id;
+    let library = PresetLibrary::default();
+
+    for preset in library.list(type_id) {
+        if ui.button(&preset).clicked() {
+            match library.load(type_id, &preset) {
+                Ok(params) => {
+                    crate::param::apply(node, &params);
+                    *error = None;
+                    ui.close_menu();
+                }
+                Err(e) => *error = Some(format!("{e:#}")),
+            }
+        }
+    }
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(name);
+        if ui.add_enabled(!name.is_empty(), egui::Button::new("Save")).clicked() {
+            match library.save(type_id, name, &crate::param::capture(node)) {
+                Ok(()) => *error = None,
+                Err(e) => *error = Some(format!("{e:#}")),
+            }
+        }
+    });
+    if let Some(e) = error {
+        ui.colored_label(ui.visuals().error_fg_color, e.as_str());
+    }
+}
+
+fn node_context_menu(
+    ui: &mut egui::Ui,
+    node: &mut dyn QuadioNode,
+    layout: &mut NodeLayout,
+    memory: &mut GraphMemory,
+    remove: &mut bool,
+) {
+    ui.menu_button("Presets", |ui| {
+        presets_menu(ui, node, &mut memory.preset_name, &mut memory.preset_error);
+    });
+    ui.separator();
+
+    ui.checkbox(&mut layout.collapsed, "Collapsed");
+
+    ui.horizontal(|ui| {
+        ui.label("Title");
+        let mut title = layout.title.clone().unwrap_or_default();
+        if ui.text_edit_singleline(&mut title).changed() {
+            layout.title = Some(title).filter(|title| !title.is_empty());
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Color");
+        let mut color = layout.color.unwrap_or_else(|| {
+            let fill = ui.style().visuals.window_fill();
+            [fill.r(), fill.g(), fill.b()]
+        });
+        if ui.color_edit_button_srgb(&mut color).changed() {
+            layout.color = Some(color);
+        }
+        if layout.color.is_some() && ui.button("Reset").clicked() {
+            layout.color = None;
+        }
+    });
+
+    ui.separator();
+    if ui.button("Remove").clicked() {
+        *remove = true;
+        ui.close_menu();
+    }
 }
 
-type NodeConstructors = [(&'static str, &'static dyn Fn() -> Box<dyn QuadioNode>)];
-
-#[allow(clippy::box_default)]
-fn node_constructors() -> &'static NodeConstructors {
-    &[
-        ("Phasor", &|| {
-            Box::new(crate::node::PhasorNode::default()) as _
-        }),
-        ("Passthru", &|| {
-            Box::new(crate::node::PassthruNode::default()) as _
-        }),
-        ("Sum", &|| Box::new(crate::node::SumNode::default()) as _),
-        ("Product", &|| {
-            Box::new(crate::node::ProductNode::default()) as _
-        }),
-        ("Linear", &|| {
-            Box::new(crate::node::LinearNode::default()) as _
-        }),
-        ("Quadrant", &|| {
-            Box::new(crate::node::QuadrantNode::default()) as _
-        }),
-        ("Phase Scale", &|| {
-            Box::new(crate::node::PhaseScaleNode::default()) as _
-        }),
-        ("Re-Im Split", &|| {
-            Box::new(crate::node::ReImSplitNode::default()) as _
-        }),
-        ("Mag-Ang Switch", &|| {
-            Box::new(crate::node::MagAngSwitchNode::default()) as _
-        }),
-        ("Quantize", &|| {
-            Box::new(crate::node::QuantizeNode::default()) as _
-        }),
-        ("Slo-Mo", &|| {
-            Box::new(crate::node::SlomoNode::default()) as _
-        }),
-
-        ("Scope", &|| {
-            Box::new(crate::node::ScopeNode::default()) as _
-        }),
-
-
-        ("Output", &|| {
-            Box::new(crate::node::OutputNode::default()) as _
-        }),
-    ]
+/// Whether `pos` is within a few pixels of `curve`.
+fn near_curve(curve: &egui::epaint::CubicBezierShape, pos: egui::Pos2) -> bool {
+    curve.flatten(Some(1.0)).windows(2).any(|segment| {
+        let (a, b) = (segment[0], segment[1]);
+        let t = ((pos - a).dot(b - a) / (b - a).length_sq().max(f32::EPSILON)).clamp(0.0, 1.0);
+        pos.distance(a + t * (b - a)) < 6.0
+    })
 }
 
 pub fn graph_ui<I>(
     ui: &mut egui::Ui,
     id_source: I,
-    graph: &mut NodeGraph<Box<dyn QuadioNode>>,
+    graph: &mut Patch,
 ) -> egui::Response
 where
     I: std::hash::Hash,
@@ -95,43 +325,132 @@ where
             mem.data.get_temp_mut_or_default::<Arc<Mutex<GraphMemory>>>(ui.id()).clone()
         });
         let mut memory = memory.lock().unwrap();
+        memory.prune(graph);
 
-        ui.menu_button("Add", |ui| {
-            for (name, ctor) in node_constructors() {
-                if ui.button(*name).clicked() {
-                    graph.add_node(ctor());
-                    ui.close_menu();
-                }
+        let new_node_pos = memory.new_node_pos(graph);
+        ui.horizontal(|ui| {
+            ui.menu_button("Add", |ui| {
+                add_node_menu(ui, graph, new_node_pos, &mut memory.add_search);
+            });
+            if let Some(e) = &memory.connection_error {
+                ui.colored_label(ui.visuals().error_fg_color, e.to_string());
             }
         });
 
         let bound_rect = egui::Rect::from_min_size(ui.next_widget_position(), ui.available_size());
+        let origin = bound_rect.min;
+
+        // right-clicking empty space adds a node right there
+        let background_response = ui.interact(bound_rect, ui.id().with("background"), egui::Sense::click());
+        if background_response.secondary_clicked() {
+            memory.add_pos = background_response.interact_pointer_pos().map(|pos| pos - origin);
+        }
+        background_response.context_menu(|ui| {
+            let pos = memory.add_pos.unwrap_or_default();
+            add_node_menu(ui, graph, [pos.x, pos.y], &mut memory.add_search);
+        });
+
+        // Tab or Space over the graph (while not typing into anything) opens the palette at the pointer
+        let pointer = ui.input(|i| i.pointer.hover_pos()).filter(|&pos| bound_rect.contains(pos));
+        let typing = ui.memory(|mem| mem.focus().is_some());
+        let summoned = ui.input(|i| i.key_pressed(egui::Key::Tab) || i.key_pressed(egui::Key::Space));
+        if let Some(pointer) = pointer.filter(|_| summoned && !typing && !memory.palette.is_open()) {
+            memory.palette.open(pointer - origin);
+        }
+        if let Some((ty, pos)) = memory.palette.show(ui.ctx(), origin) {
+            add_from_palette(graph, &mut memory, ty, [pos.x, pos.y]);
+        }
+
+        // unconnected inputs and what they read; edited in place, written back below
+        let mut input_values: HashMap<(NodeKey, usize), QuadioSample> = graph
+            .nodes()
+            .flat_map(|(node, _)| {
+                (0..graph.node_descriptor(node).input_sockets.len()).map(move |i| (node, i))
+            })
+            .filter(|&(node, i)| graph.src_for_dest(node, i).is_none())
+            .map(|(node, i)| ((node, i), graph.input_value(node, i)))
+            .collect();
+
+        // exposed parameters, with their depths edited in place like the above
+        let mut modulations: HashMap<NodeKey, Vec<ParamModulation>> = graph
+            .nodes()
+            .map(|(node, _)| (node, graph.modulations(node).to_vec()))
+            .filter(|(_, mods)| !mods.is_empty())
+            .collect();
+
+        // nodes that put out NaNs and such lately
+        let faults: HashMap<NodeKey, Fault> = graph
+            .nodes()
+            .filter_map(|(node, _)| Some((node, graph.faults().recent(node)?)))
+            .collect();
+
+        let badges: HashMap<NodeKey, String> = graph
+            .nodes()
+            .filter_map(|(node, _)| Some((node, crate::profile::badge(graph.profile(), node)?)))
+            .collect();
 
         let mut pending_connections = vec![];
-        for (node_key, node, descriptor) in graph.nodes_mut() {
+        let mut pending_removals = vec![];
+        let mut pending_exposure_toggles = vec![];
+        let mut param_edits = vec![];
+        for (node_key, node, descriptor, layout) in graph.nodes_with_layout_mut() {
             let node_is_selected = memory.is_node_selected(node_key);
+            let fault = faults.get(&node_key).copied();
+            let mut node_modulations = modulations.get_mut(&node_key);
+            let num_own_inputs = descriptor.input_sockets.len()
+                - node_modulations.as_ref().map_or(0, |mods| mods.len());
 
-            let area_response = egui::Area::new(
-                ui.id().with(node_key)
-            )
-            .drag_bounds(bound_rect)
-            .show(ui.ctx(), |ui| {
-                let node_frame = if !node_is_selected {
-                    egui::Frame {
-                        shadow: egui::epaint::Shadow::NONE,
-                        ..egui::Frame::window(ui.style())
-                    }
+            let layout_pos = origin + egui::Vec2::from(layout.pos);
+            let mut area = egui::Area::new(ui.id().with(node_key)).drag_bounds(bound_rect);
+            if memory.node_positions.get(&node_key) != Some(&layout_pos) {
+                area = area.current_pos(layout_pos);
+            }
+            let area_response = area.show(ui.ctx(), |ui| {
+                let fill = if node_is_selected {
+                    ui.style().visuals.selection.bg_fill
+                } else if let Some([r, g, b]) = layout.color {
+                    egui::Color32::from_rgb(r, g, b)
                 } else {
-                    egui::Frame {
-                        fill: ui.style().visuals.selection.bg_fill,
-                        shadow: egui::epaint::Shadow::NONE,
-                        ..egui::Frame::window(ui.style())
-                    }
+                    ui.style().visuals.window_fill()
                 };
+                let mut node_frame = egui::Frame {
+                    fill,
+                    shadow: egui::epaint::Shadow::NONE,
+                    ..egui::Frame::window(ui.style())
+                };
+                if fault.is_some() {
+                    node_frame.stroke = egui::Stroke::new(2.0, ui.visuals().error_fg_color);
+                }
 
                 node_frame.show(ui, |ui| {
                     ui.set_min_width(96.0);
-                    node.show_ui(ui);
+                    ui.horizontal(|ui| {
+                        let toggle = if layout.collapsed { "+" } else { "-" };
+                        if ui.small_button(toggle).clicked() {
+                            layout.collapsed = !layout.collapsed;
+                        }
+                        if let Some(title) = &layout.title {
+                            ui.strong(title);
+                        }
+                        if let Some(badge) = badges.get(&node_key) {
+                            ui.weak(egui::RichText::new(badge).small().monospace());
+                        }
+                    });
+                    if let Some(fault) = fault {
+                        ui.colored_label(ui.visuals().error_fg_color, format!("{fault}, {}", fault.remedy()));
+                    }
+                    if !layout.collapsed {
+                        node.show_ui(ui);
+                        let exposed = |name: &str| {
+                            node_modulations.as_ref().is_some_and(|mods| mods.iter().any(|m| m.param == name))
+                        };
+                        let response = crate::param::params_ui(ui, &**node, exposed);
+                        if let Some(idx) = response.toggle_exposed {
+                            let name = node.param_descriptors()[idx].name.to_string();
+                            pending_exposure_toggles.push((node_key, name));
+                        }
+                        param_edits.extend(response.edits.into_iter().map(|(idx, value)| (node_key, idx, value)));
+                    }
 
                     ui.shrink_width_to_current();
 
@@ -150,17 +469,30 @@ where
                                         memory.socket_positions.insert(
                                             (node_key, SocketDirection::Input, i),
                                             socket_pos);
-                                        let r = ui.button(format!("> {in_label}"));
-                                        if r.clicked() {
-                                            if let Some(Selection::Socket(other_node, SocketDirection::Output, other_node_sock_idx)) = memory.selection {
-                                                pending_connections.push(ConnectionEvent::Connect((other_node, other_node_sock_idx), (node_key, i)));
-                                                memory.selection = None;
-                                            } else {
-                                                memory.selection = Some(Selection::Socket(node_key, SocketDirection::Input, i));
+                                        ui.horizontal(|ui| {
+                                            let text = egui::RichText::new(format!("> {in_label}"))
+                                                .color(socket_color(in_desc.ty));
+                                            let r = ui.button(text);
+                                            if r.clicked() {
+                                                if let Some(Selection::Socket(other_node, SocketDirection::Output, other_node_sock_idx)) = memory.selection {
+                                                    pending_connections.push(ConnectionEvent::Connect((other_node, other_node_sock_idx), (node_key, i)));
+                                                    memory.selection = None;
+                                                } else {
+                                                    memory.selection = Some(Selection::Socket(node_key, SocketDirection::Input, i));
+                                                }
+                                            } else if r.clicked_by(egui::PointerButton::Secondary) {
+                                                pending_connections.push(ConnectionEvent::Disconnect(node_key, SocketDirection::Input, i));
                                             }
-                                        } else if r.clicked_by(egui::PointerButton::Secondary) {
-                                            pending_connections.push(ConnectionEvent::Disconnect(node_key, SocketDirection::Input, i));
-                                        }
+
+                                            if let Some(mods) = node_modulations.as_deref_mut().filter(|_| i >= num_own_inputs) {
+                                                ui.add(egui::DragValue::new(&mut mods[i - num_own_inputs].depth)
+                                                    .speed(0.01)
+                                                    .prefix("depth "));
+                                            } else if let Some(value) = input_values.get_mut(&(node_key, i)) {
+                                                // only there if nothing's wired in
+                                                edit_input_value(ui, in_desc.ty, value);
+                                            }
+                                        });
                                     } else {
                                         ui.label("");
                                     }
@@ -177,7 +509,9 @@ where
                                                 socket_pos); // should be offset to be on the frame...
 
                                             let out_label = &out_desc.label;
-                                            let r = ui.button(format!("{out_label} >"));
+                                            let text = egui::RichText::new(format!("{out_label} >"))
+                                                .color(socket_color(out_desc.ty));
+                                            let r = ui.button(text);
                                             if r.clicked() {
                                                 if let Some(Selection::Socket(other_node, SocketDirection::Input, other_node_sock_idx)) = memory.selection {
                                                     pending_connections.push(ConnectionEvent::Connect((node_key, i), (other_node, other_node_sock_idx)));
@@ -201,52 +535,167 @@ where
                 });
             }).response;
 
+            // the response's rect is from before this frame's drag
+            let pos = area_response.rect.min + area_response.drag_delta();
+            let rel_pos = pos - origin;
+            layout.pos = [rel_pos.x, rel_pos.y];
+            memory.node_positions.insert(node_key, pos);
+            memory.node_sizes.insert(node_key, area_response.rect.size());
+
             if memory.selection == Some(Selection::Node(node_key)) && area_response.clicked_elsewhere() {
                 memory.selection = None;
             } else if area_response.clicked() {
                 memory.selection = Some(Selection::Node(node_key));
             }
+
+            let mut remove = false;
+            area_response.context_menu(|ui| node_context_menu(ui, &mut **node, layout, &mut memory, &mut remove));
+            if remove {
+                pending_removals.push(node_key);
+            }
         }
 
+        for ((node_key, i), value) in input_values {
+            graph.set_input_value(node_key, i, value);
+        }
+        for (node_key, mods) in modulations {
+            for m in mods {
+                graph.set_modulation_depth(node_key, &m.param, m.depth);
+            }
+        }
+        for (node_key, idx, value) in param_edits {
+            crate::node::edit_param(graph, node_key, idx, value);
+        }
+        for (node_key, param) in pending_exposure_toggles {
+            if graph.modulations(node_key).iter().any(|m| m.param == param) {
+                graph.unexpose_param(node_key, &param);
+            } else {
+                graph.expose_param(node_key, &param);
+            }
+        }
+        for node_key in pending_removals {
+            graph.remove_node(node_key);
+        }
+        graph.refresh_descriptors();
+        memory.prune(graph);
+
         for ev in pending_connections {
             match ev {
-                ConnectionEvent::Connect(src, dst) => { graph.connect(src, dst); },
+                ConnectionEvent::Connect(src, dst) => {
+                    memory.connection_error = crate::node::connect_with_conversion(graph, src, dst).err();
+                },
                 ConnectionEvent::Disconnect(node, dir, idx) => { graph.disconnect(node, dir, idx); },
             }
         }
 
-        for (src, dst) in graph.wires() {
-            let Some(&src_pos) = memory.socket_positions.get(&(src.0, SocketDirection::Input, src.1)) else {
+        // a wire under the pointer (and not under a node) gets probed
+        let probe_pos = ui.input(|i| i.pointer.hover_pos())
+            .filter(|&pos| bound_rect.contains(pos) && !ui.ctx().is_pointer_over_area());
+        let mut probed = None;
+
+        for (dst, src) in graph.wires() {
+            let Some(&dst_pos) = memory.socket_positions.get(&(dst.0, SocketDirection::Input, dst.1)) else {
                 continue;
             };
-            let Some(&dst_pos) = memory.socket_positions.get(&(dst.0, SocketDirection::Output, dst.1)) else {
+            let Some(&src_pos) = memory.socket_positions.get(&(src.0, SocketDirection::Output, src.1)) else {
                 continue;
             };
-            let stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(0x00, 0xD3, 0xED));
+            let ty = graph.node_descriptor(src.0).output_sockets[src.1].ty;
+            let mut stroke = egui::Stroke::new(2.0, socket_color(ty));
 
-            let horiz = src_pos.x < dst_pos.x;
+            let horiz = dst_pos.x < src_pos.x;
             let points = if horiz {
                 [
-                    src_pos,
-                    egui::Pos2::new(src_pos.x, dst_pos.y),
+                    dst_pos,
                     egui::Pos2::new(dst_pos.x, src_pos.y),
-                    dst_pos
+                    egui::Pos2::new(src_pos.x, dst_pos.y),
+                    src_pos
                 ]
             } else {
                 [
-                    src_pos,
-                    egui::Pos2::new(dst_pos.x, src_pos.y),
+                    dst_pos,
                     egui::Pos2::new(src_pos.x, dst_pos.y),
-                    dst_pos
+                    egui::Pos2::new(dst_pos.x, src_pos.y),
+                    src_pos
                 ]
             };
 
-            ui.painter().add(egui::Shape::CubicBezier(egui::epaint::CubicBezierShape {
+            let mut curve = egui::epaint::CubicBezierShape {
                 points,
                 stroke,
                 fill: egui::Color32::TRANSPARENT,
                 closed: false
-            }));
+            };
+            if probed.is_none() && probe_pos.is_some_and(|pos| near_curve(&curve, pos)) {
+                probed = Some(src);
+                stroke.width = 4.0;
+                curve.stroke = stroke;
+            }
+
+            ui.painter().add(egui::Shape::CubicBezier(curve));
+        }
+
+        graph.probe_mut().set_target(probed);
+        if probed.is_some() {
+            egui::show_tooltip_at_pointer(ui.ctx(), ui.id().with("probe"), |ui| {
+                crate::probe::probe_ui(ui, graph.probe());
+            });
+            // keep it moving while it's up
+            ui.ctx().request_repaint();
         }
     }).response
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::LinearNode;
+
+    fn frame(ctx: &egui::Context, graph: &mut Patch, events: Vec<egui::Event>) {
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(1024.0, 768.0))),
+            events,
+            ..Default::default()
+        };
+        let _ = ctx.run(input, |ctx| {
+            egui::CentralPanel::default().frame(egui::Frame::none()).show(ctx, |ui| {
+                graph_ui(ui, "test_graph", graph);
+            });
+        });
+    }
+
+    fn button(pos: egui::Pos2, pressed: bool) -> egui::Event {
+        egui::Event::PointerButton {
+            pos,
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn dragging_a_node_moves_it() {
+        let mut graph = Patch::default();
+        let node = graph.add_node_with_layout(Box::new(LinearNode::default()), NodeLayout::at([100.0, 100.0]));
+        let ctx = egui::Context::default();
+        // a couple of frames for the areas to learn their sizes
+        frame(&ctx, &mut graph, vec![]);
+        frame(&ctx, &mut graph, vec![]);
+        assert_eq!(graph.layout(node).pos, [100.0, 100.0]);
+
+        // grab the frame's left edge, clear of any widget
+        let grab = egui::pos2(102.0, 160.0);
+        let moved = grab + egui::vec2(50.0, 30.0);
+        frame(&ctx, &mut graph, vec![egui::Event::PointerMoved(grab), button(grab, true)]);
+        frame(&ctx, &mut graph, vec![egui::Event::PointerMoved(moved)]);
+        frame(&ctx, &mut graph, vec![button(moved, false)]);
+        frame(&ctx, &mut graph, vec![]);
+        assert_eq!(graph.layout(node).pos, [150.0, 130.0]);
+
+        // and a layout changed from outside still moves it
+        graph.layout_mut(node).pos = [10.0, 10.0];
+        frame(&ctx, &mut graph, vec![]);
+        frame(&ctx, &mut graph, vec![]);
+        assert_eq!(graph.layout(node).pos, [10.0, 10.0]);
+    }
+}
@@ -0,0 +1,311 @@
+//! `quadio inspect patch.ron`: prints what's in a patch and checks it over,
+//! without starting the GUI or audio, so patches can be validated in CI.
+
+use std::any::TypeId;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::graph::{NodeKey, SocketDirection};
+use crate::patch::Patch;
+use crate::node::node_name;
+use crate::param::{ParamDescriptor, ParamKind, ParamValue};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+pub struct Report {
+    pub text: String,
+    pub problems: Vec<Problem>,
+}
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.problems.iter().any(|p| p.severity == Severity::Error)
+    }
+}
+
+fn format_value(descriptor: &ParamDescriptor, value: ParamValue) -> String {
+    match (value, &descriptor.kind) {
+        (ParamValue::Enum(i), ParamKind::Enum { variants }) => {
+            variants.get(i).map_or_else(|| i.to_string(), |v| v.to_string())
+        }
+        (ParamValue::Real(x), _) => x.to_string(),
+        (ParamValue::Complex(c), _) => format!("{}{:+}i", c.re, c.im),
+        (ParamValue::Int(i), _) => i.to_string(),
+        (ParamValue::Enum(i), _) => i.to_string(),
+        (ParamValue::Bool(b), _) => b.to_string(),
+    }
+}
+
+// (src, dst)
+type Wire = ((NodeKey, usize), (NodeKey, usize));
+
+enum DfsState {
+    Visiting,
+    Visited,
+}
+
+/// The order the engine runs nodes in (everything upstream of `node`, then
+/// `node`), plus any wires it finds closing a loop.
+fn execution_order(
+    graph: &Patch,
+    node: NodeKey,
+    states: &mut std::collections::HashMap<NodeKey, DfsState>,
+    order: &mut Vec<NodeKey>,
+    cycles: &mut Vec<Wire>,
+) {
+    states.insert(node, DfsState::Visiting);
+    for i in 0..graph.node_descriptor(node).input_sockets.len() {
+        let Some(src) = graph.src_for_dest(node, i) else {
+            continue;
+        };
+        match states.get(&src.0) {
+            None => execution_order(graph, src.0, states, order, cycles),
+            Some(DfsState::Visiting) => cycles.push((src, (node, i))),
+            Some(DfsState::Visited) => (),
+        }
+    }
+    states.insert(node, DfsState::Visited);
+    order.push(node);
+}
+
+pub fn inspect(graph: &Patch) -> Report {
+    let mut text = String::new();
+    let mut problems = vec![];
+    let mut problem = |severity, message: String| problems.push(Problem { severity, message });
+
+    // everything below looks nodes and sockets up by wire, which isn't safe
+    // until we know the wires and side tables add up
+    let errors = graph.validate();
+    if !errors.is_empty() {
+        writeln!(text, "(not listing anything; the patch is broken)").unwrap();
+        for e in errors {
+            problem(Severity::Error, e.to_string());
+        }
+        return Report { text, problems };
+    }
+
+    let socket_label = |(node, idx): (NodeKey, usize), direction| {
+        let descriptor = graph.node_descriptor(node);
+        let sockets = match direction {
+            SocketDirection::Input => &descriptor.input_sockets,
+            SocketDirection::Output => &descriptor.output_sockets,
+        };
+        let label = sockets.get(idx).map_or_else(|| format!("#{idx}"), |s| s.label.clone());
+        format!("{}.{label}", node_name(graph, node))
+    };
+
+    writeln!(text, "nodes:").unwrap();
+    for (key, node) in graph.nodes() {
+        let type_id = crate::registry::type_of(&**node).map_or("?", |ty| ty.id);
+        let params: Vec<String> = node
+            .param_descriptors()
+            .iter()
+            .enumerate()
+            .map(|(idx, descriptor)| format!("{} = {}", descriptor.name, format_value(descriptor, node.param(idx))))
+            .collect();
+        writeln!(text, "  {} ({type_id}) {}", node_name(graph, key), params.join(", ")).unwrap();
+    }
+
+    writeln!(text, "wires:").unwrap();
+    let mut wires: Vec<_> = graph
+        .wires()
+        .map(|(dst, src)| (socket_label(src, SocketDirection::Output), socket_label(dst, SocketDirection::Input)))
+        .collect();
+    wires.sort();
+    for (src, dst) in wires {
+        writeln!(text, "  {src} -> {dst}").unwrap();
+    }
+
+    for (key, _) in graph.nodes() {
+        let descriptor = graph.node_descriptor(key);
+        for idx in 0..descriptor.input_sockets.len() {
+            if graph.src_for_dest(key, idx).is_none() {
+                let value = graph.input_value(key, idx);
+                problem(
+                    Severity::Warning,
+                    format!(
+                        "{} is unconnected (reads {}{:+}i)",
+                        socket_label((key, idx), SocketDirection::Input),
+                        value.re,
+                        value.im
+                    ),
+                );
+            }
+        }
+    }
+
+    // same as the engine: everything is pulled from the first output node
+    let output = graph
+        .nodes()
+        .find(|(_, node)| (***node).type_id() == TypeId::of::<crate::node::OutputNode>())
+        .map(|(key, _)| key);
+    writeln!(text, "execution order:").unwrap();
+    match output {
+        Some(output) => {
+            let mut states = Default::default();
+            let mut order = vec![];
+            let mut cycles = vec![];
+            execution_order(graph, output, &mut states, &mut order, &mut cycles);
+            for (i, node) in order.iter().enumerate() {
+                writeln!(text, "  {}. {}", i + 1, node_name(graph, *node)).unwrap();
+            }
+
+            // allowed cycles still run (with a block's delay), so they're only worth a warning
+            let cycle_severity = if graph.allow_cycles() { Severity::Warning } else { Severity::Error };
+            for (src, dst) in cycles {
+                problem(
+                    cycle_severity,
+                    format!(
+                        "{} -> {} closes a cycle",
+                        socket_label(src, SocketDirection::Output),
+                        socket_label(dst, SocketDirection::Input)
+                    ),
+                );
+            }
+            for (key, _) in graph.nodes() {
+                if !order.contains(&key) {
+                    problem(
+                        Severity::Warning,
+                        format!("{} is unreachable from the output, so it never runs", node_name(graph, key)),
+                    );
+                }
+            }
+        }
+        None => {
+            writeln!(text, "  (nothing; there's no output node)").unwrap();
+            problem(Severity::Warning, "no output node, so the patch is silent".to_owned());
+        }
+    }
+
+    // errors first
+    problems.sort_by_key(|p| std::cmp::Reverse(p.severity));
+    Report { text, problems }
+}
+
+/// Renders `seconds` of `graph` as fast as it'll go, without a device, and
+/// reports how long each node took. What it renders is thrown away.
+pub fn profile(graph: &mut Patch, seconds: f32) -> String {
+    const SAMPLE_RATE: f32 = 48000.0;
+    const BLOCK_SIZE: usize = 1024;
+
+    let mut engine = crate::audio::AudioEngine::new(SAMPLE_RATE, 1);
+    let mut block = vec![0.0; BLOCK_SIZE];
+    let blocks = (seconds * SAMPLE_RATE / BLOCK_SIZE as f32).ceil() as usize;
+    for _ in 0..blocks {
+        engine.run_graph(graph, &mut block);
+    }
+    crate::profile::report(graph)
+}
+
+/// Runs `quadio inspect <patch> [--strict] [--profile]`, returning the exit
+/// code: 1 if the patch couldn't be loaded or has errors (or, with
+/// `--strict`, warnings). `--profile` also renders a few seconds of it and
+/// reports which nodes are expensive.
+pub fn main(args: &[String]) -> i32 {
+    let strict = args.iter().any(|a| a == "--strict");
+    let profiling = args.iter().any(|a| a == "--profile");
+    let paths: Vec<_> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let [path] = paths.as_slice() else {
+        eprintln!("usage: quadio inspect <patch.ron> [--strict] [--profile]");
+        return 2;
+    };
+
+    let (mut graph, repairs) = match crate::patch::load_checked(Path::new(path)) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            return 1;
+        }
+    };
+
+    let mut report = inspect(&graph);
+    // what was wrong with it as saved; what's listed is the repaired graph
+    for (i, e) in repairs.into_iter().enumerate() {
+        report.problems.insert(
+            i,
+            Problem {
+                severity: Severity::Error,
+                message: format!("{e} (repaired)"),
+            },
+        );
+    }
+    print!("{}", report.text);
+    if !report.problems.is_empty() {
+        println!("problems:");
+    }
+    for p in &report.problems {
+        let severity = match p.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        println!("  {severity}: {}", p.message);
+    }
+
+    if profiling {
+        println!("profile:");
+        print!("{}", profile(&mut graph, 5.0));
+    }
+
+    let failed = report.has_errors() || (strict && !report.problems.is_empty());
+    failed as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a wire into an input the output node doesn't have, and one from a
+    // node that isn't there
+    const CORRUPTED: &str = r#"(
+        nodes: [
+            (value: None, version: 0),
+            (value: Some((type: "phasor", params: {})), version: 1),
+            (value: Some((type: "output", params: {})), version: 1),
+        ],
+        wires_by_destination: {
+            ((idx: 2, version: 1), 4): ((idx: 1, version: 1), 0),
+            ((idx: 1, version: 1), 0): ((idx: 9, version: 1), 0),
+        },
+    )"#;
+
+    fn write_patch(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("quadio-inspect-{}-{name}.ron", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn broken_graphs_are_reported_not_panicked_on() {
+        let graph: Patch = ron::from_str(CORRUPTED).unwrap();
+        let report = inspect(&graph);
+        assert!(report.has_errors());
+    }
+
+    #[test]
+    fn corrupted_patches_fail() {
+        let path = write_patch("corrupted", CORRUPTED);
+        let code = main(&[path.display().to_string()]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn sound_patches_pass() {
+        let mut graph = Patch::default();
+        let phasor = graph.add_node(crate::registry::lookup("phasor").unwrap().make());
+        let output = graph.add_node(crate::registry::lookup("output").unwrap().make());
+        graph.connect((phasor, 0), (output, 0)).unwrap();
+        let path = write_patch("sound", &crate::patch::to_string(&graph).unwrap());
+        let code = main(&[path.display().to_string()]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(code, 0);
+    }
+}
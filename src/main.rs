@@ -1,24 +1,54 @@
 pub mod audio;
+pub mod automation;
+pub mod device;
+pub mod dsl;
 pub mod graph;
 pub mod graph_ui;
+pub mod inspect;
 pub mod math;
+pub mod monitor;
 pub mod node;
+pub mod osc;
+pub mod palette;
 pub mod param;
+pub mod patch;
+pub mod preset;
+pub mod probe;
+pub mod profile;
+pub mod registry;
 pub mod sample;
+pub mod script;
+pub mod snapshot;
+pub mod subgraph;
 
 use std::sync::{Arc, Mutex};
 
 pub struct QuadioApp {
-    graph: Arc<Mutex<graph::NodeGraph<Box<dyn node::QuadioNode>>>>,
+    graph: Arc<Mutex<patch::Patch>>,
+    monitor: Arc<monitor::OutputMonitor>,
+    audio: device::AudioPanel,
     ui_disabled: bool,
-    peeper: egui_extras::RetainedImage
+    peeper: egui_extras::RetainedImage,
+
+    patch_path: String,
+    // result of the last save/load
+    patch_status: Option<Result<String, String>>,
+    dsl: dsl::DslEditor,
+    // kept so the server stops when the app does
+    _osc: Option<osc::OscServer>,
+    // the subgraph open in the graph editor, as a path of subgraph nodes
+    // from the main graph down; empty for the main graph itself
+    subgraph_path: Vec<graph::NodeKey>,
 }
 
 impl QuadioApp {
     /// Called once before the first frame.
     pub fn new(
         _cc: &eframe::CreationContext<'_>,
-        graph: Arc<Mutex<graph::NodeGraph<Box<dyn node::QuadioNode>>>>,
+        graph: Arc<Mutex<patch::Patch>>,
+        monitor: Arc<monitor::OutputMonitor>,
+        audio: device::AudioPanel,
+        osc: Option<osc::OscServer>,
     ) -> Self {
         let peeper = egui_extras::RetainedImage::from_image_bytes(
             "peeper", include_bytes!("peeper.png"))
@@ -26,8 +56,55 @@ impl QuadioApp {
         
         QuadioApp {
             graph,
+            monitor,
+            audio,
             ui_disabled: false,
-            peeper
+            peeper,
+
+            patch_path: "patch.ron".to_owned(),
+            patch_status: None,
+            dsl: Default::default(),
+            _osc: osc,
+            subgraph_path: vec![],
+        }
+    }
+}
+
+impl QuadioApp {
+    fn patch_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Patch");
+            ui.text_edit_singleline(&mut self.patch_path);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                let graph = self.graph.lock().unwrap();
+                self.patch_status = Some(
+                    patch::save(&graph, &self.patch_path)
+                        .map(|_| format!("saved {}", self.patch_path))
+                        .map_err(|e| format!("{e:#}")),
+                );
+            }
+            if ui.button("Load").clicked() {
+                self.patch_status = Some(match patch::load(&self.patch_path) {
+                    Ok(loaded) => {
+                        self.graph.lock().unwrap().replace(loaded);
+                        // its keys could happen to match ones in the old graph
+                        self.subgraph_path.clear();
+                        Ok(format!("loaded {}", self.patch_path))
+                    }
+                    Err(e) => Err(format!("{e:#}")),
+                });
+            }
+        });
+        match &self.patch_status {
+            Some(Ok(msg)) => {
+                ui.label(msg);
+            }
+            Some(Err(msg)) => {
+                ui.colored_label(ui.visuals().error_fg_color, msg);
+            }
+            None => (),
         }
     }
 }
@@ -35,13 +112,47 @@ impl QuadioApp {
 impl eframe::App for QuadioApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
+        monitor::log_faults(&mut self.graph.lock().unwrap());
 
         egui::SidePanel::right("side_panel").show(ctx, |ui| {
             ui.heading("quadio");
             egui::warn_if_debug_build(ui);
-            ui.monospace("1ch 44100Hz, not oversampling");
+            ui.monospace(format!("{}, not oversampling", self.audio.summary()));
+            let underruns = self.audio.underruns();
+            if underruns > 0 {
+                ui.colored_label(ui.visuals().error_fg_color, format!("{underruns} underruns"));
+            }
             ui.monospace("1 voices");
             ui.checkbox(&mut self.ui_disabled, "Disable graph UI");
+
+            ui.separator();
+            monitor::output_ui(ui, &self.monitor);
+            egui::CollapsingHeader::new("Audio device").show(ui, |ui| {
+                self.audio.ui(ui);
+            });
+
+            ui.separator();
+            egui::CollapsingHeader::new("Profile").show(ui, |ui| {
+                profile::profile_ui(ui, &self.graph.lock().unwrap());
+            });
+
+            ui.separator();
+            self.patch_ui(ui);
+
+            ui.separator();
+            preset::snapshots_ui(ui, &mut self.graph.lock().unwrap());
+
+            ui.separator();
+            if self.dsl.ui(ui, &mut self.graph.lock().unwrap()) {
+                // whatever was open may be gone, or be something else now
+                self.subgraph_path.clear();
+            }
+        });
+
+        egui::TopBottomPanel::bottom("timeline").resizable(true).show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                automation::timeline_ui(ui, &mut self.graph.lock().unwrap());
+            });
         });
 
         let mut frame = egui::Frame {
@@ -60,7 +171,17 @@ impl eframe::App for QuadioApp {
                 self.peeper.show_scaled(&mut ui, 0.33);
             }
 
-            graph_ui::graph_ui(ui, "main_graph", &mut self.graph.lock().unwrap());
+            let mut main_graph = self.graph.lock().unwrap();
+            if !self.subgraph_path.is_empty() {
+                subgraph::path_ui(ui, &main_graph, &mut self.subgraph_path);
+            }
+            let graph = subgraph::resolve_path(&mut main_graph, &mut self.subgraph_path);
+            graph_ui::graph_ui(ui, ("main_graph", &self.subgraph_path), graph);
+            if let Some(opened) = subgraph::take_open_request(graph) {
+                self.subgraph_path.push(opened);
+            }
+            // edits inside a subgraph can change its sockets out here
+            main_graph.refresh_descriptors();
         });
     }
 }
@@ -70,13 +191,31 @@ fn main() -> eframe::Result<()> {
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
-    let graph: Arc<Mutex<graph::NodeGraph<Box<dyn node::QuadioNode>>>> = Default::default();
-    let _audio_stream = audio::audio_main(graph.clone()).unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("inspect") {
+        std::process::exit(inspect::main(&args[1..]));
+    }
+
+    let graph: Arc<Mutex<patch::Patch>> = Default::default();
+    let monitor: Arc<monitor::OutputMonitor> = Default::default();
+    let audio = device::AudioPanel::start(graph.clone(), monitor.clone());
+
+    let osc_addr = std::env::var("QUADIO_OSC_ADDR").unwrap_or_else(|_| "127.0.0.1:9000".to_owned());
+    let osc = match osc::OscServer::spawn(graph.clone(), osc_addr.as_str()) {
+        Ok(server) => {
+            println!("OSC: listening on {}", server.addr());
+            Some(server)
+        }
+        Err(e) => {
+            eprintln!("OSC: {e:#}");
+            None
+        }
+    };
 
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "quadio",
         native_options,
-        Box::new(|cc| Box::new(QuadioApp::new(cc, graph))),
+        Box::new(|cc| Box::new(QuadioApp::new(cc, graph, monitor, audio, osc))),
     )
 }
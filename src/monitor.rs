@@ -0,0 +1,285 @@
+//! Keeping an eye on what reaches the speakers: output meters, a safety
+//! limiter, and catching nodes that put out NaNs, infinities or denormals.
+//!
+//! The engine writes meter readings into an `OutputMonitor` and reads the
+//! limiter settings from it; the UI does the opposite. It's all atomics, so
+//! neither waits on the other. Bad node output is zeroed where it's made, and
+//! noted in the graph's `Faults` so the editor can show which node it was.
+//! The audio thread never logs; the UI picks up new faults with `log_faults`.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::graph::NodeKey;
+use crate::patch::Patch;
+use crate::sample::QuadioSample;
+
+/// How long a node stays marked after its last bad block.
+const FAULT_HOLD: Duration = Duration::from_secs(2);
+/// How many new faults can wait for the UI to log them. Past this they're
+/// still marked on the node, just not logged, so the audio thread never
+/// has to grow the queue.
+const MAX_UNLOGGED: usize = 64;
+/// How fast the peak meter falls back, in dB per second.
+const PEAK_FALL: f32 = 20.0;
+/// How fast the limiter lets go, in seconds (one-pole time constant).
+const LIMITER_RELEASE: f32 = 0.1;
+
+#[derive(Default)]
+struct AtomicF32(AtomicU32);
+impl AtomicF32 {
+    fn new(value: f32) -> AtomicF32 {
+        AtomicF32(AtomicU32::new(value.to_bits()))
+    }
+    fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+/// Shared between the engine and the UI.
+pub struct OutputMonitor {
+    peak: AtomicF32,
+    rms: AtomicF32,
+    // how much the limiter is turning things down, as a gain
+    limiter_gain: AtomicF32,
+    // samples that still went past full scale, and got clipped
+    clipped: AtomicU64,
+
+    limiter_enabled: AtomicBool,
+    // in dBFS
+    ceiling: AtomicF32,
+}
+impl Default for OutputMonitor {
+    fn default() -> Self {
+        OutputMonitor {
+            peak: AtomicF32::new(0.0),
+            rms: AtomicF32::new(0.0),
+            limiter_gain: AtomicF32::new(1.0),
+            clipped: AtomicU64::new(0),
+            limiter_enabled: AtomicBool::new(true),
+            ceiling: AtomicF32::new(-1.0),
+        }
+    }
+}
+
+/// Brick-wall limiting with instant attack, followed by a hard clip at full
+/// scale for anything that gets through anyway (i.e. with the limiter off).
+pub struct Limiter {
+    gain: f32,
+    peak: f32,
+}
+impl Default for Limiter {
+    fn default() -> Self {
+        Limiter { gain: 1.0, peak: 0.0 }
+    }
+}
+impl Limiter {
+    /// Makes `block` safe to send to the device, and updates `monitor`'s
+    /// meters with what's left.
+    pub fn process(&mut self, block: &mut [f32], sample_rate: f32, monitor: &OutputMonitor) {
+        let enabled = monitor.limiter_enabled.load(Ordering::Relaxed);
+        let ceiling = 10f32.powf(monitor.ceiling.load() / 20.0);
+        let release = 1.0 - (-1.0 / (LIMITER_RELEASE * sample_rate)).exp();
+
+        let mut clipped = 0;
+        let mut block_peak = 0.0f32;
+        let mut sum_sq = 0.0;
+        for x in block.iter_mut() {
+            if !x.is_finite() {
+                // nodes' outputs get checked, but better safe than deaf
+                *x = 0.0;
+            }
+            if enabled {
+                self.gain += (1.0 - self.gain) * release;
+                if x.abs() * self.gain > ceiling {
+                    self.gain = ceiling / x.abs();
+                }
+                *x *= self.gain;
+            } else {
+                self.gain = 1.0;
+            }
+            if x.abs() > 1.0 {
+                clipped += 1;
+                *x = x.clamp(-1.0, 1.0);
+            }
+            block_peak = block_peak.max(x.abs());
+            sum_sq += *x * *x;
+        }
+
+        let block_seconds = block.len() as f32 / sample_rate;
+        self.peak = block_peak.max(self.peak * 10f32.powf(-PEAK_FALL * block_seconds / 20.0));
+        monitor.peak.store(self.peak);
+        monitor.rms.store((sum_sq / block.len().max(1) as f32).sqrt());
+        monitor.limiter_gain.store(self.gain);
+        monitor.clipped.fetch_add(clipped, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    NaN,
+    Infinity,
+    Denormal,
+}
+impl Fault {
+    /// What `sanitize` did about it.
+    pub fn remedy(self) -> &'static str {
+        match self {
+            Fault::NaN | Fault::Infinity => "muted",
+            Fault::Denormal => "flushed to zero",
+        }
+    }
+}
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::NaN => write!(f, "NaN"),
+            Fault::Infinity => write!(f, "infinity"),
+            Fault::Denormal => write!(f, "denormals"),
+        }
+    }
+}
+
+/// Checks a node's output block. NaNs and infinities mute the whole block,
+/// since whatever's around them is likely nonsense too; denormals are just
+/// flushed to zero.
+pub fn sanitize(block: &mut [QuadioSample]) -> Option<Fault> {
+    let parts = |x: &QuadioSample| [x.re, x.im];
+    if let Some(bad) = block.iter().flat_map(parts).find(|x| !x.is_finite()) {
+        block.fill(QuadioSample::from(0.0));
+        return Some(if bad.is_nan() { Fault::NaN } else { Fault::Infinity });
+    }
+
+    let mut fault = None;
+    for x in block.iter_mut() {
+        for part in [&mut x.re, &mut x.im] {
+            if part.is_subnormal() {
+                *part = 0.0;
+                fault = Some(Fault::Denormal);
+            }
+        }
+    }
+    fault
+}
+
+/// Which nodes have put out something bad lately.
+pub struct Faults {
+    last: slotmap::SecondaryMap<NodeKey, (Fault, Instant)>,
+    // news for the log: node, output socket, fault
+    unlogged: Vec<(NodeKey, usize, Fault)>,
+}
+impl Default for Faults {
+    fn default() -> Self {
+        Faults {
+            last: Default::default(),
+            unlogged: Vec::with_capacity(MAX_UNLOGGED),
+        }
+    }
+}
+impl Faults {
+    /// Notes that `node` just did `fault` on `output`. If it's news (the
+    /// node hadn't done anything wrong lately), it's queued for the log.
+    pub fn report(&mut self, node: NodeKey, output: usize, fault: Fault) {
+        let now = Instant::now();
+        let news = self.last.get(node).is_none_or(|&(last_fault, at)| {
+            last_fault != fault || now.duration_since(at) > FAULT_HOLD
+        });
+        self.last.insert(node, (fault, now));
+        if news && self.unlogged.len() < MAX_UNLOGGED {
+            self.unlogged.push((node, output, fault));
+        }
+    }
+
+    /// Faults that haven't been logged yet, oldest first. Drains in place so
+    /// the queue keeps its capacity.
+    pub fn take_unlogged(&mut self) -> std::vec::Drain<'_, (NodeKey, usize, Fault)> {
+        self.unlogged.drain(..)
+    }
+
+    /// What `node` did wrong, if it did lately.
+    pub fn recent(&self, node: NodeKey) -> Option<Fault> {
+        let &(fault, at) = self.last.get(node)?;
+        (at.elapsed() <= FAULT_HOLD).then_some(fault)
+    }
+
+    pub fn remove_node(&mut self, node: NodeKey) {
+        self.last.remove(node);
+        self.unlogged.retain(|&(n, _, _)| n != node);
+    }
+}
+
+/// Logs whatever faults the audio thread has queued since last time. Meant
+/// for the UI thread, so nothing gets printed while the engine holds the
+/// graph.
+pub fn log_faults(graph: &mut Patch) {
+    let unlogged: Vec<_> = graph.faults_mut().take_unlogged().collect();
+    for (node, output, fault) in unlogged {
+        let name = crate::node::node_name(graph, node);
+        eprintln!("{name} put out {fault} on output {output}, {}", fault.remedy());
+    }
+}
+
+fn decibels(x: f32) -> f32 {
+    20.0 * x.max(1e-6).log10()
+}
+
+fn meter(ui: &mut egui::Ui, label: &str, value: f32) {
+    // -60..0 dB
+    let fill = ((decibels(value) + 60.0) / 60.0).clamp(0.0, 1.0);
+    let color = if value >= 1.0 {
+        ui.visuals().error_fg_color
+    } else {
+        egui::Color32::from_rgb(90, 190, 110)
+    };
+    ui.horizontal(|ui| {
+        ui.monospace(label);
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(120.0, 10.0), egui::Sense::hover());
+        ui.painter().rect_filled(rect, 1.0, ui.visuals().extreme_bg_color);
+        let mut filled = rect;
+        filled.set_width(rect.width() * fill);
+        ui.painter().rect_filled(filled, 1.0, color);
+        ui.monospace(format!("{:6.1} dB", decibels(value)));
+    });
+}
+
+/// Meters and limiter settings, for the side panel.
+pub fn output_ui(ui: &mut egui::Ui, monitor: &OutputMonitor) {
+    ui.label("Output");
+    meter(ui, "peak", monitor.peak.load());
+    meter(ui, "rms ", monitor.rms.load());
+
+    ui.horizontal(|ui| {
+        let mut enabled = monitor.limiter_enabled.load(Ordering::Relaxed);
+        if ui.checkbox(&mut enabled, "Limiter").changed() {
+            monitor.limiter_enabled.store(enabled, Ordering::Relaxed);
+        }
+        let mut ceiling = monitor.ceiling.load();
+        let r = ui.add_enabled(
+            enabled,
+            egui::DragValue::new(&mut ceiling)
+                .clamp_range(-24.0..=0.0)
+                .speed(0.1)
+                .suffix(" dB"),
+        );
+        if r.changed() {
+            monitor.ceiling.store(ceiling);
+        }
+        let reduction = decibels(monitor.limiter_gain.load());
+        if enabled && reduction < -0.1 {
+            ui.monospace(format!("{reduction:.1} dB"));
+        }
+    });
+
+    let clipped = monitor.clipped.load(Ordering::Relaxed);
+    if clipped > 0 {
+        ui.horizontal(|ui| {
+            ui.colored_label(ui.visuals().error_fg_color, format!("clipped {clipped} samples"));
+            if ui.small_button("reset").clicked() {
+                monitor.clipped.store(0, Ordering::Relaxed);
+            }
+        });
+    }
+}
@@ -1,22 +1,41 @@
-use core::f32::consts::TAU;
-use core::ops::RangeInclusive;
+use core::f32::consts::{PI, TAU};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 use num_complex::Complex32;
 use serde::{Deserialize, Serialize};
 
 use crate::audio::AudioContext;
-use crate::graph::{self, NodeDescriptor, SocketDescriptor};
+use crate::graph::{self, GraphError, NodeDescriptor, NodeKey, NodeLayout, SocketDescriptor, SocketType};
+use crate::patch::Patch;
+use crate::param::{ParamDescriptor, ParamSet, ParamValue, Params, Smoothed, Smoothing};
+use crate::params;
 
 use crate::sample::QuadioSample;
+use crate::snapshot::{self, SnapshotReader, SnapshotWriter};
 
-pub trait QuadioNode: graph::Node + Send + Sync + std::any::Any {
+pub trait QuadioNode: graph::Node + Params + Send + Sync + std::any::Any {
+    /// Draws anything the node wants besides its parameters' editors
+    /// (which `graph_ui` takes care of).
     fn show_ui(&mut self, ui: &mut egui::Ui);
 
     fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]);
+
+    /// Anything besides parameters that a saved patch needs to recreate the
+    /// node (a script's source, say). Restored before the parameters are.
+    fn save_state(&self) -> Option<String> {
+        None
+    }
+    fn load_state(&mut self, _state: &str) {}
 }
 impl graph::Node for Box<dyn QuadioNode> {
     fn get_descriptor(&self) -> graph::NodeDescriptor {
         (**self).get_descriptor()
     }
+
+    fn take_descriptor_changed(&mut self) -> bool {
+        (**self).take_descriptor_changed()
+    }
 }
 
 #[derive(Default)]
@@ -26,13 +45,16 @@ impl graph::Node for PassthruNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(PassthruNode {});
 impl QuadioNode for PassthruNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.label("PASSTHRU");
@@ -45,41 +67,135 @@ impl QuadioNode for PassthruNode {
     }
 }
 
+/// What a node is called outside the editor (OSC addresses, `quadio
+/// inspect`, the DSL): its title if it has one, or its type and slot number
+/// otherwise, lowercased and with anything that isn't alphanumeric turned
+/// into `_`. Names that would start with a digit (or be empty) get a `_` in
+/// front, so they can't be mistaken for numbers.
+pub fn node_name(graph: &Patch, node: NodeKey) -> String {
+    let name = match &graph.layout(node).title {
+        Some(title) => title.clone(),
+        None => {
+            let type_id = crate::registry::type_of(&**graph.get_node(node)).map_or("node", |ty| ty.id);
+            // the low half of the key is the slot index
+            let slot = slotmap::Key::data(&node).as_ffi() as u32;
+            format!("{type_id}{slot}")
+        }
+    };
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+/// What a node looks like in a saved patch: its type's id, its parameters by
+/// name, and whatever `save_state` gives. Anything else about it (oscillator
+/// phases and so on) isn't saved.
+#[derive(Deserialize, Serialize)]
+struct SavedNode {
+    #[serde(rename = "type")]
+    type_id: String,
+    params: ParamSet,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    state: Option<String>,
+}
+
 impl<'de> Deserialize<'de> for Box<dyn QuadioNode> {
-    fn deserialize<D>(_deserializer: D) -> Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        unimplemented!()
+        let saved = SavedNode::deserialize(deserializer)?;
+        let Some(ty) = crate::registry::lookup(&saved.type_id) else {
+            return Err(serde::de::Error::custom(format!("unknown node type {:?}", saved.type_id)));
+        };
+
+        let mut node = ty.make();
+        if let Some(state) = &saved.state {
+            node.load_state(state);
+        }
+        // parameters that no longer exist are just dropped
+        crate::param::apply(&mut *node, &saved.params);
+        // nodes with dynamic sockets may have just changed them; the graph
+        // picks that up when it adds the node, or in `repair` once a whole
+        // patch is loaded
+        node.take_descriptor_changed();
+
+        Ok(node)
     }
 }
 impl Serialize for Box<dyn QuadioNode> {
-    fn serialize<S>(&self, _serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        unimplemented!()
+        let Some(ty) = crate::registry::type_of(&**self) else {
+            return Err(serde::ser::Error::custom("node type isn't registered"));
+        };
+
+        SavedNode {
+            type_id: ty.id.to_owned(),
+            params: crate::param::capture(&**self),
+            state: self.save_state(),
+        }
+        .serialize(serializer)
     }
 }
 
-#[derive(Default)]
-pub struct SumNode;
+pub struct SumNode {
+    num_inputs: usize,
+    descriptor_changed: bool,
+}
+impl Default for SumNode {
+    fn default() -> Self {
+        SumNode { num_inputs: 2, descriptor_changed: false }
+    }
+}
 impl graph::Node for SumNode {
     fn get_descriptor(&self) -> NodeDescriptor {
         NodeDescriptor {
-            input_sockets: vec![
-                SocketDescriptor {
-                    label: "A".to_owned(),
-                },
-                SocketDescriptor {
-                    label: "B".to_owned(),
-                },
-            ],
+            input_sockets: (0..self.num_inputs)
+                .map(|i| SocketDescriptor {
+                    label: char::from(b'A' + i as u8).to_string(),
+                    ..Default::default()
+                })
+                .collect(),
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
+
+    fn take_descriptor_changed(&mut self) -> bool {
+        std::mem::take(&mut self.descriptor_changed)
+    }
+}
+// by hand, since changing the input count has to change the sockets too
+impl Params for SumNode {
+    fn param_descriptors(&self) -> &[ParamDescriptor] {
+        static DESCRIPTORS: std::sync::OnceLock<[ParamDescriptor; 1]> = std::sync::OnceLock::new();
+        DESCRIPTORS.get_or_init(|| [ParamDescriptor::int("inputs", 1..=16)])
+    }
+    fn param(&self, _idx: usize) -> ParamValue {
+        ParamValue::Int(self.num_inputs as i32)
+    }
+    fn set_param(&mut self, _idx: usize, value: ParamValue) {
+        let num_inputs = crate::param::ParamType::from_value(value);
+        if num_inputs != self.num_inputs {
+            self.num_inputs = num_inputs;
+            self.descriptor_changed = true;
+        }
+    }
+    fn modulate_param(&mut self, _idx: usize, _values: &[ParamValue]) -> bool {
+        // sockets can't come and go mid-block, so there's nothing to modulate
+        true
+    }
 }
 impl QuadioNode for SumNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
@@ -87,12 +203,11 @@ impl QuadioNode for SumNode {
     }
 
     fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
-        for ((a, b), out) in inputs[0]
-            .iter()
-            .zip(inputs[1].iter())
-            .zip(outputs[0].iter_mut())
-        {
-            *out = *a + *b;
+        outputs[0].fill(QuadioSample::from(0.0));
+        for input in inputs {
+            for (x, out) in input.iter().zip(outputs[0].iter_mut()) {
+                *out += *x;
+            }
         }
     }
 }
@@ -103,19 +218,26 @@ impl graph::Node for ProductNode {
     fn get_descriptor(&self) -> NodeDescriptor {
         NodeDescriptor {
             input_sockets: vec![
+                // so an unconnected input doesn't just mute everything
                 SocketDescriptor {
                     label: "A".to_owned(),
+                    default: QuadioSample::from(1.0),
+                    ..Default::default()
                 },
                 SocketDescriptor {
                     label: "B".to_owned(),
+                    default: QuadioSample::from(1.0),
+                    ..Default::default()
                 },
             ],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(ProductNode {});
 impl QuadioNode for ProductNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.label("PRODUCT");
@@ -132,85 +254,79 @@ impl QuadioNode for ProductNode {
     }
 }
 
-fn edit_complex(
-    ui: &mut egui::Ui,
-    label: impl Into<egui::RichText>,
-    c: &mut Complex32,
-    r_range: RangeInclusive<f32>,
-    logarithmic: bool,
-) {
-    let (mut r, mut theta) = c.to_polar();
-    ui.horizontal(|ui| {
-        ui.monospace(label);
-        ui.add(egui::Slider::new(&mut r, r_range).logarithmic(logarithmic));
-        ui.drag_angle(&mut theta);
-    });
-    *c = Complex32::from_polar(r, theta);
-}
-
 #[derive(Default)]
 pub struct LinearNode {
-    m: Complex32,
-    b: Complex32,
+    m: Smoothed<Complex32>,
+    b: Smoothed<Complex32>,
 }
 impl graph::Node for LinearNode {
     fn get_descriptor(&self) -> NodeDescriptor {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(LinearNode {
+    m => ParamDescriptor::complex("m", 0.0..=1024.)
+        .logarithmic()
+        .smoothed(Smoothing::linear(0.02).polar()),
+    b => ParamDescriptor::complex("b", 0.0..=1024.)
+        .logarithmic()
+        .smoothed(Smoothing::linear(0.02)),
+});
 impl QuadioNode for LinearNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.label("LINEAR");
-
-        edit_complex(ui, "m", &mut self.m, 0.0..=1024., true);
-        edit_complex(ui, "b", &mut self.b, 0.0..=1024., true);
     }
 
-    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
         for (x, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
-            *out = (self.m * *x) + self.b
+            let m = self.m.next(ctx.sample_rate);
+            let b = self.b.next(ctx.sample_rate);
+            *out = (m * *x) + b
         }
     }
 }
 
 #[derive(Default)]
 pub struct PhaseScaleNode {
-    scale: f32,
+    scale: Smoothed<f32>,
 }
 impl graph::Node for PhaseScaleNode {
     fn get_descriptor(&self) -> NodeDescriptor {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(PhaseScaleNode {
+    scale => ParamDescriptor::real("scale", 0.0..=32.0)
+        .logarithmic()
+        .smoothed(Smoothing::linear(0.02)),
+});
 impl QuadioNode for PhaseScaleNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.label("PHASE-SCALE");
-
-        ui.add(
-            egui::Slider::new(&mut self.scale, 0.0..=32.0)
-                .logarithmic(true)
-                .text("Scale"),
-        );
     }
 
-    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
         for (inp, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
             let (r, theta) = inp.to_polar();
-            *out = Complex32::from_polar(r, theta * self.scale);
+            *out = Complex32::from_polar(r, theta * self.scale.next(ctx.sample_rate));
         }
     }
 }
@@ -222,13 +338,16 @@ impl graph::Node for MagAngSwitchNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(MagAngSwitchNode {});
 impl QuadioNode for MagAngSwitchNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.label("MAG-ANG SWITCH");
@@ -250,16 +369,21 @@ impl graph::Node for ReImSplitNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Re".to_owned(),
+                ty: SocketType::Real,
+                ..Default::default()
             },
             SocketDescriptor {
                 label: "Im".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(ReImSplitNode {});
 impl QuadioNode for ReImSplitNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.monospace("RE-IM SPLIT");
@@ -275,11 +399,11 @@ impl QuadioNode for ReImSplitNode {
 }
 
 pub struct QuadrantNode {
-    scales: [Complex32; 4],
+    scales: [Smoothed<Complex32>; 4],
 }
 impl Default for QuadrantNode {
     fn default() -> Self {
-     QuadrantNode { scales: [Complex32::new(1.0, 0.0); 4] }
+     QuadrantNode { scales: std::array::from_fn(|_| Smoothed::new(Complex32::new(1.0, 0.0))) }
     }
 }
 impl graph::Node for QuadrantNode {
@@ -287,30 +411,35 @@ impl graph::Node for QuadrantNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(QuadrantNode {
+    scales[0] => ParamDescriptor::complex("I", 0.0..=32.0).smoothed(Smoothing::linear(0.02).polar()),
+    scales[1] => ParamDescriptor::complex("II", 0.0..=32.0).smoothed(Smoothing::linear(0.02).polar()),
+    scales[2] => ParamDescriptor::complex("III", 0.0..=32.0).smoothed(Smoothing::linear(0.02).polar()),
+    scales[3] => ParamDescriptor::complex("IV", 0.0..=32.0).smoothed(Smoothing::linear(0.02).polar()),
+});
 impl QuadioNode for QuadrantNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.monospace("QUADRANT");
-
-        edit_complex(ui, "I", &mut self.scales[0], 0.0..=32.0, false);
-        edit_complex(ui, "II", &mut self.scales[1], 0.0..=32.0, false);
-        edit_complex(ui, "II", &mut self.scales[2], 0.0..=32.0, false);
-        edit_complex(ui, "IV", &mut self.scales[3], 0.0..=32.0, false);
     }
 
-    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
         for (inp, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
+            // advance all of them, not just the one we use, so they stay in step
+            let scales = self.scales.each_mut().map(|s| s.next(ctx.sample_rate));
             let scale = match (inp.re.is_sign_positive(), inp.im.is_sign_positive()) {
-                (true, true) => self.scales[0],
-                (false, true) => self.scales[1],
-                (false, false) => self.scales[2],
-                (true, false) => self.scales[3],
+                (true, true) => scales[0],
+                (false, true) => scales[1],
+                (false, false) => scales[2],
+                (true, false) => scales[3],
             };
             *out = inp * scale;
         }
@@ -318,12 +447,12 @@ impl QuadioNode for QuadrantNode {
 }
 
 pub struct QuantizeNode {
-    amp_factor: f32,
-    phase_factor: f32
+    amp_bits: f32,
+    phase_bits: f32
 }
 impl Default for QuantizeNode {
     fn default() -> Self {
-     QuantizeNode { amp_factor: 2.0f32.powf(16.0), phase_factor: 2.0f32.powf(16.0) }
+     QuantizeNode { amp_bits: 16.0, phase_bits: 16.0 }
     }
 }
 impl graph::Node for QuantizeNode {
@@ -331,44 +460,44 @@ impl graph::Node for QuantizeNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(QuantizeNode {
+    amp_bits => ParamDescriptor::real("amp_bits", 1.0..=16.0).unit(" bits"),
+    phase_bits => ParamDescriptor::real("phase_bits", 1.0..=16.0).unit(" bits"),
+});
 impl QuadioNode for QuantizeNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.monospace("QUANTIZE");
-
-        let mut amp_bits = self.amp_factor.log2();
-        ui.add(egui::DragValue::new(&mut amp_bits).clamp_range(1.0..=16.0));
-        self.amp_factor = 2.0f32.powf(amp_bits);
-        
-        let mut phase_bits = self.phase_factor.log2();
-        ui.add(egui::DragValue::new(&mut phase_bits).clamp_range(1.0..=16.0));
-        self.phase_factor = 2.0f32.powf(phase_bits);
     }
 
     fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        let amp_factor = 2.0f32.powf(self.amp_bits);
+        let phase_factor = 2.0f32.powf(self.phase_bits);
         for (inp, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
             let (amp, phase) = inp.to_polar();
-            let amp = (amp * self.amp_factor).round() / self.amp_factor;
-            let phase = (phase * self.phase_factor / TAU).round() / self.phase_factor;
+            let amp = (amp * amp_factor).round() / amp_factor;
+            let phase = (phase * phase_factor / TAU).round() / phase_factor;
             *out = Complex32::from_polar(amp, phase * TAU);
         }
     }
 }
 
 pub struct SlomoNode {
-    alpha: f32,
+    alpha: Smoothed<f32>,
     
     last_phase: f32,
 }
 impl Default for SlomoNode {
     fn default() -> Self {
-     SlomoNode { alpha: 0.9, last_phase: 0.0 }
+     SlomoNode { alpha: Smoothed::new(0.9), last_phase: 0.0 }
     }
 }
 impl graph::Node for SlomoNode {
@@ -376,112 +505,336 @@ impl graph::Node for SlomoNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(SlomoNode {
+    alpha => ParamDescriptor::real("alpha", 0.0..=1.0)
+        .logarithmic()
+        .smoothed(Smoothing::one_pole(0.01)),
+});
 impl QuadioNode for SlomoNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.monospace("SLO-MO");
-
-        ui.add(egui::Slider::new(&mut self.alpha, 0.0..=1.0).logarithmic(true));
     }
 
-    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
         for (inp, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
             let (amp, phase) = inp.to_polar();
-            let phase = crate::math::lerp(self.last_phase, phase, 1.0 - self.alpha);
+            let alpha = self.alpha.next(ctx.sample_rate);
+            let phase = crate::math::lerp(self.last_phase, phase, 1.0 - alpha);
             self.last_phase = phase;
             *out = Complex32::from_polar(amp, phase);
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TriggerSource {
+    Re,
+    #[default]
+    Im,
+    Magnitude,
+    Phase,
+}
+crate::enum_param!(TriggerSource { Re => "Re", Im => "Im", Magnitude => "Magnitude", Phase => "Phase" });
+impl TriggerSource {
+    fn value(self, x: QuadioSample) -> f32 {
+        match self {
+            TriggerSource::Re => x.re,
+            TriggerSource::Im => x.im,
+            TriggerSource::Magnitude => x.norm(),
+            // in half turns, so it fits in -1..1 like the others
+            TriggerSource::Phase => x.arg() / PI,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Slope {
+    #[default]
+    Rising,
+    Falling,
+    Either,
+}
+crate::enum_param!(Slope { Rising => "Rising", Falling => "Falling", Either => "Either" });
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// capture after capture, no trigger needed
+    Free,
+    /// wait for a trigger, but not forever
+    Auto,
+    /// wait for a trigger
+    #[default]
+    Normal,
+    /// wait for a trigger, capture once, then stop until re-armed
+    Single,
+}
+crate::enum_param!(TriggerMode { Free => "Free", Auto => "Auto", Normal => "Normal", Single => "Single" });
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScopeView {
+    #[default]
+    IQ,
+    Time,
+}
+crate::enum_param!(ScopeView { IQ => "I/Q", Time => "Time" });
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScopeState {
+    Waiting,
+    Capturing,
+    // captured, but the UI hasn't taken the last capture yet
+    Done,
+    // after a single-shot capture
+    Stopped,
+}
+
+#[derive(Default)]
+struct ScopeCapture {
+    samples: Vec<QuadioSample>,
+    sample_rate: f32,
+    // false if Auto gave up waiting
+    triggered: bool,
+}
+
+/// How long Auto waits for a trigger before capturing anyway, in seconds.
+const AUTO_TIMEOUT: f32 = 0.1;
+
 pub struct ScopeNode {
     length: usize,
+    view: ScopeView,
+    mode: TriggerMode,
+    source: TriggerSource,
+    slope: Slope,
+    level: f32,
     depth: bool,
+    show_re: bool,
+    show_im: bool,
+    show_magnitude: bool,
+    show_phase: bool,
 
-    triggered: bool,
-    free_run: bool,
-    last_sample: QuadioSample,
+    state: ScopeState,
+    waited: usize,
+    last_value: f32,
 
-    full_waveform: Vec<QuadioSample>,
-    capture_buf: Vec<QuadioSample>
+    // the audio thread fills captures in; the UI draws them from the reader
+    // this scope left under `id` in `SCOPE_READERS`
+    id: u64,
+    writer: SnapshotWriter<ScopeCapture>,
 }
+
+/// The other end of every scope's captures. A scope puts its reader in here
+/// as it's made and keeps only the writer, so captures reach the UI without
+/// going through the node (and the graph lock with it). The audio thread
+/// never touches this.
+static SCOPE_READERS: Mutex<BTreeMap<u64, SnapshotReader<ScopeCapture>>> = Mutex::new(BTreeMap::new());
+static NEXT_SCOPE_ID: AtomicU64 = AtomicU64::new(0);
+
 impl Default for ScopeNode {
     fn default() -> Self {
+        let (writer, reader) = snapshot::snapshot();
+        let id = NEXT_SCOPE_ID.fetch_add(1, Ordering::Relaxed);
+        SCOPE_READERS.lock().unwrap().insert(id, reader);
         ScopeNode { length: 4096,
-            triggered: false,
-            free_run: false,
+            view: ScopeView::default(),
+            mode: TriggerMode::default(),
+            source: TriggerSource::default(),
+            slope: Slope::default(),
+            level: 0.0,
             depth: false,
-            full_waveform: Vec::new(),
-            last_sample: 0.0.into(),
-            capture_buf: Vec::new()
+            show_re: true,
+            show_im: true,
+            show_magnitude: false,
+            show_phase: false,
+            state: ScopeState::Waiting,
+            waited: 0,
+            last_value: 0.0,
+            id,
+            writer,
+        }
+    }
+}
+impl Drop for ScopeNode {
+    fn drop(&mut self) {
+        SCOPE_READERS.lock().unwrap().remove(&self.id);
+    }
+}
+impl ScopeNode {
+    fn crossed(&self, value: f32) -> bool {
+        let rising = self.last_value < self.level && value >= self.level;
+        let falling = self.last_value > self.level && value <= self.level;
+        match self.slope {
+            Slope::Rising => rising,
+            Slope::Falling => falling,
+            Slope::Either => rising || falling,
         }
     }
+
+    fn finish_capture(&mut self) {
+        self.state = if !self.writer.publish() {
+            ScopeState::Done
+        } else if self.mode == TriggerMode::Single {
+            ScopeState::Stopped
+        } else {
+            ScopeState::Waiting
+        };
+    }
 }
 impl graph::Node for ScopeNode {
     fn get_descriptor(&self) -> NodeDescriptor {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "In".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(ScopeNode {
+    length => ParamDescriptor::int("length", 1..=16384).unit(" smp"),
+    view => ParamDescriptor::enumeration::<ScopeView>("view"),
+    mode => ParamDescriptor::enumeration::<TriggerMode>("mode"),
+    source => ParamDescriptor::enumeration::<TriggerSource>("trigger on"),
+    slope => ParamDescriptor::enumeration::<Slope>("slope"),
+    level => ParamDescriptor::real("level", -1.0..=1.0),
+    depth => ParamDescriptor::bool("3d"),
+    show_re => ParamDescriptor::bool("re"),
+    show_im => ParamDescriptor::bool("im"),
+    show_magnitude => ParamDescriptor::bool("magnitude"),
+    show_phase => ParamDescriptor::bool("phase"),
+});
 impl QuadioNode for ScopeNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
-        ui.monospace("SCOPE");
         ui.horizontal(|ui| {
-            ui.add(egui::DragValue::new(&mut self.length).clamp_range(1..=16384));
-            ui.checkbox(&mut self.depth, "3D");
-            ui.checkbox(&mut self.free_run, "FREE");
+            ui.monospace("SCOPE");
+            if self.state == ScopeState::Stopped && ui.small_button("arm").clicked() {
+                self.state = ScopeState::Waiting;
+                self.waited = 0;
+            } else if self.state == ScopeState::Waiting && self.mode == TriggerMode::Single {
+                ui.weak("armed");
+            }
         });
 
+        let mut readers = SCOPE_READERS.lock().unwrap();
+        let Some(reader) = readers.get_mut(&self.id) else {
+            return;
+        };
+        let capture = reader.latest();
+        if self.mode == TriggerMode::Auto && !capture.samples.is_empty() && !capture.triggered {
+            ui.weak("no trigger");
+        }
 
+        match self.view {
+            ScopeView::IQ => {
+                let len = capture.samples.len();
+                let deepen = |i| {
+                    1.0 + if self.depth { 8.0 * (i as f64 / len as f64) } else { 0.0 }
+                };
+                let points: egui::plot::PlotPoints = capture.samples.iter()
+                    .enumerate()
+                    .map(|(i, sample)| (deepen(i), sample))
+                    .map(|(depth, sample)| [sample.re as f64 / depth, sample.im as f64 / depth])
+                    .collect();
 
-        let deepen = |i| {
-            1.0 + if self.depth { 8.0 * (i as f64 / self.full_waveform.len() as f64) } else { 0.0 }
-        };
-        let points: egui::plot::PlotPoints = self.full_waveform.iter()
-            .enumerate()
-            .map(|(i, sample)| (deepen(i), sample))
-            .map(|(depth, sample)| [sample.re as f64 / depth, sample.im as f64 / depth])
-            .collect();
-        
-        let line = egui::plot::Line::new(points);
-        egui::plot::Plot::new("plot").view_aspect(1.0)
-            .width(256.0)
-            .height(256.0)
-            .data_aspect(1.0)
-            .center_x_axis(true)
-            .center_y_axis(true).show(ui, |plot_ui| {
-                plot_ui.set_plot_bounds(egui::plot::PlotBounds::from_min_max([-1.0, -1.0], [1.0,  1.0]));
-                plot_ui.line(line);
-            });
+                let line = egui::plot::Line::new(points);
+                egui::plot::Plot::new("plot").view_aspect(1.0)
+                    .width(256.0)
+                    .height(256.0)
+                    .data_aspect(1.0)
+                    .center_x_axis(true)
+                    .center_y_axis(true).show(ui, |plot_ui| {
+                        plot_ui.set_plot_bounds(egui::plot::PlotBounds::from_min_max([-1.0, -1.0], [1.0,  1.0]));
+                        plot_ui.line(line);
+                    });
+            }
+            ScopeView::Time => {
+                // in milliseconds since the trigger
+                let ms_per_sample = 1000.0 / capture.sample_rate.max(1.0) as f64;
+                let trace = |f: fn(&QuadioSample) -> f32| -> egui::plot::PlotPoints {
+                    capture.samples.iter()
+                        .enumerate()
+                        .map(|(i, sample)| [i as f64 * ms_per_sample, f(sample) as f64])
+                        .collect()
+                };
+                let traces = [
+                    (self.show_re, "re", egui::Color32::from_rgb(230, 110, 90), trace(|x| x.re)),
+                    (self.show_im, "im", egui::Color32::from_rgb(90, 160, 230), trace(|x| x.im)),
+                    (self.show_magnitude, "magnitude", egui::Color32::from_gray(200), trace(|x| x.norm())),
+                    (self.show_phase, "phase", egui::Color32::from_rgb(220, 200, 80), trace(|x| TriggerSource::Phase.value(*x))),
+                ];
+                let end = (self.length as f64 * ms_per_sample).max(ms_per_sample);
+                let show_level = self.mode != TriggerMode::Free;
+
+                egui::plot::Plot::new("plot")
+                    .width(320.0)
+                    .height(160.0)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .legend(egui::plot::Legend::default())
+                    .show(ui, |plot_ui| {
+                        plot_ui.set_plot_bounds(egui::plot::PlotBounds::from_min_max([0.0, -1.1], [end, 1.1]));
+                        if show_level {
+                            plot_ui.hline(egui::plot::HLine::new(self.level)
+                                .color(egui::Color32::from_gray(90))
+                                .style(egui::plot::LineStyle::dashed_loose()));
+                        }
+                        for (shown, name, color, points) in traces {
+                            if shown {
+                                plot_ui.line(egui::plot::Line::new(points).name(name).color(color));
+                            }
+                        }
+                    });
+            }
+        }
     }
 
-    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
-        for inp in inputs[0] {
-            if (inp.im.is_sign_positive() && self.last_sample.im.is_sign_negative()) || self.free_run {
-                self.triggered = true;
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        if self.state == ScopeState::Done {
+            self.finish_capture();
+        }
+        if self.state == ScopeState::Stopped && self.mode != TriggerMode::Single {
+            self.state = ScopeState::Waiting;
+        }
+        let timeout = ((ctx.sample_rate * AUTO_TIMEOUT) as usize).max(self.length);
+
+        for &inp in inputs[0] {
+            let value = self.source.value(inp);
+            let crossed = self.crossed(value);
+            self.last_value = value;
+
+            if self.state == ScopeState::Waiting {
+                self.waited += 1;
+                let gave_up = self.mode == TriggerMode::Auto && self.waited > timeout;
+                if crossed || gave_up || self.mode == TriggerMode::Free {
+                    self.state = ScopeState::Capturing;
+                    self.waited = 0;
+                    let capture = self.writer.back();
+                    capture.samples.clear();
+                    capture.sample_rate = ctx.sample_rate;
+                    capture.triggered = crossed;
+                }
             }
-            self.last_sample = *inp;
-
-            if self.triggered {
-                self.capture_buf.push(*inp);
-                if self.capture_buf.len() >= self.length {
-                    std::mem::swap(&mut self.capture_buf, &mut self.full_waveform);
-                    self.capture_buf.clear();
-                    self.triggered = false;
+
+            if self.state == ScopeState::Capturing {
+                let capture = self.writer.back();
+                capture.samples.push(inp);
+                if capture.samples.len() >= self.length {
+                    self.finish_capture();
                 }
-            } 
+            }
         }
 
         outputs[0].copy_from_slice(inputs[0])
@@ -489,6 +842,237 @@ impl QuadioNode for ScopeNode {
 }
 
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FftSize {
+    S256,
+    S512,
+    #[default]
+    S1024,
+    S2048,
+    S4096,
+    S8192,
+}
+crate::enum_param!(FftSize { S256 => "256", S512 => "512", S1024 => "1024", S2048 => "2048", S4096 => "4096", S8192 => "8192" });
+impl FftSize {
+    fn len(self) -> usize {
+        256 << crate::param::EnumParam::index(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+}
+crate::enum_param!(Window { Rectangular => "Rectangular", Hann => "Hann", Hamming => "Hamming", Blackman => "Blackman" });
+impl Window {
+    fn coefficient(self, i: usize, n: usize) -> f32 {
+        let x = TAU * i as f32 / n as f32;
+        match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * x.cos(),
+            Window::Hamming => 0.54 - 0.46 * x.cos(),
+            Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+        }
+    }
+}
+
+// frequencies closer to DC than this all sit at the middle of a log axis
+const LOG_FREQ_FLOOR: f64 = 10.0;
+
+/// Where frequency `f` goes on the spectrum's x axis. The log axis is
+/// mirrored, so negative frequencies get their own decades left of DC.
+fn freq_to_x(f: f64, log: bool) -> f64 {
+    if log {
+        f.signum() * (f.abs() / LOG_FREQ_FLOOR).max(1.0).log10()
+    } else {
+        f
+    }
+}
+
+fn x_to_freq(x: f64, log: bool) -> f64 {
+    if log && x != 0.0 {
+        x.signum() * LOG_FREQ_FLOOR * 10f64.powf(x.abs())
+    } else {
+        x
+    }
+}
+
+/// Magnitude spectrum of the input, from -Nyquist to +Nyquist, since a
+/// complex signal's negative frequencies aren't just a mirror image of the
+/// positive ones. Audio passes through untouched.
+pub struct SpectrumNode {
+    size: FftSize,
+    window: Window,
+    decibels: bool,
+    peak_hold: bool,
+    log_freq: bool,
+
+    // keeps every size planned so far, so flipping between sizes doesn't
+    // plan again
+    planner: rustfft::FftPlanner<f32>,
+    fft: Option<std::sync::Arc<dyn rustfft::Fft<f32>>>,
+    capture_buf: Vec<QuadioSample>,
+    fft_buf: Vec<Complex32>,
+    scratch: Vec<Complex32>,
+    // magnitudes, lowest (most negative) frequency first
+    spectrum: Vec<f32>,
+    peaks: Vec<f32>,
+    sample_rate: f32,
+}
+impl Default for SpectrumNode {
+    fn default() -> Self {
+        SpectrumNode {
+            size: FftSize::default(),
+            window: Window::default(),
+            decibels: true,
+            peak_hold: false,
+            log_freq: false,
+            planner: rustfft::FftPlanner::new(),
+            fft: None,
+            capture_buf: Vec::new(),
+            fft_buf: Vec::new(),
+            scratch: Vec::new(),
+            spectrum: Vec::new(),
+            peaks: Vec::new(),
+            sample_rate: 48000.0,
+        }
+    }
+}
+impl SpectrumNode {
+    /// Plans the FFT if the size changed since the last plan. The UI calls
+    /// this every frame so a new size is normally planned there, not on the
+    /// audio thread.
+    fn plan(&mut self) {
+        let n = self.size.len();
+        if self.fft.as_ref().map(|fft| fft.len()) == Some(n) {
+            return;
+        }
+        let fft = self.planner.plan_fft_forward(n);
+        self.scratch = vec![Complex32::default(); fft.get_inplace_scratch_len()];
+        self.fft = Some(fft);
+        self.capture_buf.clear();
+        self.capture_buf.reserve(2 * n);
+        self.fft_buf.reserve(n);
+        self.peaks.clear();
+    }
+
+    fn analyze(&mut self, n: usize) {
+        let fft = self.fft.as_ref().unwrap();
+        // normalized so a full-scale sinusoid reads 1 (0 dB) whatever the window
+        let gain: f32 = (0..n).map(|i| self.window.coefficient(i, n)).sum();
+        self.fft_buf.clear();
+        self.fft_buf.extend(
+            self.capture_buf
+                .drain(..n)
+                .enumerate()
+                .map(|(i, x)| x * self.window.coefficient(i, n) / gain),
+        );
+        fft.process_with_scratch(&mut self.fft_buf, &mut self.scratch);
+
+        // bin 0 is DC and the top half is the negative frequencies, so rotate
+        // by half to have them run from -Nyquist up
+        self.spectrum.clear();
+        self.spectrum.extend((0..n).map(|i| self.fft_buf[(i + n / 2) % n].norm()));
+        if self.peak_hold && self.peaks.len() == n {
+            for (peak, &x) in self.peaks.iter_mut().zip(&self.spectrum) {
+                *peak = peak.max(x);
+            }
+        } else {
+            self.peaks.clone_from(&self.spectrum);
+        }
+    }
+}
+impl graph::Node for SpectrumNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(SpectrumNode {
+    size => ParamDescriptor::enumeration::<FftSize>("size"),
+    window => ParamDescriptor::enumeration::<Window>("window"),
+    decibels => ParamDescriptor::bool("dB"),
+    peak_hold => ParamDescriptor::bool("peak hold"),
+    log_freq => ParamDescriptor::bool("log freq"),
+});
+impl QuadioNode for SpectrumNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        self.plan();
+        ui.horizontal(|ui| {
+            ui.monospace("SPECTRUM");
+            if self.peak_hold && ui.small_button("reset peaks").clicked() {
+                self.peaks.clone_from(&self.spectrum);
+            }
+        });
+
+        let n = self.spectrum.len();
+        let log = self.log_freq;
+        let nyquist = freq_to_x(self.sample_rate as f64 / 2.0, log);
+        let scale = |x: f32| {
+            if self.decibels {
+                20.0 * (x.max(1e-6) as f64).log10()
+            } else {
+                x as f64
+            }
+        };
+        let line = |values: &[f32]| -> egui::plot::PlotPoints {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, &x)| {
+                    let freq = (i as f64 - (n / 2) as f64) * self.sample_rate as f64 / n as f64;
+                    [freq_to_x(freq, log), scale(x)]
+                })
+                .collect()
+        };
+        let spectrum = egui::plot::Line::new(line(&self.spectrum));
+        let peaks = egui::plot::Line::new(line(&self.peaks)).color(egui::Color32::from_gray(120));
+        let (y_min, y_max) = if self.decibels { (-120.0, 6.0) } else { (0.0, 1.1) };
+
+        egui::plot::Plot::new("spectrum")
+            .width(320.0)
+            .height(160.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .x_axis_formatter(move |x, _| format!("{:.0}", x_to_freq(x, log)))
+            .label_formatter(move |_, point| format!("{:.0} Hz\n{:.1}", x_to_freq(point.x, log), point.y))
+            .show(ui, |plot_ui| {
+                plot_ui.set_plot_bounds(egui::plot::PlotBounds::from_min_max([-nyquist, y_min], [nyquist, y_max]));
+                if self.peak_hold {
+                    plot_ui.line(peaks);
+                }
+                plot_ui.line(spectrum);
+            });
+    }
+
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        // only does anything if nothing has drawn the node since the size changed
+        self.plan();
+        let n = self.size.len();
+        self.sample_rate = ctx.sample_rate;
+
+        self.capture_buf.extend_from_slice(inputs[0]);
+        while self.capture_buf.len() >= n {
+            self.analyze(n);
+        }
+
+        outputs[0].copy_from_slice(inputs[0])
+    }
+}
+
 
 #[derive(Default)]
 pub struct OutputNode;
@@ -497,12 +1081,14 @@ impl graph::Node for OutputNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![],
         }
     }
 }
 
+params!(OutputNode {});
 impl QuadioNode for OutputNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.heading("Out");
@@ -514,8 +1100,8 @@ impl QuadioNode for OutputNode {
 }
 
 pub struct PhasorNode {
-    f_mul: f32,
-    f_div: f32,
+    f_mul: Smoothed<f32>,
+    f_div: Smoothed<f32>,
 
     mod_mag_scale: f32,
     mod_ang_scale: f32,
@@ -527,8 +1113,8 @@ pub struct PhasorNode {
 impl Default for PhasorNode {
     fn default() -> Self {
         PhasorNode {
-            f_mul: 1.0,
-            f_div: 1.0,
+            f_mul: Smoothed::new(1.0),
+            f_div: Smoothed::new(1.0),
             mod_mag_scale: 0.0,
             mod_ang_scale: 0.1,
             phase: 0.0,
@@ -542,28 +1128,29 @@ impl graph::Node for PhasorNode {
         NodeDescriptor {
             input_sockets: vec![SocketDescriptor {
                 label: "Mod".to_owned(),
+                ..Default::default()
             }],
             output_sockets: vec![SocketDescriptor {
                 label: "Out".to_owned(),
+                ..Default::default()
             }],
         }
     }
 }
+params!(PhasorNode {
+    f_mul => ParamDescriptor::real("f_mul", -256.0..=256.0).smoothed(Smoothing::linear(0.02)),
+    f_div => ParamDescriptor::real("f_div", 0.001..=256.0)
+        .logarithmic()
+        .smoothed(Smoothing::linear(0.02)),
+    mod_mag_scale => ParamDescriptor::real("mod_mag", 0.0..=8.0).logarithmic(),
+    mod_ang_scale => ParamDescriptor::real("mod_ang", 0.0..=32.0).logarithmic(),
+});
 impl QuadioNode for PhasorNode {
     fn show_ui(&mut self, ui: &mut egui::Ui) {
         ui.heading("Phasor");
-        ui.horizontal(|ui| {
-            ui.monospace("FREQ MUL/DIV");
-            ui.add(egui::DragValue::new(&mut self.f_mul));
-            ui.add(egui::DragValue::new(&mut self.f_div).clamp_range(0.001..=f32::MAX));
-        });
-        ui.monospace("MOD MAG-FM SCL");
-        ui.add(egui::Slider::new(&mut self.mod_mag_scale, 0.0..=8.0).logarithmic(true));
-        ui.monospace("MOD ANG SCL");
-        ui.add(egui::Slider::new(&mut self.mod_ang_scale, 0.0..=32.0).logarithmic(true));
     }
 
-    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
         for (mod_in, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
             let (_mod_mag, mod_ang) = mod_in.to_polar();
 
@@ -571,7 +1158,8 @@ impl QuadioNode for PhasorNode {
 
             let mod_f_scale = 0.02 * (mod_in.re * self.mod_mag_scale);
 
-            self.phase += 0.02 * self.f_mul / self.f_div; // main accumulator
+            let (f_mul, f_div) = (self.f_mul.next(ctx.sample_rate), self.f_div.next(ctx.sample_rate));
+            self.phase += 0.02 * f_mul / f_div; // main accumulator
             self.phase += est_mod_freq * self.mod_ang_scale; // PM (previously differentiated)
             self.phase += mod_f_scale; // FM
             self.phase %= TAU;
@@ -585,3 +1173,389 @@ impl QuadioNode for PhasorNode {
         }
     }
 }
+
+/// Takes the real part. Put between complex outputs and real inputs.
+#[derive(Default)]
+pub struct ToRealNode;
+impl graph::Node for ToRealNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ty: SocketType::Complex,
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Re".to_owned(),
+                ty: SocketType::Real,
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(ToRealNode {});
+impl QuadioNode for ToRealNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.monospace("TO REAL");
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        for (inp, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
+            *out = Complex32::new(inp.re, 0.0);
+        }
+    }
+}
+
+/// Control-rate to audio-rate, ramping from the previous block's value so
+/// there are no steps. `REAL` makes it put out just the real part, on a real
+/// socket.
+#[derive(Default)]
+pub struct ControlToAudioNode<const REAL: bool = false> {
+    last: QuadioSample,
+}
+pub type ControlToRealNode = ControlToAudioNode<true>;
+impl<const REAL: bool> graph::Node for ControlToAudioNode<REAL> {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ty: SocketType::Control,
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ty: if REAL { SocketType::Real } else { SocketType::Complex },
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(ControlToAudioNode<false> {});
+params!(ControlToAudioNode<true> {});
+impl<const REAL: bool> QuadioNode for ControlToAudioNode<REAL>
+where
+    Self: Params,
+{
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.monospace(if REAL { "CTL > REAL" } else { "CTL > AUDIO" });
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        let mut target = inputs[0][0];
+        if REAL {
+            target.im = 0.0;
+        }
+        let len = outputs[0].len() as f32;
+        for (i, out) in outputs[0].iter_mut().enumerate() {
+            *out = self.last + (target - self.last) * ((i + 1) as f32 / len);
+        }
+        self.last = target;
+    }
+}
+
+/// Audio-rate to control-rate: one value per block (the block's mean).
+#[derive(Default)]
+pub struct AudioToControlNode;
+impl graph::Node for AudioToControlNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ty: SocketType::Complex,
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ty: SocketType::Control,
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(AudioToControlNode {});
+impl QuadioNode for AudioToControlNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.monospace("AUDIO > CTL");
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        let sum: QuadioSample = inputs[0].iter().sum();
+        outputs[0][0] = sum / inputs[0].len().max(1) as f32;
+    }
+}
+
+/// Audio to events: fires one (with a payload of 1) each time the real part
+/// rises through the threshold.
+pub struct AudioToEventNode {
+    threshold: f32,
+    above: bool,
+}
+impl Default for AudioToEventNode {
+    fn default() -> Self {
+        AudioToEventNode { threshold: 0.5, above: false }
+    }
+}
+impl graph::Node for AudioToEventNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ty: SocketType::Complex,
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ty: SocketType::Event,
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(AudioToEventNode {
+    threshold => ParamDescriptor::real("threshold", -1.0..=1.0),
+});
+impl QuadioNode for AudioToEventNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.monospace("AUDIO > EVENT");
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        for (inp, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
+            let above = inp.re >= self.threshold;
+            *out = QuadioSample::from(if above && !self.above { 1.0 } else { 0.0 });
+            self.above = above;
+        }
+    }
+}
+
+/// Control to events: fires one at the start of each block where the value
+/// has changed, carrying the new value. Changes to zero don't fire, since a
+/// zero sample isn't an event.
+#[derive(Default)]
+pub struct ControlToEventNode {
+    last: QuadioSample,
+}
+impl graph::Node for ControlToEventNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ty: SocketType::Control,
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ty: SocketType::Event,
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(ControlToEventNode {});
+impl QuadioNode for ControlToEventNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.monospace("CTL > EVENT");
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        let value = inputs[0][0];
+        outputs[0].fill(QuadioSample::from(0.0));
+        if value != self.last {
+            outputs[0][0] = value;
+            self.last = value;
+        }
+    }
+}
+
+/// Events to audio: holds the payload of the latest event, starting from
+/// zero. `REAL` makes it put out just the real part, on a real socket.
+#[derive(Default)]
+pub struct EventToAudioNode<const REAL: bool = false> {
+    held: QuadioSample,
+}
+pub type EventToRealNode = EventToAudioNode<true>;
+impl<const REAL: bool> graph::Node for EventToAudioNode<REAL> {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ty: SocketType::Event,
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ty: if REAL { SocketType::Real } else { SocketType::Complex },
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(EventToAudioNode<false> {});
+params!(EventToAudioNode<true> {});
+impl<const REAL: bool> QuadioNode for EventToAudioNode<REAL>
+where
+    Self: Params,
+{
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.monospace(if REAL { "EVENT > REAL" } else { "EVENT > AUDIO" });
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        for (inp, out) in inputs[0].iter().zip(outputs[0].iter_mut()) {
+            if *inp != QuadioSample::from(0.0) {
+                self.held = if REAL { QuadioSample::from(inp.re) } else { *inp };
+            }
+            *out = self.held;
+        }
+    }
+}
+
+/// Events to control-rate: the payload of the latest event, as of the end
+/// of each block.
+#[derive(Default)]
+pub struct EventToControlNode {
+    held: QuadioSample,
+}
+impl graph::Node for EventToControlNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ty: SocketType::Event,
+                ..Default::default()
+            }],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ty: SocketType::Control,
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(EventToControlNode {});
+impl QuadioNode for EventToControlNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.monospace("EVENT > CTL");
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        if let Some(&last) = inputs[0].iter().rev().find(|&&x| x != QuadioSample::from(0.0)) {
+            self.held = last;
+        }
+        outputs[0][0] = self.held;
+    }
+}
+
+/// The node that turns a `from` signal into a `to` one, if there is one.
+pub fn conversion_node(from: SocketType, to: SocketType) -> Option<Box<dyn QuadioNode>> {
+    use SocketType::*;
+
+    match (from, to) {
+        (Complex, Real) => Some(Box::new(ToRealNode)),
+        (Control, Complex) => Some(Box::new(ControlToAudioNode::<false>::default())),
+        (Control, Real) => Some(Box::new(ControlToRealNode::default())),
+        (Complex | Real, Control) => Some(Box::new(AudioToControlNode)),
+        (Complex | Real, Event) => Some(Box::new(AudioToEventNode::default())),
+        (Control, Event) => Some(Box::new(ControlToEventNode::default())),
+        (Event, Complex) => Some(Box::new(EventToAudioNode::<false>::default())),
+        (Event, Real) => Some(Box::new(EventToRealNode::default())),
+        (Event, Control) => Some(Box::new(EventToControlNode::default())),
+        _ => None,
+    }
+}
+
+/// Like `NodeGraph::connect`, but if the sockets' types don't match and there's
+/// a `conversion_node` for them, one gets put in between.
+pub fn connect_with_conversion(
+    graph: &mut Patch,
+    src: (NodeKey, usize),
+    dst: (NodeKey, usize),
+) -> Result<Option<(NodeKey, usize)>, GraphError> {
+    let (src_type, dst_type) = match graph.connect(src, dst) {
+        Err(GraphError::TypeMismatch { src_type, dst_type }) => (src_type, dst_type),
+        rv => return rv,
+    };
+    let Some(conversion) = conversion_node(src_type, dst_type) else {
+        return Err(GraphError::TypeMismatch { src_type, dst_type });
+    };
+
+    let [src_x, src_y] = graph.layout(src.0).pos;
+    let [dst_x, dst_y] = graph.layout(dst.0).pos;
+    let midpoint = [(src_x + dst_x) / 2.0, (src_y + dst_y) / 2.0];
+    let conversion = graph.add_node_with_layout(conversion, NodeLayout::at(midpoint));
+
+    // src -> conversion can't fail (or make a cycle); conversion -> dst might
+    let rv = graph
+        .connect(src, (conversion, 0))
+        .and_then(|_| graph.connect((conversion, 0), dst));
+    if rv.is_err() {
+        graph.remove_node(conversion);
+    }
+    rv
+}
+
+/// Sets a parameter the way a user would: clamped, smoothed, and recorded
+/// into automation if that's armed. The UI and OSC both come through here.
+pub fn edit_param(graph: &mut Patch, node: NodeKey, idx: usize, value: ParamValue) {
+    let Some(descriptor) = graph.get_node(node).param_descriptors().get(idx) else {
+        return;
+    };
+    let (name, value) = (descriptor.name.to_string(), descriptor.clamp(value));
+    graph.get_node_mut(node).set_param(idx, value);
+    graph.automation_mut().record(node, &name, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPES: [SocketType; 4] = [SocketType::Complex, SocketType::Real, SocketType::Control, SocketType::Event];
+
+    #[test]
+    fn every_mismatch_has_a_conversion() {
+        for from in TYPES {
+            for to in TYPES {
+                if from.connects_to(to) {
+                    continue;
+                }
+                let node = conversion_node(from, to)
+                    .unwrap_or_else(|| panic!("nothing converts {from:?} to {to:?}"));
+                let descriptor = node.get_descriptor();
+                assert!(from.connects_to(descriptor.input_sockets[0].ty), "{from:?} to {to:?}");
+                assert!(descriptor.output_sockets[0].ty.connects_to(to), "{from:?} to {to:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn events_hold_until_the_next_one() {
+        let ctx = AudioContext { sample_rate: 48000.0 };
+        let input: Vec<_> = [0.0, 2.0, 0.0, 0.0, 3.0, 0.0].map(QuadioSample::from).into();
+        let mut out = vec![QuadioSample::from(0.0); input.len()];
+        EventToRealNode::default().process(&ctx, &[&input], &mut [&mut out]);
+        assert_eq!(out, [0.0, 2.0, 2.0, 2.0, 3.0, 3.0].map(QuadioSample::from));
+
+        let mut events = vec![QuadioSample::from(0.0); input.len()];
+        let audio: Vec<_> = [0.0, 0.7, 0.9, 0.1, 0.6, 0.8].map(QuadioSample::from).into();
+        AudioToEventNode::default().process(&ctx, &[&audio], &mut [&mut events]);
+        assert_eq!(events, [0.0, 1.0, 0.0, 0.0, 1.0, 0.0].map(QuadioSample::from));
+    }
+    #[test]
+    fn scope_captures_reach_the_ui_without_the_node() {
+        let ctx = AudioContext { sample_rate: 48000.0 };
+        let mut scope = ScopeNode::default();
+        scope.mode = TriggerMode::Free;
+        scope.length = 4;
+        let input = [1.0, 2.0, 3.0, 4.0].map(QuadioSample::from);
+        let mut out = [QuadioSample::from(0.0); 4];
+        scope.process(&ctx, &[&input], &mut [&mut out]);
+
+        let id = scope.id;
+        let mut readers = SCOPE_READERS.lock().unwrap();
+        assert_eq!(readers.get_mut(&id).unwrap().latest().samples, input);
+        drop(readers);
+
+        drop(scope);
+        assert!(!SCOPE_READERS.lock().unwrap().contains_key(&id));
+    }
+}
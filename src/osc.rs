@@ -0,0 +1,518 @@
+//! A small OSC server, so other tools can drive the graph over UDP.
+//!
+//! Addresses:
+//! - `/node/<name>/<param> <value...>` sets a parameter (complex ones take
+//!   two floats, enums an index or a variant name); without arguments it
+//!   replies with the current value at the same address
+//! - `/node/<name>` replies with every parameter of the node, as above
+//! - `/graph` replies with `/graph/node <name> <type>` for every node and
+//!   `/graph/wire <src> <output> <dst> <input>` for every wire
+//! - `/graph/add <type> [<title>]` replies with `/graph/added <name>`; types
+//!   are registry ids, like `phase_scale`
+//! - `/graph/remove <name>`
+//! - `/graph/connect <src> <output> <dst> <input>`, where sockets are given by
+//!   index or by label
+//! - `/graph/disconnect <dst> <input>`
+//!
+//! Anything that goes wrong is replied to with `/error <message>`.
+
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use num_complex::Complex32;
+
+use crate::graph::{NodeKey, NodeLayout, SocketDirection};
+use crate::node::node_name;
+use crate::param::{ParamKind, ParamValue};
+use crate::patch::Patch;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+    Long(i64),
+    Double(f64),
+    Bool(bool),
+    Nil,
+}
+impl OscArg {
+    fn as_f32(&self) -> Option<f32> {
+        match *self {
+            OscArg::Int(i) => Some(i as f32),
+            OscArg::Float(x) => Some(x),
+            OscArg::Long(i) => Some(i as f32),
+            OscArg::Double(x) => Some(x as f32),
+            OscArg::Bool(b) => Some(b as u8 as f32),
+            _ => None,
+        }
+    }
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            OscArg::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub addr: String,
+    pub args: Vec<OscArg>,
+}
+impl OscMessage {
+    pub fn new(addr: impl Into<String>, args: Vec<OscArg>) -> OscMessage {
+        OscMessage {
+            addr: addr.into(),
+            args,
+        }
+    }
+}
+
+fn read_padded<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    let padded = (len + 3) & !3;
+    if buf.len() < padded {
+        bail!("packet ends early");
+    }
+    let (data, rest) = buf.split_at(padded);
+    *buf = rest;
+    Ok(&data[..len])
+}
+fn read_string(buf: &mut &[u8]) -> anyhow::Result<String> {
+    let len = buf
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| anyhow!("unterminated string"))?;
+    // the terminating nul counts towards the padding
+    let s = read_padded(buf, len + 1)?;
+    Ok(std::str::from_utf8(&s[..len])?.to_owned())
+}
+fn read_array<const N: usize>(buf: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    Ok(read_padded(buf, N)?.try_into().unwrap())
+}
+
+/// Decodes a packet, flattening any bundles (their time tags are ignored;
+/// everything happens right away).
+pub fn decode(mut packet: &[u8]) -> anyhow::Result<Vec<OscMessage>> {
+    let buf = &mut packet;
+    let addr = read_string(buf)?;
+
+    if addr == "#bundle" {
+        let _time_tag: [u8; 8] = read_array(buf)?;
+        let mut messages = vec![];
+        while !buf.is_empty() {
+            let len = i32::from_be_bytes(read_array(buf)?);
+            let element = read_padded(buf, len.max(0) as usize)?;
+            messages.extend(decode(element)?);
+        }
+        return Ok(messages);
+    }
+
+    // some old senders leave the type tags off entirely
+    let tags = if buf.is_empty() { ",".to_owned() } else { read_string(buf)? };
+    let Some(tags) = tags.strip_prefix(',') else {
+        bail!("bad type tags {tags:?}");
+    };
+
+    let mut args = vec![];
+    for tag in tags.chars() {
+        args.push(match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(read_array(buf)?)),
+            'f' => OscArg::Float(f32::from_be_bytes(read_array(buf)?)),
+            's' | 'S' => OscArg::String(read_string(buf)?),
+            'b' => {
+                let len = i32::from_be_bytes(read_array(buf)?);
+                OscArg::Blob(read_padded(buf, len.max(0) as usize)?.to_vec())
+            }
+            'h' => OscArg::Long(i64::from_be_bytes(read_array(buf)?)),
+            'd' => OscArg::Double(f64::from_be_bytes(read_array(buf)?)),
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'N' | 'I' => OscArg::Nil,
+            other => bail!("unsupported argument type {other:?}"),
+        });
+    }
+    Ok(vec![OscMessage { addr, args }])
+}
+
+fn write_padded(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(data);
+    out.resize((out.len() + 3) & !3, 0);
+}
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.push(0);
+    out.resize((out.len() + 3) & !3, 0);
+}
+
+pub fn encode(message: &OscMessage) -> Vec<u8> {
+    let mut out = vec![];
+    write_string(&mut out, &message.addr);
+
+    let tags: String = std::iter::once(',')
+        .chain(message.args.iter().map(|arg| match arg {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::String(_) => 's',
+            OscArg::Blob(_) => 'b',
+            OscArg::Long(_) => 'h',
+            OscArg::Double(_) => 'd',
+            OscArg::Bool(true) => 'T',
+            OscArg::Bool(false) => 'F',
+            OscArg::Nil => 'N',
+        }))
+        .collect();
+    write_string(&mut out, &tags);
+
+    for arg in &message.args {
+        match arg {
+            OscArg::Int(i) => out.extend_from_slice(&i.to_be_bytes()),
+            OscArg::Float(x) => out.extend_from_slice(&x.to_be_bytes()),
+            OscArg::String(s) => write_string(&mut out, s),
+            OscArg::Blob(data) => {
+                out.extend_from_slice(&(data.len() as i32).to_be_bytes());
+                write_padded(&mut out, data);
+            }
+            OscArg::Long(i) => out.extend_from_slice(&i.to_be_bytes()),
+            OscArg::Double(x) => out.extend_from_slice(&x.to_be_bytes()),
+            OscArg::Bool(_) | OscArg::Nil => (),
+        }
+    }
+    out
+}
+
+fn find_node(graph: &Patch, name: &str) -> anyhow::Result<NodeKey> {
+    let mut matches = graph.nodes().map(|(key, _)| key).filter(|&key| node_name(graph, key) == name);
+    let node = matches.next().ok_or_else(|| anyhow!("no node named {name:?}"))?;
+    // better to refuse than to poke at whichever one happens to come first
+    let others = matches.count();
+    if others > 0 {
+        bail!("{} nodes are named {name:?}; give them titles to tell them apart", others + 1);
+    }
+    Ok(node)
+}
+
+fn find_socket(
+    graph: &Patch,
+    node: NodeKey,
+    direction: SocketDirection,
+    arg: Option<&OscArg>,
+) -> anyhow::Result<usize> {
+    let descriptor = graph.node_descriptor(node);
+    let sockets = match direction {
+        SocketDirection::Input => &descriptor.input_sockets,
+        SocketDirection::Output => &descriptor.output_sockets,
+    };
+    match arg {
+        // out-of-range indices are left for `connect` to complain about
+        Some(OscArg::Int(idx)) => Ok((*idx).max(0) as usize),
+        Some(OscArg::String(label)) => sockets
+            .iter()
+            .position(|s| &s.label == label)
+            .ok_or_else(|| anyhow!("no {direction:?} socket labelled {label:?}")),
+        _ => bail!("expected a socket index or label"),
+    }
+}
+
+fn value_args(value: ParamValue) -> Vec<OscArg> {
+    match value {
+        ParamValue::Real(x) => vec![OscArg::Float(x)],
+        ParamValue::Complex(c) => vec![OscArg::Float(c.re), OscArg::Float(c.im)],
+        ParamValue::Int(i) => vec![OscArg::Int(i)],
+        ParamValue::Enum(i) => vec![OscArg::Int(i as i32)],
+        ParamValue::Bool(b) => vec![OscArg::Bool(b)],
+    }
+}
+
+fn parse_value(kind: &ParamKind, args: &[OscArg]) -> anyhow::Result<ParamValue> {
+    let number = |i: usize| {
+        args.get(i)
+            .and_then(OscArg::as_f32)
+            .ok_or_else(|| anyhow!("expected a number"))
+    };
+    Ok(match kind {
+        ParamKind::Real { .. } => ParamValue::Real(number(0)?),
+        ParamKind::Complex { .. } => {
+            ParamValue::Complex(Complex32::new(number(0)?, number(1).unwrap_or(0.0)))
+        }
+        ParamKind::Int { .. } => ParamValue::Int(number(0)?.round() as i32),
+        ParamKind::Enum { variants } => match args.first().and_then(OscArg::as_str) {
+            Some(name) => ParamValue::Enum(
+                variants
+                    .iter()
+                    .position(|v| v == name)
+                    .ok_or_else(|| anyhow!("no variant {name:?}"))?,
+            ),
+            None => ParamValue::Enum(number(0)?.max(0.0) as usize),
+        },
+        ParamKind::Bool => ParamValue::Bool(number(0)? != 0.0),
+    })
+}
+
+/// Carries out one message, returning the replies.
+pub fn handle(graph: &mut Patch, message: &OscMessage) -> Vec<OscMessage> {
+    match try_handle(graph, message) {
+        Ok(replies) => replies,
+        Err(e) => vec![OscMessage::new("/error", vec![OscArg::String(format!("{}: {e:#}", message.addr))])],
+    }
+}
+
+fn try_handle(
+    graph: &mut Patch,
+    message: &OscMessage,
+) -> anyhow::Result<Vec<OscMessage>> {
+    let parts: Vec<&str> = message.addr.split('/').skip(1).collect();
+    let args = &message.args;
+    let str_arg = |i: usize| {
+        args.get(i)
+            .and_then(OscArg::as_str)
+            .ok_or_else(|| anyhow!("argument {i} should be a string"))
+    };
+
+    let mut replies = vec![];
+    match parts.as_slice() {
+        ["node", name] => {
+            let node = find_node(graph, name)?;
+            let node_ref = graph.get_node(node);
+            for (idx, descriptor) in node_ref.param_descriptors().iter().enumerate() {
+                let addr = format!("/node/{name}/{}", descriptor.name);
+                replies.push(OscMessage::new(addr, value_args(node_ref.param(idx))));
+            }
+        }
+        ["node", name, param] => {
+            let node = find_node(graph, name)?;
+            let idx = graph
+                .get_node(node)
+                .param_index(param)
+                .ok_or_else(|| anyhow!("{name} has no parameter {param:?}"))?;
+            if args.is_empty() {
+                let value = graph.get_node(node).param(idx);
+                replies.push(OscMessage::new(message.addr.clone(), value_args(value)));
+            } else {
+                let kind = graph.get_node(node).param_descriptors()[idx].kind.clone();
+                crate::node::edit_param(graph, node, idx, parse_value(&kind, args)?);
+            }
+        }
+        ["graph"] => {
+            for (key, node) in graph.nodes() {
+                let type_id = crate::registry::type_of(&**node).map_or("?", |ty| ty.id);
+                replies.push(OscMessage::new(
+                    "/graph/node",
+                    vec![OscArg::String(node_name(graph, key)), OscArg::String(type_id.to_owned())],
+                ));
+            }
+            for (dst, src) in graph.wires() {
+                replies.push(OscMessage::new(
+                    "/graph/wire",
+                    vec![
+                        OscArg::String(node_name(graph, src.0)),
+                        OscArg::Int(src.1 as i32),
+                        OscArg::String(node_name(graph, dst.0)),
+                        OscArg::Int(dst.1 as i32),
+                    ],
+                ));
+            }
+        }
+        ["graph", "add"] => {
+            let type_id = str_arg(0)?;
+            let Some(ty) = crate::registry::lookup(type_id) else {
+                bail!("unknown node type {type_id:?}");
+            };
+            let layout = NodeLayout {
+                title: args.get(1).and_then(OscArg::as_str).map(str::to_owned),
+                ..Default::default()
+            };
+            let node = graph.add_node_with_layout(ty.make(), layout);
+            replies.push(OscMessage::new("/graph/added", vec![OscArg::String(node_name(graph, node))]));
+        }
+        ["graph", "remove"] => {
+            let node = find_node(graph, str_arg(0)?)?;
+            graph.remove_node(node);
+        }
+        ["graph", "connect"] => {
+            let src = find_node(graph, str_arg(0)?)?;
+            let src_idx = find_socket(graph, src, SocketDirection::Output, args.get(1))?;
+            let dst = find_node(graph, str_arg(2)?)?;
+            let dst_idx = find_socket(graph, dst, SocketDirection::Input, args.get(3))?;
+            crate::node::connect_with_conversion(graph, (src, src_idx), (dst, dst_idx))?;
+        }
+        ["graph", "disconnect"] => {
+            let dst = find_node(graph, str_arg(0)?)?;
+            let dst_idx = find_socket(graph, dst, SocketDirection::Input, args.get(1))?;
+            graph.disconnect(dst, SocketDirection::Input, dst_idx);
+        }
+        _ => bail!("unknown address"),
+    }
+    // e.g. Sum's input count
+    graph.refresh_descriptors();
+    Ok(replies)
+}
+
+// how long the server waits for a packet before checking whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// how long it backs off for when the socket keeps failing, at most
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Stops the server when dropped.
+pub struct OscServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+impl OscServer {
+    /// Listens on `addr` on a thread of its own, until dropped.
+    pub fn spawn(graph: Arc<Mutex<Patch>>, addr: impl ToSocketAddrs) -> anyhow::Result<OscServer> {
+        let socket = UdpSocket::bind(addr).context("couldn't bind OSC socket")?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let addr = socket.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut buf = vec![0; 65536];
+            let mut backoff = Duration::ZERO;
+            while !thread_stop.load(Ordering::Relaxed) {
+                let (len, sender) = match socket.recv_from(&mut buf) {
+                    Ok(x) => x,
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+                    Err(e) => {
+                        // don't spin on a socket that's failing every time
+                        backoff = (backoff * 2).clamp(POLL_INTERVAL / 10, MAX_BACKOFF);
+                        eprintln!("OSC: {e} (retrying in {backoff:?})");
+                        std::thread::sleep(backoff);
+                        continue;
+                    }
+                };
+                backoff = Duration::ZERO;
+                let messages = match decode(&buf[..len]) {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        eprintln!("OSC: bad packet from {sender}: {e:#}");
+                        continue;
+                    }
+                };
+
+                let replies: Vec<_> = {
+                    let mut graph = graph.lock().unwrap();
+                    messages.iter().flat_map(|m| handle(&mut graph, m)).collect()
+                };
+                for reply in replies {
+                    if let Err(e) = socket.send_to(&encode(&reply), sender) {
+                        eprintln!("OSC: couldn't reply to {sender}: {e}");
+                    }
+                }
+            }
+        });
+
+        Ok(OscServer {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // it notices within `POLL_INTERVAL`
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_arg() -> Vec<OscArg> {
+        vec![
+            OscArg::Int(-7),
+            OscArg::Float(0.25),
+            OscArg::String("abc".to_owned()),
+            OscArg::String("abcd".to_owned()),
+            OscArg::Blob(vec![1, 2, 3, 4, 5]),
+            OscArg::Long(1 << 40),
+            OscArg::Double(-1.5),
+            OscArg::Bool(true),
+            OscArg::Bool(false),
+            OscArg::Nil,
+        ]
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let message = OscMessage::new("/node/x/y", every_arg());
+        let packet = encode(&message);
+        assert_eq!(packet.len() % 4, 0);
+        assert_eq!(decode(&packet).unwrap(), vec![message]);
+
+        // bundles come out flattened
+        let other = OscMessage::new("/graph", vec![]);
+        let mut bundle = vec![];
+        write_string(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        for m in [&OscMessage::new("/a", every_arg()), &other] {
+            let element = encode(m);
+            bundle.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(&element);
+        }
+        assert_eq!(decode(&bundle).unwrap(), vec![OscMessage::new("/a", every_arg()), other]);
+
+        // and junk is an error rather than a panic
+        assert!(decode(&packet[..packet.len() - 3]).is_err());
+        assert!(decode(b"/no/terminator").is_err());
+    }
+
+    #[test]
+    fn ambiguous_names_are_refused() {
+        let mut graph = Patch::default();
+        let add = OscMessage::new(
+            "/graph/add",
+            vec![OscArg::String("phasor".to_owned()), OscArg::String("lfo".to_owned())],
+        );
+        handle(&mut graph, &add);
+        handle(&mut graph, &add);
+        let replies = handle(&mut graph, &OscMessage::new("/graph/remove", vec![OscArg::String("lfo".to_owned())]));
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].addr, "/error");
+        assert_eq!(graph.nodes().count(), 2);
+    }
+
+    #[test]
+    fn server_replies_over_udp() {
+        let graph = Arc::new(Mutex::new(Patch::default()));
+        let server = OscServer::spawn(graph.clone(), "127.0.0.1:0").unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let add = OscMessage::new(
+            "/graph/add",
+            vec![OscArg::String("phasor".to_owned()), OscArg::String("osc".to_owned())],
+        );
+        client.send_to(&encode(&add), server.addr()).unwrap();
+
+        let mut buf = [0; 1024];
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(
+            decode(&buf[..len]).unwrap(),
+            vec![OscMessage::new("/graph/added", vec![OscArg::String("osc".to_owned())])]
+        );
+        assert_eq!(graph.lock().unwrap().nodes().count(), 1);
+
+        // dropping it stops the thread and lets go of the port
+        let addr = server.addr();
+        drop(server);
+        UdpSocket::bind(addr).unwrap();
+    }
+}
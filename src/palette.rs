@@ -0,0 +1,148 @@
+//! The quick-add palette: press Tab or Space over the graph, type a bit of a
+//! node's name (or description), and Enter. Up and Down move through the
+//! matches; nodes used recently come first.
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+
+use crate::registry::NodeType;
+
+const MAX_RECENT: usize = 8;
+const MAX_SHOWN: usize = 12;
+
+/// Ids of the node types picked lately, most recent first. Shared by every
+/// graph editor, so it lives in egui's memory rather than in a `Palette`.
+fn recent(ctx: &egui::Context) -> Vec<&'static str> {
+    ctx.data_mut(|data| data.get_temp(recent_id()).unwrap_or_default())
+}
+fn recent_id() -> egui::Id {
+    egui::Id::new("palette_recent")
+}
+fn remember(ctx: &egui::Context, ty: &NodeType) {
+    let mut recent = recent(ctx);
+    recent.retain(|&id| id != ty.id);
+    recent.insert(0, ty.id);
+    recent.truncate(MAX_RECENT);
+    ctx.data_mut(|data| data.insert_temp(recent_id(), recent));
+}
+
+/// Node types matching `query`, best first.
+fn matches(query: &str, recent: &[&str]) -> Vec<NodeType> {
+    let types: Vec<NodeType> = crate::registry::registry().types().copied().collect();
+    let recency = |ty: &NodeType| recent.iter().position(|&id| id == ty.id);
+
+    if query.is_empty() {
+        let mut types = types;
+        // stable, so everything not used lately stays in registry order
+        types.sort_by_key(|ty| recency(ty).unwrap_or(usize::MAX));
+        return types;
+    }
+
+    let matcher = SkimMatcherV2::default();
+    let mut scored: Vec<(i64, NodeType)> = types
+        .into_iter()
+        .filter_map(|ty| {
+            let name = matcher.fuzzy_match(ty.name, query);
+            // matching the description is a weaker hint than matching the name
+            let description = matcher.fuzzy_match(ty.description, query).map(|score| score / 2);
+            let score = name.max(description)?;
+            let bonus = recency(&ty).map_or(0, |rank| 8 * (MAX_RECENT - rank) as i64);
+            Some((score + bonus, ty))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, ty)| ty).collect()
+}
+
+#[derive(Default)]
+pub struct Palette {
+    /// where it was opened, relative to the graph's origin; `None` if closed
+    pos: Option<egui::Vec2>,
+    query: String,
+    highlighted: usize,
+}
+impl Palette {
+    pub fn is_open(&self) -> bool {
+        self.pos.is_some()
+    }
+
+    pub fn open(&mut self, pos: egui::Vec2) {
+        self.pos = Some(pos);
+        self.query.clear();
+        self.highlighted = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.pos = None;
+    }
+
+    /// Draws the palette, if it's open. Returns the type picked and where
+    /// the palette was opened (relative to `origin`), once something is.
+    pub fn show(&mut self, ctx: &egui::Context, origin: egui::Pos2) -> Option<(NodeType, egui::Vec2)> {
+        let pos = self.pos?;
+        let recent = recent(ctx);
+        let found = matches(&self.query, &recent);
+        let mut picked = None;
+
+        let area = egui::Area::new("node_palette")
+            .order(egui::Order::Foreground)
+            .fixed_pos(origin + pos)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    // before the text box gets to see them
+                    let (up, down, enter, escape) = ui.input_mut(|i| {
+                        (
+                            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                            i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                            i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+                        )
+                    });
+                    let shown = found.len().min(MAX_SHOWN);
+                    if down && self.highlighted + 1 < shown {
+                        self.highlighted += 1;
+                    }
+                    if up {
+                        self.highlighted = self.highlighted.saturating_sub(1);
+                    }
+
+                    let search = ui.add(
+                        egui::TextEdit::singleline(&mut self.query)
+                            .hint_text("add a node...")
+                            .desired_width(200.0),
+                    );
+                    search.request_focus();
+                    if search.changed() {
+                        self.highlighted = 0;
+                    }
+
+                    if found.is_empty() {
+                        ui.weak("nothing matches");
+                    }
+                    for (i, ty) in found.iter().take(MAX_SHOWN).enumerate() {
+                        let r = ui
+                            .selectable_label(i == self.highlighted, ty.name)
+                            .on_hover_text(ty.description);
+                        if r.clicked() {
+                            picked = Some(*ty);
+                        }
+                    }
+
+                    if enter {
+                        picked = picked.or_else(|| found.get(self.highlighted).copied());
+                    }
+                    if escape {
+                        self.close();
+                    }
+                });
+            });
+        if area.response.clicked_elsewhere() {
+            self.close();
+        }
+
+        let ty = picked?;
+        remember(ctx, &ty);
+        self.close();
+        Some((ty, pos))
+    }
+}
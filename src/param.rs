@@ -1,39 +1,735 @@
-/*pub struct ParameterMeta {
-    label: String
-}
-
-macro_rules! param {
-    (
-        struct $struct_name:ident: $desc_name:ident {
-            $(
-                $field_name:ident : $param_ty:ty
-            ),*
-        }
-    ) => {
-        pub struct $struct_name<'a> {
-            $(
-                $field_name: &'a [$param_ty]
-            ),*
-        }
-        pub struct $desc_name {
-            $(
-                $field_name: ParameterMeta
-            ),*
-        }
-        impl std::default::Default for $desc_name {
-            fn default() -> $desc_name {
-                $desc_name {
-                    $(
-                        $field_name: ParameterMeta { label: stringify!($field_name).to_owned() }
-                    ),*
+//! Node parameters.
+//!
+//! Nodes declare their parameters (with `params!`) instead of hand-rolling
+//! widgets for them; everything else - the editor UI, saving and loading,
+//! automation, presets - works off those declarations.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ParamValue {
+    Real(f32),
+    Complex(Complex32),
+    Int(i32),
+    Enum(usize),
+    Bool(bool),
+}
+impl ParamValue {
+    /// `self + depth * input`, in whatever way makes sense for the kind of
+    /// value; only complex values look at the imaginary part.
+    pub fn modulated(self, depth: f32, input: Complex32) -> ParamValue {
+        let offset = depth * input.re;
+        match self {
+            ParamValue::Real(x) => ParamValue::Real(x + offset),
+            ParamValue::Complex(c) => ParamValue::Complex(c + input * depth),
+            ParamValue::Int(i) => ParamValue::Int(i + offset.round() as i32),
+            ParamValue::Enum(i) => ParamValue::Enum((i as f32 + offset).round().max(0.0) as usize),
+            ParamValue::Bool(b) => ParamValue::Bool(b as u8 as f32 + offset >= 0.5),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamKind {
+    Real { range: RangeInclusive<f32> },
+    /// edited in polar form; the range is for the magnitude
+    Complex { magnitude: RangeInclusive<f32> },
+    Int { range: RangeInclusive<i32> },
+    Enum { variants: Vec<Cow<'static, str>> },
+    Bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scaling {
+    #[default]
+    Linear,
+    Logarithmic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmoothingCurve {
+    /// straight line to the new value, arriving after `time`
+    Linear,
+    /// exponential approach, with `time` as the time constant
+    OnePole,
+}
+
+/// Which coordinates complex parameters get smoothed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ComplexSpace {
+    #[default]
+    Cartesian,
+    /// magnitude and angle separately, so e.g. a rotating gain keeps its size
+    Polar,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Smoothing {
+    pub curve: SmoothingCurve,
+    /// seconds
+    pub time: f32,
+    pub space: ComplexSpace,
+}
+impl Smoothing {
+    pub fn linear(time: f32) -> Smoothing {
+        Smoothing {
+            curve: SmoothingCurve::Linear,
+            time,
+            space: ComplexSpace::Cartesian,
+        }
+    }
+    pub fn one_pole(time: f32) -> Smoothing {
+        Smoothing {
+            curve: SmoothingCurve::OnePole,
+            time,
+            space: ComplexSpace::Cartesian,
+        }
+    }
+    pub fn polar(mut self) -> Smoothing {
+        self.space = ComplexSpace::Polar;
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamDescriptor {
+    /// unique within the node, and what patches refer to the parameter by
+    pub name: Cow<'static, str>,
+    pub kind: ParamKind,
+    pub unit: Cow<'static, str>,
+    pub scaling: Scaling,
+    /// how `Smoothed` fields glide to new values; `None` means they jump
+    pub smoothing: Option<Smoothing>,
+}
+impl ParamDescriptor {
+    fn new(name: impl Into<Cow<'static, str>>, kind: ParamKind) -> ParamDescriptor {
+        ParamDescriptor {
+            name: name.into(),
+            kind,
+            unit: Cow::Borrowed(""),
+            scaling: Scaling::Linear,
+            smoothing: None,
+        }
+    }
+
+    pub fn real(name: impl Into<Cow<'static, str>>, range: RangeInclusive<f32>) -> ParamDescriptor {
+        ParamDescriptor::new(name, ParamKind::Real { range })
+    }
+    pub fn complex(
+        name: impl Into<Cow<'static, str>>,
+        magnitude: RangeInclusive<f32>,
+    ) -> ParamDescriptor {
+        ParamDescriptor::new(name, ParamKind::Complex { magnitude })
+    }
+    pub fn int(name: impl Into<Cow<'static, str>>, range: RangeInclusive<i32>) -> ParamDescriptor {
+        ParamDescriptor::new(name, ParamKind::Int { range })
+    }
+    pub fn enumeration<E: EnumParam>(name: impl Into<Cow<'static, str>>) -> ParamDescriptor {
+        let variants = E::VARIANTS.iter().map(|&v| Cow::Borrowed(v)).collect();
+        ParamDescriptor::new(name, ParamKind::Enum { variants })
+    }
+    pub fn bool(name: impl Into<Cow<'static, str>>) -> ParamDescriptor {
+        ParamDescriptor::new(name, ParamKind::Bool)
+    }
+
+    pub fn unit(mut self, unit: impl Into<Cow<'static, str>>) -> ParamDescriptor {
+        self.unit = unit.into();
+        self
+    }
+    pub fn logarithmic(mut self) -> ParamDescriptor {
+        self.scaling = Scaling::Logarithmic;
+        self
+    }
+    pub fn smoothed(mut self, smoothing: Smoothing) -> ParamDescriptor {
+        self.smoothing = Some(smoothing);
+        self
+    }
+
+    /// Forces `value` into the right shape and range for this parameter.
+    pub fn clamp(&self, value: ParamValue) -> ParamValue {
+        match &self.kind {
+            ParamKind::Real { range } => {
+                ParamValue::Real(f32::from_value(value).clamp(*range.start(), *range.end()))
+            }
+            ParamKind::Complex { magnitude } => {
+                let c = Complex32::from_value(value);
+                let (r, theta) = c.to_polar();
+                if magnitude.contains(&r) {
+                    // going through polar and back would nudge it every time
+                    return ParamValue::Complex(c);
                 }
+                let r = r.clamp(*magnitude.start(), *magnitude.end());
+                ParamValue::Complex(Complex32::from_polar(r, theta))
+            }
+            ParamKind::Int { range } => {
+                ParamValue::Int(i32::from_value(value).clamp(*range.start(), *range.end()))
             }
+            ParamKind::Enum { variants } => {
+                ParamValue::Enum(usize::from_value(value).min(variants.len().saturating_sub(1)))
+            }
+            ParamKind::Bool => ParamValue::Bool(bool::from_value(value)),
+        }
+    }
+}
+
+/// Something a parameter can be stored in.
+pub trait ParamType: Sized {
+    fn to_value(&self) -> ParamValue;
+    /// should be lenient: a Real for an Int field and so on is fine
+    fn from_value(value: ParamValue) -> Self;
+
+    /// What `Params::set_param` actually calls, with the descriptor's
+    /// `smoothing`; only `Smoothed` cares about that.
+    fn set_from_value(&mut self, value: ParamValue, _smoothing: Option<Smoothing>) {
+        *self = Self::from_value(value);
+    }
+    /// What `Params::modulate_param` calls: plays out `values`, one per
+    /// sample of the coming block, leaving its own value alone while it
+    /// does. Only `Smoothed` can do that; everything else returns false and
+    /// gets set with `set_from_value` wherever the value changes.
+    fn follow(&mut self, _values: &[ParamValue]) -> bool {
+        false
+    }
+}
+impl ParamType for f32 {
+    fn to_value(&self) -> ParamValue {
+        ParamValue::Real(*self)
+    }
+    fn from_value(value: ParamValue) -> Self {
+        match value {
+            ParamValue::Real(x) => x,
+            ParamValue::Complex(c) => c.re,
+            ParamValue::Int(i) => i as f32,
+            ParamValue::Enum(i) => i as f32,
+            ParamValue::Bool(b) => b as u8 as f32,
+        }
+    }
+}
+impl ParamType for Complex32 {
+    fn to_value(&self) -> ParamValue {
+        ParamValue::Complex(*self)
+    }
+    fn from_value(value: ParamValue) -> Self {
+        match value {
+            ParamValue::Complex(c) => c,
+            other => Complex32::new(f32::from_value(other), 0.0),
+        }
+    }
+}
+impl ParamType for i32 {
+    fn to_value(&self) -> ParamValue {
+        ParamValue::Int(*self)
+    }
+    fn from_value(value: ParamValue) -> Self {
+        match value {
+            ParamValue::Int(i) => i,
+            other => f32::from_value(other).round() as i32,
+        }
+    }
+}
+impl ParamType for usize {
+    fn to_value(&self) -> ParamValue {
+        ParamValue::Int(*self as i32)
+    }
+    fn from_value(value: ParamValue) -> Self {
+        i32::from_value(value).max(0) as usize
+    }
+}
+impl ParamType for bool {
+    fn to_value(&self) -> ParamValue {
+        ParamValue::Bool(*self)
+    }
+    fn from_value(value: ParamValue) -> Self {
+        match value {
+            ParamValue::Bool(b) => b,
+            other => f32::from_value(other) != 0.0,
+        }
+    }
+}
+
+/// Something that can be glided between values by `Smoothed`. Values get
+/// mapped to a point in some 2D space, and smoothed there.
+pub trait Smoothable: ParamType + Copy {
+    fn to_space(self, space: ComplexSpace) -> [f32; 2];
+    fn from_space(v: [f32; 2], space: ComplexSpace) -> Self;
+}
+impl Smoothable for f32 {
+    fn to_space(self, _space: ComplexSpace) -> [f32; 2] {
+        [self, 0.0]
+    }
+    fn from_space(v: [f32; 2], _space: ComplexSpace) -> Self {
+        v[0]
+    }
+}
+impl Smoothable for Complex32 {
+    fn to_space(self, space: ComplexSpace) -> [f32; 2] {
+        match space {
+            ComplexSpace::Cartesian => [self.re, self.im],
+            ComplexSpace::Polar => {
+                let (r, theta) = self.to_polar();
+                [r, theta]
+            }
+        }
+    }
+    fn from_space(v: [f32; 2], space: ComplexSpace) -> Self {
+        match space {
+            ComplexSpace::Cartesian => Complex32::new(v[0], v[1]),
+            ComplexSpace::Polar => Complex32::from_polar(v[0], v[1]),
+        }
+    }
+}
+
+/// A parameter that glides to new values (per its descriptor's `smoothing`)
+/// rather than jumping, to avoid zipper noise. Call `next` once per sample
+/// in `process`.
+#[derive(Clone, Debug)]
+pub struct Smoothed<T> {
+    target: T,
+    smoothing: Option<Smoothing>,
+
+    // what we sit at once there's nothing left to do: `target`, or where
+    // the last track left us
+    end: T,
+    // per-sample values from modulation or automation, played out by `next`
+    // instead of gliding to `target`; kept around so its room gets reused
+    track: Vec<T>,
+    track_pos: usize,
+    // where we are, in the smoothing space
+    current: [f32; 2],
+    target_point: [f32; 2],
+    // set when the target changes; we need the sample rate to set up the ramp
+    retarget: bool,
+    curve: SmoothingCurve,
+    step: [f32; 2],
+    remaining: usize,
+}
+impl<T: Smoothable> Smoothed<T> {
+    pub fn new(value: T) -> Smoothed<T> {
+        let point = value.to_space(ComplexSpace::Cartesian);
+        Smoothed {
+            target: value,
+            smoothing: None,
+            end: value,
+            track: Vec::new(),
+            track_pos: 0,
+            current: point,
+            target_point: point,
+            retarget: false,
+            curve: SmoothingCurve::Linear,
+            step: [0.0; 2],
+            remaining: 0,
+        }
+    }
+
+    /// where we're headed
+    pub fn target(&self) -> T {
+        self.target
+    }
+
+    fn space(&self) -> ComplexSpace {
+        self.smoothing.map(|s| s.space).unwrap_or_default()
+    }
+
+    fn start_ramp(&mut self, to: T, samples: f32, curve: SmoothingCurve) {
+        let space = self.space();
+        let mut point = to.to_space(space);
+        if space == ComplexSpace::Polar {
+            // go the short way around
+            point[1] = self.current[1] + crate::math::clean_angle_radians(point[1] - self.current[1]);
+        }
+        self.end = to;
+        self.target_point = point;
+        self.curve = curve;
+        self.remaining = samples as usize;
+        self.step = [
+            (point[0] - self.current[0]) / samples,
+            (point[1] - self.current[1]) / samples,
+        ];
+    }
+
+    /// Advances by one sample and returns the current value.
+    pub fn next(&mut self, sample_rate: f32) -> T {
+        if let Some(&value) = self.track.get(self.track_pos) {
+            self.track_pos += 1;
+            self.end = value;
+            self.current = value.to_space(self.space());
+            return value;
+        }
+
+        if std::mem::take(&mut self.retarget) {
+            match self.smoothing {
+                Some(smoothing) => {
+                    let samples = (smoothing.time * sample_rate).max(1.0);
+                    self.start_ramp(self.target, samples, smoothing.curve);
+                }
+                None => {
+                    self.end = self.target;
+                    self.current = self.target.to_space(self.space());
+                    self.remaining = 0;
+                }
+            }
+        }
+
+        if self.remaining == 0 {
+            return self.end;
         }
+        self.remaining -= 1;
+
+        match self.curve {
+            SmoothingCurve::Linear => {
+                self.current[0] += self.step[0];
+                self.current[1] += self.step[1];
+            }
+            SmoothingCurve::OnePole => {
+                let samples = self.smoothing.map_or(1.0, |s| (s.time * sample_rate).max(1.0));
+                let coeff = (-1.0 / samples).exp();
+                for i in 0..2 {
+                    self.current[i] =
+                        self.target_point[i] + (self.current[i] - self.target_point[i]) * coeff;
+                }
+                // a one-pole never quite gets there; call it done once close
+                let close = (0..2).all(|i| (self.current[i] - self.target_point[i]).abs() < 1e-5);
+                self.remaining = if close { 0 } else { self.remaining.max(1) };
+            }
+        }
+        if self.remaining == 0 {
+            self.current = self.target_point;
+            return self.end;
+        }
+
+        T::from_space(self.current, self.space())
+    }
+}
+impl<T: Smoothable + Default> Default for Smoothed<T> {
+    fn default() -> Self {
+        Smoothed::new(T::default())
+    }
+}
+impl<T: Smoothable> ParamType for Smoothed<T> {
+    fn to_value(&self) -> ParamValue {
+        self.target.to_value()
+    }
+    fn from_value(value: ParamValue) -> Self {
+        Smoothed::new(T::from_value(value))
+    }
+    fn set_from_value(&mut self, value: ParamValue, smoothing: Option<Smoothing>) {
+        let space = smoothing.map(|s| s.space).unwrap_or_default();
+        if self.smoothing.map(|s| s.space).unwrap_or_default() != space {
+            // (re)express where we are in the new space
+            let here = T::from_space(
+                self.current,
+                self.smoothing.map(|s| s.space).unwrap_or_default(),
+            );
+            self.current = here.to_space(space);
+        }
+        self.smoothing = smoothing;
+        self.target = T::from_value(value);
+        self.retarget = true;
+        // glides from wherever the track left us
+        self.track.clear();
+    }
+    fn follow(&mut self, values: &[ParamValue]) -> bool {
+        // this replaces any glide to `target`; if nothing drives us again
+        // next block, `set_from_value` sends us back there
+        self.retarget = false;
+        self.remaining = 0;
+        self.track.clear();
+        self.track.extend(values.iter().map(|&v| T::from_value(v)));
+        self.track_pos = 0;
+        true
+    }
+}
+
+/// A plain enum usable as a parameter. Implement it with `enum_param!`.
+pub trait EnumParam: Copy {
+    const VARIANTS: &'static [&'static str];
+    fn index(self) -> usize;
+    fn from_index(idx: usize) -> Self;
+}
+
+/// Implements `EnumParam` and `ParamType` for a fieldless enum:
+/// `enum_param!(Slope { Rising => "Rising", Falling => "Falling" });`
+#[macro_export]
+macro_rules! enum_param {
+    ($ty:ident { $($variant:ident => $label:literal),+ $(,)? }) => {
+        impl $crate::param::EnumParam for $ty {
+            const VARIANTS: &'static [&'static str] = &[$($label),+];
+            fn index(self) -> usize {
+                [$($ty::$variant),+].iter().position(|&v| v == self).unwrap()
+            }
+            fn from_index(idx: usize) -> Self {
+                let all = [$($ty::$variant),+];
+                all[idx.min(all.len() - 1)]
+            }
+        }
+        impl $crate::param::ParamType for $ty {
+            fn to_value(&self) -> $crate::param::ParamValue {
+                $crate::param::ParamValue::Enum($crate::param::EnumParam::index(*self))
+            }
+            fn from_value(value: $crate::param::ParamValue) -> Self {
+                <$ty as $crate::param::EnumParam>::from_index(
+                    <usize as $crate::param::ParamType>::from_value(value))
+            }
+        }
+    };
+}
+
+/// Parameter access, by index into `param_descriptors()`.
+pub trait Params {
+    /// Cheap enough to call from `process`: nothing gets built per call.
+    fn param_descriptors(&self) -> &[ParamDescriptor];
+    fn param(&self, idx: usize) -> ParamValue;
+    /// `value` has already been `clamp`ed to the descriptor
+    fn set_param(&mut self, idx: usize, value: ParamValue);
+    /// Drives a parameter from modulation or automation for the coming
+    /// block, before `process`: `values` holds its (clamped) value for every
+    /// sample. Returns whether the node plays them out itself, which
+    /// `Smoothed` fields do. If not, the engine splits the block where the
+    /// value changes and `set_param`s each piece's value before processing
+    /// it. Either way it puts the base value back with `set_param` after.
+    fn modulate_param(&mut self, _idx: usize, _values: &[ParamValue]) -> bool {
+        false
+    }
+
+    fn param_index(&self, name: &str) -> Option<usize> {
+        self.param_descriptors().iter().position(|d| d.name == name)
+    }
+}
+
+/// Implements `Params` for a node by listing which fields are parameters:
+///
+/// ```ignore
+/// params!(LinearNode {
+///     m => ParamDescriptor::complex("m", 0.0..=1024.0).logarithmic(),
+///     b => ParamDescriptor::complex("b", 0.0..=1024.0).logarithmic(),
+/// });
+/// ```
+///
+/// Fields can be anything implementing `ParamType`, or an element of an
+/// array of those (`scales[0] => ...`).
+#[macro_export]
+macro_rules! params {
+    ($ty:ty { $($field:ident $([$idx:literal])? => $desc:expr),* $(,)? }) => {
+        impl $crate::param::Params for $ty {
+            fn param_descriptors(&self) -> &[$crate::param::ParamDescriptor] {
+                // they can't depend on `self`, so one list does for every node of the type
+                static DESCRIPTORS: std::sync::OnceLock<Vec<$crate::param::ParamDescriptor>> =
+                    std::sync::OnceLock::new();
+                DESCRIPTORS.get_or_init(|| vec![$($desc),*])
+            }
+
+            #[allow(unused_assignments, unused_mut, unused_variables)]
+            fn param(&self, idx: usize) -> $crate::param::ParamValue {
+                let mut i = 0;
+                $(
+                    if idx == i {
+                        return $crate::param::ParamType::to_value(&self.$field $([$idx])?);
+                    }
+                    i += 1;
+                )*
+                panic!("no parameter #{idx}")
+            }
+
+            #[allow(unused_assignments, unused_mut, unused_variables)]
+            fn set_param(&mut self, idx: usize, value: $crate::param::ParamValue) {
+                let smoothing = $crate::param::Params::param_descriptors(self)[idx].smoothing;
+                let mut i = 0;
+                $(
+                    if idx == i {
+                        $crate::param::ParamType::set_from_value(
+                            &mut self.$field $([$idx])?, value, smoothing);
+                        return;
+                    }
+                    i += 1;
+                )*
+                panic!("no parameter #{idx}")
+            }
+
+            #[allow(unused_assignments, unused_mut, unused_variables)]
+            fn modulate_param(&mut self, idx: usize, values: &[$crate::param::ParamValue]) -> bool {
+                let mut i = 0;
+                $(
+                    if idx == i {
+                        return $crate::param::ParamType::follow(&mut self.$field $([$idx])?, values);
+                    }
+                    i += 1;
+                )*
+                panic!("no parameter #{idx}")
+            }
+        }
+    };
+}
+
+/// A node's parameters by name, as found in patches, presets and snapshots.
+pub type ParamSet = BTreeMap<String, ParamValue>;
+
+pub fn capture(node: &(impl Params + ?Sized)) -> ParamSet {
+    node.param_descriptors()
+        .iter()
+        .enumerate()
+        .map(|(idx, descriptor)| (descriptor.name.to_string(), node.param(idx)))
+        .collect()
+}
+
+/// Sets every parameter in `params` that `node` has; the rest are ignored.
+pub fn apply(node: &mut (impl Params + ?Sized), params: &ParamSet) {
+    for (name, &value) in params {
+        if let Some(idx) = node.param_index(name) {
+            set_param_clamped(node, idx, value);
+        }
+    }
+}
+
+/// Goes `t` of the way from `a` to `b`. Complex values move in polar form
+/// (the short way around), so a gain doesn't dip in magnitude on the way
+/// to a different phase; enums and bools just switch halfway.
+pub fn interpolate(a: ParamValue, b: ParamValue, t: f32) -> ParamValue {
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    match (a, b) {
+        (ParamValue::Real(a), ParamValue::Real(b)) => ParamValue::Real(lerp(a, b)),
+        (ParamValue::Complex(a), ParamValue::Complex(b)) => {
+            let (ra, theta_a) = a.to_polar();
+            let (rb, theta_b) = b.to_polar();
+            let theta = theta_a + crate::math::clean_angle_radians(theta_b - theta_a) * t;
+            ParamValue::Complex(Complex32::from_polar(lerp(ra, rb), theta))
+        }
+        (ParamValue::Int(a), ParamValue::Int(b)) => {
+            ParamValue::Int(lerp(a as f32, b as f32).round() as i32)
+        }
+        (a, b) => {
+            if t < 0.5 {
+                a
+            } else {
+                b
+            }
+        }
+    }
+}
+
+/// Sets parameter `idx` of `node`, clamping it to its declared range first.
+pub fn set_param_clamped(node: &mut (impl Params + ?Sized), idx: usize, value: ParamValue) {
+    let Some(value) = node.param_descriptors().get(idx).map(|d| d.clamp(value)) else {
+        return;
     };
+    node.set_param(idx, value);
+}
+
+#[derive(Debug, Default)]
+pub struct ParamsResponse {
+    /// which parameters were changed, and what to
+    pub edits: Vec<(usize, ParamValue)>,
+    /// the parameter whose "expose as input" was toggled, if any
+    pub toggle_exposed: Option<usize>,
+}
+
+/// Draws an editor for every parameter of `node`, leaving it to the caller
+/// to apply the edits (see `node::edit_param`). Right-clicking a
+/// parameter's name offers to expose it as an input; `is_exposed` says
+/// which ones already are.
+pub fn params_ui(
+    ui: &mut egui::Ui,
+    node: &(impl Params + ?Sized),
+    is_exposed: impl Fn(&str) -> bool,
+) -> ParamsResponse {
+    let mut response = ParamsResponse::default();
+    for (idx, descriptor) in node.param_descriptors().iter().enumerate() {
+        let mut value = node.param(idx);
+        let exposed = is_exposed(&descriptor.name);
+        let (changed, toggled) = param_ui(ui, descriptor, exposed, &mut value);
+        if changed {
+            response.edits.push((idx, descriptor.clamp(value)));
+        }
+        if toggled {
+            response.toggle_exposed = Some(idx);
+        }
+    }
+    response
 }
 
-param!(struct Foob: FoobParam {
-    z: f32,
-    y: u8
-});
-*/
\ No newline at end of file
+/// returns (value changed, exposed toggled)
+fn param_ui(
+    ui: &mut egui::Ui,
+    descriptor: &ParamDescriptor,
+    exposed: bool,
+    value: &mut ParamValue,
+) -> (bool, bool) {
+    let logarithmic = descriptor.scaling == Scaling::Logarithmic;
+    let name = descriptor.name.as_ref();
+    let unit = descriptor.unit.as_ref();
+
+    ui.horizontal(|ui| {
+        // exposed ones are marked like their sockets
+        let label = if exposed { format!("~{name}") } else { name.to_owned() };
+        let mut toggled = false;
+        ui.add(egui::Label::new(egui::RichText::new(label).monospace()).sense(egui::Sense::click()))
+            .context_menu(|ui| {
+                let text = if exposed { "Unexpose" } else { "Expose as input" };
+                if ui.button(text).clicked() {
+                    toggled = true;
+                    ui.close_menu();
+                }
+            });
+
+        let changed = match &descriptor.kind {
+            ParamKind::Real { range } => {
+                let mut x = f32::from_value(*value);
+                let r = ui.add(
+                    egui::Slider::new(&mut x, range.clone())
+                        .logarithmic(logarithmic)
+                        .suffix(unit),
+                );
+                *value = ParamValue::Real(x);
+                r.changed()
+            }
+            ParamKind::Complex { magnitude } => {
+                let (mut r, mut theta) = Complex32::from_value(*value).to_polar();
+                let r_changed = ui
+                    .add(
+                        egui::Slider::new(&mut r, magnitude.clone())
+                            .logarithmic(logarithmic)
+                            .suffix(unit),
+                    )
+                    .changed();
+                let theta_changed = ui.drag_angle(&mut theta).changed();
+                *value = ParamValue::Complex(Complex32::from_polar(r, theta));
+                r_changed || theta_changed
+            }
+            ParamKind::Int { range } => {
+                let mut i = i32::from_value(*value);
+                let r = ui.add(
+                    egui::DragValue::new(&mut i)
+                        .clamp_range(range.clone())
+                        .suffix(unit),
+                );
+                *value = ParamValue::Int(i);
+                r.changed()
+            }
+            ParamKind::Enum { variants } => {
+                let mut selected = usize::from_value(*value);
+                let before = selected;
+                egui::ComboBox::from_id_source(name)
+                    .selected_text(variants.get(selected).map_or("?", |v| v.as_ref()))
+                    .show_ui(ui, |ui| {
+                        for (i, variant) in variants.iter().enumerate() {
+                            ui.selectable_value(&mut selected, i, variant.as_ref());
+                        }
+                    });
+                *value = ParamValue::Enum(selected);
+                selected != before
+            }
+            ParamKind::Bool => {
+                let mut b = bool::from_value(*value);
+                let r = ui.checkbox(&mut b, "");
+                *value = ParamValue::Bool(b);
+                r.changed()
+            }
+        };
+        (changed, toggled)
+    })
+    .inner
+}
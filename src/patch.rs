@@ -0,0 +1,266 @@
+//! Saving and loading whole graphs ("patches") as RON.
+
+use std::path::Path;
+
+use anyhow::Context;
+
+use serde::{Deserialize, Serialize};
+
+use crate::automation::Automation;
+use crate::graph::{GraphError, NodeGraph, NodeKey, SideTables};
+use crate::monitor::Faults;
+use crate::node::QuadioNode;
+use crate::preset::Snapshots;
+use crate::probe::Probe;
+use crate::profile::Profile;
+
+pub type Patch = NodeGraph<Box<dyn QuadioNode>, PatchTables>;
+
+/// Everything Quadio keeps about a patch's nodes besides the graph itself.
+/// Automation and snapshots get saved with it; the rest is just for while
+/// it's playing.
+#[derive(Default, Serialize, Deserialize)]
+pub struct PatchTables {
+    #[serde(default)]
+    automation: Automation,
+    #[serde(default)]
+    snapshots: Snapshots,
+
+    #[serde(skip)]
+    probe: Probe,
+    #[serde(skip)]
+    faults: Faults,
+    #[serde(skip)]
+    profile: Profile,
+}
+impl SideTables for PatchTables {
+    fn remove_node(&mut self, node: NodeKey) {
+        self.automation.remove_node(node);
+        self.snapshots.remove_node(node);
+        self.probe.remove_node(node);
+        self.faults.remove_node(node);
+        self.profile.remove_node(node);
+    }
+}
+
+impl Patch {
+    pub fn automation(&self) -> &Automation {
+        &self.tables().automation
+    }
+    pub fn automation_mut(&mut self) -> &mut Automation {
+        &mut self.tables_mut().automation
+    }
+
+    pub fn snapshots(&self) -> &Snapshots {
+        &self.tables().snapshots
+    }
+    pub fn snapshots_mut(&mut self) -> &mut Snapshots {
+        &mut self.tables_mut().snapshots
+    }
+
+    pub fn probe(&self) -> &Probe {
+        &self.tables().probe
+    }
+    pub fn probe_mut(&mut self) -> &mut Probe {
+        &mut self.tables_mut().probe
+    }
+
+    pub fn faults(&self) -> &Faults {
+        &self.tables().faults
+    }
+    pub fn faults_mut(&mut self) -> &mut Faults {
+        &mut self.tables_mut().faults
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.tables().profile
+    }
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.tables_mut().profile
+    }
+}
+
+pub fn to_string(graph: &Patch) -> anyhow::Result<String> {
+    Ok(ron::ser::to_string_pretty(graph, ron::ser::PrettyConfig::default())?)
+}
+
+/// Parses a patch, putting right whatever doesn't add up (see
+/// `NodeGraph::repair`) and complaining about it on stderr.
+pub fn from_str(s: &str) -> anyhow::Result<Patch> {
+    let (graph, problems) = from_str_checked(s)?;
+    for e in problems {
+        eprintln!("patch had to be repaired: {e}");
+    }
+    Ok(graph)
+}
+
+/// Like `from_str`, but hands back what had to be repaired instead of
+/// printing it.
+pub fn from_str_checked(s: &str) -> anyhow::Result<(Patch, Vec<GraphError>)> {
+    let mut graph: Patch = ron::from_str(s)?;
+    let problems = graph.repair();
+    Ok((graph, problems))
+}
+
+pub fn save(graph: &Patch, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    std::fs::write(path, to_string(graph)?)
+        .with_context(|| format!("couldn't write {}", path.display()))
+}
+
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Patch> {
+    let path = path.as_ref();
+    let s = std::fs::read_to_string(path)
+        .with_context(|| format!("couldn't read {}", path.display()))?;
+    from_str(&s).with_context(|| format!("couldn't parse {}", path.display()))
+}
+
+/// Like `load`, but hands back what had to be repaired instead of printing it.
+pub fn load_checked(path: impl AsRef<Path>) -> anyhow::Result<(Patch, Vec<GraphError>)> {
+    let path = path.as_ref();
+    let s = std::fs::read_to_string(path)
+        .with_context(|| format!("couldn't read {}", path.display()))?;
+    from_str_checked(&s).with_context(|| format!("couldn't parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::graph::NodeLayout;
+    use crate::param::ParamValue;
+
+    #[derive(Clone, Debug)]
+    struct RandomNode {
+        type_idx: usize,
+        params: Vec<f32>,
+        pos: [f32; 2],
+        title: Option<String>,
+    }
+
+    fn random_node() -> impl Strategy<Value = RandomNode> {
+        (
+            any::<usize>(),
+            prop::collection::vec(-100.0f32..100.0, 0..8),
+            any::<[f32; 2]>().prop_filter("finite", |p| p.iter().all(|x| x.is_finite())),
+            prop::option::of("[a-z ]{1,8}"),
+        )
+            .prop_map(|(type_idx, params, pos, title)| RandomNode {
+                type_idx,
+                params,
+                pos,
+                title,
+            })
+    }
+
+    fn build(nodes: &[RandomNode], wires: &[(usize, usize, usize, usize)]) -> Patch {
+        let types: Vec<_> = crate::registry::registry().types().copied().collect();
+        let mut graph = Patch::default();
+        let mut keys = vec![];
+        for spec in nodes {
+            let mut node = types[spec.type_idx % types.len()].make();
+            for (idx, &x) in spec.params.iter().enumerate().take(node.param_descriptors().len()) {
+                crate::param::set_param_clamped(&mut *node, idx, ParamValue::Real(x));
+            }
+            let layout = NodeLayout {
+                title: spec.title.clone(),
+                ..NodeLayout::at(spec.pos)
+            };
+            keys.push(graph.add_node_with_layout(node, layout));
+        }
+        graph.refresh_descriptors();
+        if !keys.is_empty() {
+            for &(a, i, b, j) in wires {
+                let _ = graph.connect((keys[a % keys.len()], i), (keys[b % keys.len()], j));
+            }
+        }
+        graph
+    }
+
+    fn wires(graph: &Patch) -> HashSet<((NodeKey, usize), (NodeKey, usize))> {
+        graph.wires().collect()
+    }
+
+    use crate::graph::NodeKey;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn round_trips(
+            nodes in prop::collection::vec(random_node(), 0..12),
+            wires_spec in prop::collection::vec((any::<usize>(), 0..4usize, any::<usize>(), 0..4usize), 0..24),
+        ) {
+            let graph = build(&nodes, &wires_spec);
+            let saved = to_string(&graph).unwrap();
+            let loaded = from_str(&saved).unwrap();
+
+            prop_assert_eq!(loaded.validate(), vec![]);
+            prop_assert_eq!(wires(&loaded), wires(&graph));
+            let keys: Vec<_> = graph.nodes().map(|(k, _)| k).collect();
+            prop_assert_eq!(loaded.nodes().map(|(k, _)| k).collect::<Vec<_>>(), keys.clone());
+            for key in keys {
+                prop_assert_eq!(
+                    crate::param::capture(&**loaded.get_node(key)),
+                    crate::param::capture(&**graph.get_node(key))
+                );
+                prop_assert_eq!(
+                    format!("{:?}", loaded.layout(key)),
+                    format!("{:?}", graph.layout(key))
+                );
+                prop_assert_eq!(
+                    format!("{:?}", loaded.node_descriptor(key)),
+                    format!("{:?}", graph.node_descriptor(key))
+                );
+            }
+            // and once more makes no difference
+            let again = from_str(&to_string(&loaded).unwrap()).unwrap();
+            prop_assert_eq!(wires(&again), wires(&graph));
+        }
+    }
+    // no layouts at all, no descriptor for the output, and wires to a socket
+    // that isn't there and from a node that isn't there
+    const MALFORMED: &str = r#"(
+        nodes: [
+            (value: None, version: 0),
+            (value: Some((type: "phasor", params: {"f_mul": Real(2.0)})), version: 1),
+            (value: Some((type: "output", params: {})), version: 1),
+        ],
+        descriptors: [
+            (value: None, version: 0),
+            (value: Some((
+                input_sockets: [(label: "Mod", ty: Complex, default: (0.0, 0.0))],
+                output_sockets: [(label: "Out", ty: Complex, default: (0.0, 0.0))],
+            )), version: 1),
+        ],
+        wires_by_destination: {
+            ((idx: 2, version: 1), 0): ((idx: 1, version: 1), 0),
+            ((idx: 2, version: 1), 3): ((idx: 1, version: 1), 0),
+            ((idx: 1, version: 1), 0): ((idx: 7, version: 1), 0),
+        },
+        input_values: {((idx: 2, version: 1), 9): (1.0, 0.0)},
+    )"#;
+
+    #[test]
+    fn malformed_patches_are_repaired() {
+        let (graph, problems) = from_str_checked(MALFORMED).unwrap();
+        assert!(!problems.is_empty());
+        assert_eq!(graph.validate(), vec![]);
+
+        let keys: Vec<_> = graph.nodes().map(|(k, _)| k).collect();
+        let [phasor, output] = keys[..] else { panic!("expected two nodes, got {keys:?}") };
+        assert_eq!(wires(&graph), HashSet::from([((output, 0), (phasor, 0))]));
+        assert_eq!(graph.node_descriptor(output).input_sockets.len(), 1);
+        // layouts got filled in, so this doesn't trip over them
+        let mut graph = graph;
+        assert_eq!(graph.nodes_with_layout_mut().count(), 2);
+
+        // and the repaired patch comes back clean
+        let (again, problems) = from_str_checked(&to_string(&graph).unwrap()).unwrap();
+        assert_eq!(problems, vec![]);
+        assert_eq!(wires(&again), wires(&graph));
+    }
+}
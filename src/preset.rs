@@ -0,0 +1,173 @@
+//! Per-node-type preset libraries, and whole-graph parameter snapshots that
+//! can be morphed between.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{NodeKey};
+use crate::patch::Patch;
+use crate::param::ParamSet;
+
+/// Named parameter sets on disk, one directory per node type:
+/// `<dir>/<type id>/<name>.ron`.
+pub struct PresetLibrary {
+    dir: PathBuf,
+}
+impl Default for PresetLibrary {
+    fn default() -> Self {
+        PresetLibrary::new("presets")
+    }
+}
+impl PresetLibrary {
+    pub fn new(dir: impl Into<PathBuf>) -> PresetLibrary {
+        PresetLibrary { dir: dir.into() }
+    }
+
+    fn path(&self, type_id: &str, name: &str) -> PathBuf {
+        self.dir.join(type_id).join(name).with_extension("ron")
+    }
+
+    /// The presets saved for `type_id`, sorted. Empty if there aren't any
+    /// (or the directory can't be read).
+    pub fn list(&self, type_id: &str) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(self.dir.join(type_id)) else {
+            return vec![];
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn save(&self, type_id: &str, name: &str, params: &ParamSet) -> anyhow::Result<()> {
+        let path = self.path(type_id, name);
+        let s = ron::ser::to_string_pretty(params, ron::ser::PrettyConfig::default())?;
+        std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| std::fs::write(&path, s))
+            .with_context(|| format!("couldn't write {}", path.display()))
+    }
+
+    pub fn load(&self, type_id: &str, name: &str) -> anyhow::Result<ParamSet> {
+        let path = self.path(type_id, name);
+        let s = std::fs::read_to_string(&path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        ron::from_str(&s).with_context(|| format!("couldn't parse {}", path.display()))
+    }
+}
+
+pub const SNAPSHOT_NAMES: [&str; 4] = ["A", "B", "C", "D"];
+
+/// Up to four captures of every node's parameters, and where the morph
+/// slider sits between them (0 is A, 3 is D).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Snapshots {
+    slots: [Option<HashMap<NodeKey, ParamSet>>; 4],
+    morph: f32,
+}
+impl Snapshots {
+    pub fn is_stored(&self, slot: usize) -> bool {
+        self.slots[slot].is_some()
+    }
+    pub fn clear(&mut self, slot: usize) {
+        self.slots[slot] = None;
+    }
+    pub fn morph(&self) -> f32 {
+        self.morph
+    }
+    /// forgets `node` in every snapshot
+    pub fn remove_node(&mut self, node: NodeKey) {
+        for slot in self.slots.iter_mut().flatten() {
+            slot.remove(&node);
+        }
+    }
+}
+
+/// Captures every node's parameters into snapshot `slot`.
+pub fn store_snapshot(graph: &mut Patch, slot: usize) {
+    let snapshot = graph
+        .nodes()
+        .map(|(key, node)| (key, crate::param::capture(&**node)))
+        .collect();
+    graph.snapshots_mut().slots[slot] = Some(snapshot);
+}
+
+/// Sets every node's parameters to `position` of the way along A-B-C-D.
+/// Missing snapshots are skipped over: between A and an empty B is just A.
+pub fn morph_to(graph: &mut Patch, position: f32) {
+    let position = position.clamp(0.0, (SNAPSHOT_NAMES.len() - 1) as f32);
+    let snapshots = graph.snapshots_mut();
+    snapshots.morph = position;
+
+    let lo = position.floor() as usize;
+    let hi = (lo + 1).min(SNAPSHOT_NAMES.len() - 1);
+    let t = position - lo as f32;
+    let (a, b) = match (&snapshots.slots[lo], &snapshots.slots[hi]) {
+        (Some(a), Some(b)) => (a, b),
+        (Some(only), None) | (None, Some(only)) => (only, only),
+        (None, None) => return,
+    };
+
+    let morphed: Vec<(NodeKey, ParamSet)> = a
+        .iter()
+        .map(|(&key, params_a)| {
+            let params = params_a
+                .iter()
+                .map(|(name, &value_a)| {
+                    let value = match b.get(&key).and_then(|params_b| params_b.get(name)) {
+                        Some(&value_b) => crate::param::interpolate(value_a, value_b, t),
+                        None => value_a,
+                    };
+                    (name.clone(), value)
+                })
+                .collect();
+            (key, params)
+        })
+        .collect();
+
+    for (key, params) in morphed {
+        if graph.contains_node(key) {
+            crate::param::apply(&mut **graph.get_node_mut(key), &params);
+        }
+    }
+}
+
+/// Store buttons for each snapshot and the morph slider.
+pub fn snapshots_ui(ui: &mut egui::Ui, graph: &mut Patch) {
+    ui.horizontal(|ui| {
+        ui.label("Snapshots");
+        for (slot, name) in SNAPSHOT_NAMES.iter().enumerate() {
+            let text = if graph.snapshots().is_stored(slot) {
+                egui::RichText::new(*name).strong()
+            } else {
+                egui::RichText::new(*name).weak()
+            };
+            let r = ui
+                .button(text)
+                .on_hover_text("click to store, right-click to clear");
+            if r.clicked() {
+                store_snapshot(graph, slot);
+            } else if r.secondary_clicked() {
+                graph.snapshots_mut().clear(slot);
+            }
+        }
+    });
+
+    let mut morph = graph.snapshots().morph();
+    let max = (SNAPSHOT_NAMES.len() - 1) as f64;
+    let slider = egui::Slider::new(&mut morph, 0.0..=max as f32)
+        .text("morph")
+        .custom_formatter(|x, _| {
+            let lo = x.floor().min(max - 1.0);
+            format!("{} {:.0}% {}", SNAPSHOT_NAMES[lo as usize], (x - lo) * 100.0, SNAPSHOT_NAMES[lo as usize + 1])
+        });
+    if ui.add(slider).changed() {
+        morph_to(graph, morph);
+    }
+}
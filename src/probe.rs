@@ -0,0 +1,109 @@
+//! Wire probes: hovering a wire in the editor shows what's going through it.
+//!
+//! The editor points the graph's `Probe` at the output socket the wire comes
+//! from, and the engine copies that one buffer in after every block. When
+//! nothing's hovered there's no target, and the engine doesn't copy anything.
+
+use std::collections::VecDeque;
+
+use crate::graph::NodeKey;
+use crate::sample::QuadioSample;
+
+/// How many of the latest samples a probe keeps (and draws).
+const PROBE_LEN: usize = 512;
+
+#[derive(Default)]
+pub struct Probe {
+    target: Option<(NodeKey, usize)>,
+    samples: VecDeque<QuadioSample>,
+}
+impl Probe {
+    /// The output socket being probed, if any.
+    pub fn target(&self) -> Option<(NodeKey, usize)> {
+        self.target
+    }
+
+    /// Starts probing an output socket, or stops probing with `None`.
+    pub fn set_target(&mut self, target: Option<(NodeKey, usize)>) {
+        if self.target != target {
+            self.target = target;
+            self.samples.clear();
+        }
+    }
+
+    /// Takes in a block of the target's output. Called by the engine.
+    pub fn tap(&mut self, block: &[QuadioSample]) {
+        self.samples.extend(block);
+        let excess = self.samples.len().saturating_sub(PROBE_LEN);
+        self.samples.drain(..excess);
+    }
+
+    pub fn remove_node(&mut self, node: NodeKey) {
+        if self.target.is_some_and(|(target, _)| target == node) {
+            self.set_target(None);
+        }
+    }
+
+    /// Largest magnitude of what's been taken in.
+    pub fn peak(&self) -> f32 {
+        self.samples.iter().map(|x| x.norm()).fold(0.0, f32::max)
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self.samples.iter().map(|x| x.norm_sqr()).sum();
+        (sum / self.samples.len() as f32).sqrt()
+    }
+}
+
+fn decibels(x: f32) -> f32 {
+    20.0 * x.max(1e-6).log10()
+}
+
+/// A little scope and level meter for what `probe` has taken in, for the
+/// tooltip of a hovered wire.
+pub fn probe_ui(ui: &mut egui::Ui, probe: &Probe) {
+    if probe.samples.is_empty() {
+        ui.weak("nothing yet (is this connected to the output?)");
+        return;
+    }
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(200.0, 80.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    painter.hline(rect.x_range(), rect.center().y, egui::Stroke::new(1.0, egui::Color32::from_gray(60)));
+
+    let n = probe.samples.len().max(2) - 1;
+    let trace = |f: fn(&QuadioSample) -> f32| -> Vec<egui::Pos2> {
+        probe.samples
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let y = f(x).clamp(-1.0, 1.0);
+                egui::pos2(
+                    rect.left() + rect.width() * i as f32 / n as f32,
+                    rect.center().y - y * rect.height() / 2.0,
+                )
+            })
+            .collect()
+    };
+    painter.add(egui::Shape::line(trace(|x| x.re), egui::Stroke::new(1.0, egui::Color32::from_rgb(230, 110, 90))));
+    painter.add(egui::Shape::line(trace(|x| x.im), egui::Stroke::new(1.0, egui::Color32::from_rgb(90, 160, 230))));
+
+    // -60..0 dB, rms filled in and peak as a tick
+    let (peak, rms) = (decibels(probe.peak()), decibels(probe.rms()));
+    let (meter, _) = ui.allocate_exact_size(egui::vec2(200.0, 8.0), egui::Sense::hover());
+    let meter_x = |db: f32| meter.left() + meter.width() * ((db + 60.0) / 60.0).clamp(0.0, 1.0);
+    let meter_color = if peak > 0.0 { ui.visuals().error_fg_color } else { egui::Color32::from_rgb(90, 190, 110) };
+    ui.painter().rect_filled(meter, 1.0, ui.visuals().extreme_bg_color);
+    ui.painter().rect_filled(
+        egui::Rect::from_x_y_ranges(meter.left()..=meter_x(rms), meter.y_range()),
+        1.0,
+        meter_color,
+    );
+    ui.painter().vline(meter_x(peak), meter.y_range(), egui::Stroke::new(2.0, meter_color));
+
+    ui.monospace(format!("peak {peak:6.1} dB  rms {rms:6.1} dB"));
+}
@@ -0,0 +1,201 @@
+//! Where the DSP time goes: the engine times every node's `process` each
+//! block, and keeps rolling averages of those and of the whole block, as a
+//! fraction of the time it had to render it in.
+
+use std::time::Duration;
+
+use crate::graph::{NodeKey};
+use crate::patch::Patch;
+use crate::node::node_name;
+
+/// Roughly how far back the averages look, in seconds.
+const AVERAGE_OVER: f64 = 1.0;
+
+#[derive(Default)]
+pub struct Profile {
+    // average seconds spent per block
+    nodes: slotmap::SecondaryMap<NodeKey, f64>,
+    // how long a block lasts, i.e. the time there is to render one
+    block_seconds: f64,
+    // average fraction of that spent rendering
+    load: Option<f64>,
+}
+
+fn average(average: &mut f64, x: f64, block_seconds: f64) {
+    let alpha = 1.0 - (-block_seconds / AVERAGE_OVER).exp();
+    *average += (x - *average) * alpha;
+}
+
+impl Profile {
+    /// Notes that `node` just took `elapsed` to process a block lasting
+    /// `block_seconds`. Called by the engine.
+    pub fn record_node(&mut self, node: NodeKey, elapsed: Duration, block_seconds: f64) {
+        let elapsed = elapsed.as_secs_f64();
+        match self.nodes.get_mut(node) {
+            Some(avg) => average(avg, elapsed, block_seconds),
+            None => {
+                self.nodes.insert(node, elapsed);
+            }
+        }
+        self.block_seconds = block_seconds;
+    }
+
+    /// Notes that a whole block took `elapsed`. Called by the engine.
+    pub fn record_block(&mut self, elapsed: Duration, block_seconds: f64) {
+        let load = elapsed.as_secs_f64() / block_seconds;
+        match &mut self.load {
+            Some(avg) => average(avg, load, block_seconds),
+            None => self.load = Some(load),
+        }
+        self.block_seconds = block_seconds;
+    }
+
+    /// Average time `node` takes per block, if it's been running.
+    pub fn node_time(&self, node: NodeKey) -> Option<Duration> {
+        self.nodes.get(node).map(|&t| Duration::from_secs_f64(t))
+    }
+
+    /// `node_time` as a fraction of the time there is per block.
+    pub fn node_load(&self, node: NodeKey) -> Option<f64> {
+        Some(self.nodes.get(node)? / self.block_seconds)
+    }
+
+    /// Average fraction of the time there is per block spent rendering it,
+    /// if blocks have been rendered. Over 1 means it can't keep up.
+    pub fn load(&self) -> Option<f64> {
+        self.load
+    }
+
+    pub fn remove_node(&mut self, node: NodeKey) {
+        self.nodes.remove(node);
+    }
+}
+
+fn format_time(t: Duration) -> String {
+    let micros = t.as_secs_f64() * 1e6;
+    if micros < 1000.0 {
+        format!("{micros:.1}µs")
+    } else {
+        format!("{:.2}ms", micros / 1000.0)
+    }
+}
+
+/// The little text on each node frame.
+pub fn badge(profile: &Profile, node: NodeKey) -> Option<String> {
+    let time = profile.node_time(node)?;
+    let load = profile.node_load(node)?;
+    Some(format!("{} {:.1}%", format_time(time), load * 100.0))
+}
+
+struct Row {
+    name: String,
+    type_name: &'static str,
+    time: Duration,
+    load: f64,
+}
+
+fn rows(graph: &Patch) -> Vec<Row> {
+    let profile = graph.profile();
+    graph
+        .nodes()
+        .filter_map(|(key, node)| {
+            Some(Row {
+                name: node_name(graph, key),
+                type_name: crate::registry::type_of(&**node).map_or("?", |ty| ty.name),
+                time: profile.node_time(key)?,
+                load: profile.node_load(key)?,
+            })
+        })
+        .collect()
+}
+
+/// A plain-text report, most expensive nodes first.
+pub fn report(graph: &Patch) -> String {
+    let mut rows = rows(graph);
+    rows.sort_by_key(|row| std::cmp::Reverse(row.time));
+
+    let mut text = String::new();
+    match graph.profile().load() {
+        Some(load) => text += &format!("  load {:.1}% of the block deadline\n", load * 100.0),
+        None => text += "  (nothing rendered)\n",
+    }
+    for row in rows {
+        text += &format!(
+            "  {:>10} {:>6.2}%  {} ({})\n",
+            format_time(row.time),
+            row.load * 100.0,
+            row.name,
+            row.type_name
+        );
+    }
+    text
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum SortBy {
+    Name,
+    Type,
+    #[default]
+    Time,
+}
+
+/// Overall load and a table of every node's time, for the side panel.
+/// Clicking a column's header sorts by it; clicking again flips the order.
+pub fn profile_ui(ui: &mut egui::Ui, graph: &Patch) {
+    let Some(load) = graph.profile().load() else {
+        ui.weak("not running");
+        return;
+    };
+    let text = format!("DSP load {:.1}%", load * 100.0);
+    if load > 1.0 {
+        ui.colored_label(ui.visuals().error_fg_color, text);
+    } else {
+        ui.label(text);
+    }
+
+    let sort_id = ui.id().with("profile_sort");
+    let (mut sort_by, mut descending) = ui.data_mut(|data| data.get_temp(sort_id).unwrap_or((SortBy::Time, true)));
+
+    let mut rows = rows(graph);
+    rows.sort_by(|a, b| match sort_by {
+        SortBy::Name => a.name.cmp(&b.name),
+        SortBy::Type => a.type_name.cmp(b.type_name),
+        SortBy::Time => a.time.cmp(&b.time),
+    });
+    if descending {
+        rows.reverse();
+    }
+
+    egui::ScrollArea::vertical().id_source("profile").max_height(200.0).show(ui, |ui| {
+        egui::Grid::new("profile").num_columns(4).striped(true).show(ui, |ui| {
+            for (column, label) in [(SortBy::Name, "node"), (SortBy::Type, "type"), (SortBy::Time, "time")] {
+                let arrow = match (column == sort_by, descending) {
+                    (false, _) => "",
+                    (true, true) => " ⬇",
+                    (true, false) => " ⬆",
+                };
+                if ui.selectable_label(column == sort_by, format!("{label}{arrow}")).clicked() {
+                    if column == sort_by {
+                        descending = !descending;
+                    } else {
+                        sort_by = column;
+                        // biggest first is what you want for times, A-Z for names
+                        descending = column == SortBy::Time;
+                    }
+                }
+            }
+            ui.strong("load");
+            ui.end_row();
+
+            for row in rows {
+                ui.monospace(row.name);
+                ui.label(row.type_name);
+                ui.monospace(format_time(row.time));
+                ui.monospace(format!("{:.2}%", row.load * 100.0));
+                ui.end_row();
+            }
+        });
+    });
+
+    ui.data_mut(|data| data.insert_temp(sort_id, (sort_by, descending)));
+}
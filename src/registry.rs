@@ -0,0 +1,158 @@
+//! Every kind of node Quadio can make. The Add menu, patch loading, the DSL,
+//! OSC and `quadio inspect` all go through here, so a node type registered
+//! with `register` (before any patches are loaded) works everywhere the
+//! built-in ones do.
+
+use std::any::TypeId;
+use std::sync::{OnceLock, RwLock, RwLockReadGuard};
+
+use crate::node::QuadioNode;
+
+#[derive(Clone, Copy)]
+pub struct NodeType {
+    /// what patches, the DSL, OSC and the CLI call the type, so it can't
+    /// change once there are patches using it
+    pub id: &'static str,
+    /// what the editor calls it
+    pub name: &'static str,
+    /// which submenu of the Add menu it's in
+    pub category: &'static str,
+    /// one line, shown when hovering over it in the Add menu
+    pub description: &'static str,
+    constructor: fn() -> Box<dyn QuadioNode>,
+    type_id: TypeId,
+}
+impl NodeType {
+    pub fn new<T: QuadioNode + Default>(
+        id: &'static str,
+        name: &'static str,
+        category: &'static str,
+        description: &'static str,
+    ) -> NodeType {
+        NodeType {
+            id,
+            name,
+            category,
+            description,
+            constructor: || Box::new(T::default()),
+            type_id: TypeId::of::<T>(),
+        }
+    }
+
+    /// A new node of this type, with default parameters.
+    pub fn make(&self) -> Box<dyn QuadioNode> {
+        (self.constructor)()
+    }
+}
+
+#[derive(Default)]
+pub struct NodeRegistry {
+    types: Vec<NodeType>,
+}
+impl NodeRegistry {
+    pub fn with_builtins() -> NodeRegistry {
+        use crate::node::*;
+        use crate::script::ScriptNode;
+        use crate::subgraph::{InletNode, OutletNode, SubgraphNode};
+
+        let mut registry = NodeRegistry::default();
+        for ty in [
+            NodeType::new::<PhasorNode>("phasor", "Phasor", "Sources", "a complex sinusoid spinning at a given frequency"),
+            NodeType::new::<SumNode>("sum", "Sum", "Math", "adds up to 16 inputs"),
+            NodeType::new::<ProductNode>("product", "Product", "Math", "multiplies two inputs"),
+            NodeType::new::<LinearNode>("linear", "Linear", "Math", "m * x + b, with complex m and b"),
+            NodeType::new::<QuadrantNode>("quadrant", "Quadrant", "Math", "scales the input differently in each quadrant"),
+            NodeType::new::<PhaseScaleNode>("phase_scale", "Phase Scale", "Phase", "multiplies the phase, keeping the magnitude"),
+            NodeType::new::<MagAngSwitchNode>("mag_ang_switch", "Mag-Ang Switch", "Phase", "swaps magnitude and angle"),
+            NodeType::new::<QuantizeNode>("quantize", "Quantize", "Phase", "rounds magnitude and phase to some number of bits"),
+            NodeType::new::<SlomoNode>("slo_mo", "Slo-Mo", "Phase", "smooths the phase, slowing down how fast it turns"),
+            NodeType::new::<ReImSplitNode>("re_im_split", "Re-Im Split", "Conversion", "splits into real and imaginary parts"),
+            NodeType::new::<ToRealNode>("to_real", "To Real", "Conversion", "takes the real part"),
+            NodeType::new::<ControlToAudioNode>("control_to_audio", "Control to Audio", "Conversion", "ramps a control signal up to audio rate"),
+            NodeType::new::<AudioToControlNode>("audio_to_control", "Audio to Control", "Conversion", "averages each block down to one control value"),
+            NodeType::new::<ControlToRealNode>("control_to_real", "Control to Real", "Conversion", "ramps a control signal up to a real audio-rate one"),
+            NodeType::new::<AudioToEventNode>("audio_to_event", "Audio to Event", "Conversion", "fires an event whenever the signal rises through a threshold"),
+            NodeType::new::<ControlToEventNode>("control_to_event", "Control to Event", "Conversion", "fires an event whenever the control value changes"),
+            NodeType::new::<EventToAudioNode>("event_to_audio", "Event to Audio", "Conversion", "holds the latest event's value"),
+            NodeType::new::<EventToRealNode>("event_to_real", "Event to Real", "Conversion", "holds the real part of the latest event's value"),
+            NodeType::new::<EventToControlNode>("event_to_control", "Event to Control", "Conversion", "holds the latest event's value, once per block"),
+            NodeType::new::<PassthruNode>("passthru", "Passthru", "Utility", "does nothing, handy for tidying up wires"),
+            NodeType::new::<ScopeNode>("scope", "Scope", "Utility", "draws the signal"),
+            NodeType::new::<SpectrumNode>("spectrum", "Spectrum", "Utility", "FFT of the signal, negative frequencies and all"),
+            NodeType::new::<OutputNode>("output", "Output", "Utility", "what's connected here is what you hear"),
+            NodeType::new::<ScriptNode>("script", "Script", "Custom", "DSP written in Rhai, right in the node"),
+            NodeType::new::<SubgraphNode>("subgraph", "Subgraph", "Custom", "a graph inside a node"),
+            NodeType::new::<InletNode>("inlet", "Inlet", "Custom", "an input socket of the subgraph it's in"),
+            NodeType::new::<OutletNode>("outlet", "Outlet", "Custom", "an output socket of the subgraph it's in"),
+        ] {
+            registry.register(ty);
+        }
+        registry
+    }
+
+    /// Adds `ty`, replacing any type already registered with the same id.
+    pub fn register(&mut self, ty: NodeType) {
+        match self.types.iter_mut().find(|t| t.id == ty.id) {
+            Some(existing) => *existing = ty,
+            None => self.types.push(ty),
+        }
+    }
+
+    /// Looks a type up by id, or by name: patches from before there were
+    /// ids saved names instead.
+    pub fn get(&self, id: &str) -> Option<&NodeType> {
+        self.types
+            .iter()
+            .find(|t| t.id == id)
+            .or_else(|| self.types.iter().find(|t| t.name == id))
+    }
+
+    /// What type `node` is, if it's a registered one.
+    pub fn type_of(&self, node: &dyn QuadioNode) -> Option<&NodeType> {
+        let type_id = (*node).type_id();
+        self.types.iter().find(|t| t.type_id == type_id)
+    }
+
+    /// In the order they were registered.
+    pub fn types(&self) -> impl Iterator<Item = &NodeType> {
+        self.types.iter()
+    }
+
+    /// Every category, in the order they first show up.
+    pub fn categories(&self) -> Vec<&'static str> {
+        let mut categories = vec![];
+        for ty in &self.types {
+            if !categories.contains(&ty.category) {
+                categories.push(ty.category);
+            }
+        }
+        categories
+    }
+}
+
+fn global() -> &'static RwLock<NodeRegistry> {
+    static REGISTRY: OnceLock<RwLock<NodeRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(NodeRegistry::with_builtins()))
+}
+
+/// The registry everything uses. Don't hold on to it for long (and in
+/// particular, not while making or loading nodes, which can need it too):
+/// copy the `NodeType` out instead.
+pub fn registry() -> RwLockReadGuard<'static, NodeRegistry> {
+    global().read().unwrap()
+}
+
+/// Makes a node type available everywhere; see `NodeRegistry::register`.
+pub fn register(ty: NodeType) {
+    global().write().unwrap().register(ty);
+}
+
+/// Shorthand for `registry().get(id).copied()`.
+pub fn lookup(id: &str) -> Option<NodeType> {
+    registry().get(id).copied()
+}
+
+/// Shorthand for `registry().type_of(node).copied()`.
+pub fn type_of(node: &dyn QuadioNode) -> Option<NodeType> {
+    registry().type_of(node).copied()
+}
@@ -0,0 +1,373 @@
+//! `ScriptNode`: a node whose processing is a Rhai script, so new DSP can be
+//! tried out without recompiling.
+//!
+//! A script is a handful of functions (top-level statements never run):
+//!
+//! ```text
+//! fn inputs() { ["In"] }                  // input socket labels
+//! fn outputs() { ["Out"] }                // output socket labels
+//! fn params() { [#{ name: "gain", min: 0.0, max: 4.0, init: 1.0 }] }
+//!
+//! // called once per block; `inputs` holds an array of samples per input
+//! // socket, and it returns one per output. `this` is a map that sticks
+//! // around between blocks (and reloads), for oscillator phases and such.
+//! fn process(inputs, params, sample_rate) {
+//!     let out = [];
+//!     for x in inputs[0] { out.push(x * params.gain); }
+//!     [out]
+//! }
+//! ```
+//!
+//! Only `process` is required; the others default to one input, one output
+//! and no parameters (a parameter's `min`, `max` and `init` default to 0, 1
+//! and `min`). Samples are `Complex` values with `re`/`im`, the usual
+//! arithmetic (with each other and with floats), `complex(re, im)`,
+//! `polar(r, theta)`, `abs`, `arg`, `conj`, `exp`.
+//!
+//! Scripts can't touch files or the network, can't `import` or `eval`, and
+//! get a fixed budget of operations per block, so a runaway loop shows up as
+//! an error on the node instead of hanging the audio thread.
+
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+
+use num_complex::Complex32;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, Map, Scope, AST, FLOAT};
+
+use crate::audio::AudioContext;
+use crate::graph::{self, NodeDescriptor, SocketDescriptor};
+use crate::node::QuadioNode;
+use crate::param::{ParamDescriptor, ParamType, ParamValue, Params};
+use crate::sample::QuadioSample;
+
+const DEFAULT_SCRIPT: &str = r#"fn inputs() { ["In"] }
+fn outputs() { ["Out"] }
+fn params() { [#{ name: "gain", min: 0.0, max: 4.0, init: 1.0 }] }
+
+fn process(inputs, params, sample_rate) {
+    let out = [];
+    for x in inputs[0] {
+        out.push(x * params.gain);
+    }
+    [out]
+}
+"#;
+
+// plenty for a per-sample loop over a block, but a `loop {}` still ends
+const MAX_OPERATIONS_PER_BLOCK: u64 = 2_000_000;
+const MAX_SOCKETS: usize = 16;
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new())
+            .disable_symbol("eval")
+            // there's nowhere for these to go, and stdout from the audio thread is a bad idea anyway
+            .on_print(|_| {})
+            .on_debug(|_, _, _| {})
+            .set_max_operations(MAX_OPERATIONS_PER_BLOCK)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(4096)
+            .set_max_array_size(1 << 16)
+            .set_max_map_size(1024);
+
+        engine
+            .register_type_with_name::<Complex32>("Complex")
+            .register_fn("complex", Complex32::new)
+            .register_fn("polar", |r: FLOAT, theta: FLOAT| Complex32::from_polar(r, theta))
+            .register_get_set("re", |c: &mut Complex32| c.re, |c: &mut Complex32, re: FLOAT| c.re = re)
+            .register_get_set("im", |c: &mut Complex32| c.im, |c: &mut Complex32, im: FLOAT| c.im = im)
+            .register_fn("abs", |c: Complex32| c.norm())
+            .register_fn("arg", |c: Complex32| c.arg())
+            .register_fn("conj", |c: Complex32| c.conj())
+            .register_fn("exp", |c: Complex32| c.exp())
+            .register_fn("-", |c: Complex32| -c)
+            .register_fn("to_string", |c: &mut Complex32| format!("{}{:+}i", c.re, c.im))
+            .register_fn("to_debug", |c: &mut Complex32| format!("{}{:+}i", c.re, c.im));
+
+        macro_rules! register_ops {
+            ($($op:tt)*) => {$(
+                engine
+                    .register_fn(stringify!($op), |a: Complex32, b: Complex32| a $op b)
+                    .register_fn(stringify!($op), |a: Complex32, b: FLOAT| a $op b)
+                    .register_fn(stringify!($op), |a: FLOAT, b: Complex32| Complex32::from(a) $op b);
+            )*};
+        }
+        register_ops!(+ - * /);
+
+        engine
+    })
+}
+
+#[derive(Clone)]
+struct ScriptParam {
+    name: String,
+    range: RangeInclusive<f32>,
+    default: f32,
+}
+
+/// A script that compiled, and what it declared.
+#[derive(Clone)]
+struct Script {
+    ast: AST,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    params: Vec<ScriptParam>,
+    // made from `params` up front, so handing them out costs nothing
+    descriptors: Vec<ParamDescriptor>,
+}
+
+fn to_float(value: &Dynamic) -> Option<f32> {
+    value.as_float().ok().or_else(|| value.as_int().ok().map(|i| i as f32))
+}
+
+fn to_sample(value: Dynamic) -> QuadioSample {
+    match to_float(&value) {
+        Some(x) => QuadioSample::from(x),
+        None => value.try_cast::<Complex32>().unwrap_or_default(),
+    }
+}
+
+/// Calls `name()` if the script defines it, for one of the declarations.
+fn declaration(ast: &AST, name: &str) -> Result<Option<Array>, String> {
+    if !ast.iter_functions().any(|f| f.name == name && f.params.is_empty()) {
+        return Ok(None);
+    }
+    let options = CallFnOptions::new().eval_ast(false);
+    engine()
+        .call_fn_with_options::<Array>(options, &mut Scope::new(), ast, name, ())
+        .map(Some)
+        .map_err(|e| format!("{name}(): {e}"))
+}
+
+fn socket_labels(ast: &AST, name: &str, default: &str) -> Result<Vec<String>, String> {
+    let Some(labels) = declaration(ast, name)? else {
+        return Ok(vec![default.to_owned()]);
+    };
+    if labels.len() > MAX_SOCKETS {
+        return Err(format!("{name}(): at most {MAX_SOCKETS} sockets"));
+    }
+    labels
+        .into_iter()
+        .map(|label| label.into_string().map_err(|ty| format!("{name}(): expected strings, got {ty}")))
+        .collect()
+}
+
+fn script_params(ast: &AST) -> Result<Vec<ScriptParam>, String> {
+    let Some(params) = declaration(ast, "params")? else {
+        return Ok(vec![]);
+    };
+    let mut parsed: Vec<ScriptParam> = vec![];
+    for param in params {
+        let Some(map) = param.try_cast::<Map>() else {
+            return Err("params(): expected maps like #{ name: \"gain\", min: 0.0, max: 1.0 }".to_owned());
+        };
+        let Some(name) = map.get("name").and_then(|n| n.clone().into_string().ok()) else {
+            return Err("params(): every parameter needs a name".to_owned());
+        };
+        if parsed.iter().any(|p| p.name == name) {
+            return Err(format!("params(): {name:?} is declared twice"));
+        }
+        let number = |key: &str, default: f32| match map.get(key) {
+            Some(value) => to_float(value).ok_or_else(|| format!("params(): {name}.{key} should be a number")),
+            None => Ok(default),
+        };
+        let min = number("min", 0.0)?;
+        let max = number("max", 1.0)?;
+        if min > max {
+            return Err(format!("params(): {name} has min > max"));
+        }
+        let default = number("init", min)?.clamp(min, max);
+        parsed.push(ScriptParam { name, range: min..=max, default });
+    }
+    Ok(parsed)
+}
+
+fn compile(source: &str) -> Result<Script, String> {
+    let ast = engine().compile(source).map_err(|e| e.to_string())?;
+    if !ast.iter_functions().any(|f| f.name == "process" && f.params.len() == 3) {
+        return Err("no process(inputs, params, sample_rate) function".to_owned());
+    }
+    let params = script_params(&ast)?;
+    Ok(Script {
+        inputs: socket_labels(&ast, "inputs", "In")?,
+        outputs: socket_labels(&ast, "outputs", "Out")?,
+        descriptors: params
+            .iter()
+            .map(|p| ParamDescriptor::real(p.name.clone(), p.range.clone()))
+            .collect(),
+        params,
+        ast,
+    })
+}
+
+pub struct ScriptNode {
+    source: String,
+    /// the last version of `source` that compiled, which is what runs
+    script: Option<Script>,
+    compile_error: Option<String>,
+    runtime_error: Option<String>,
+    /// parallel to `script.params`
+    param_values: Vec<f32>,
+    /// `this` in the script
+    state: Dynamic,
+    descriptor_changed: bool,
+}
+impl Default for ScriptNode {
+    fn default() -> Self {
+        // no point compiling the same thing for every new script node
+        static DEFAULT: OnceLock<Script> = OnceLock::new();
+        let script = DEFAULT.get_or_init(|| compile(DEFAULT_SCRIPT).unwrap()).clone();
+        ScriptNode {
+            source: DEFAULT_SCRIPT.to_owned(),
+            param_values: script.params.iter().map(|p| p.default).collect(),
+            script: Some(script),
+            compile_error: None,
+            runtime_error: None,
+            state: Dynamic::from_map(Map::new()),
+            descriptor_changed: false,
+        }
+    }
+}
+impl ScriptNode {
+    /// Recompiles `source`. If it doesn't compile, the old script keeps
+    /// running and the error shows on the node.
+    fn reload(&mut self) {
+        let script = match compile(&self.source) {
+            Ok(script) => script,
+            Err(e) => {
+                self.compile_error = Some(e);
+                return;
+            }
+        };
+        self.compile_error = None;
+        self.runtime_error = None;
+
+        // parameters that are still around keep their values
+        let old = self.script.take();
+        let old_value = |name: &str| {
+            let old = old.as_ref()?;
+            let idx = old.params.iter().position(|p| p.name == name)?;
+            Some(self.param_values[idx])
+        };
+        let param_values = script
+            .params
+            .iter()
+            .map(|p| old_value(&p.name).map_or(p.default, |v| v.clamp(*p.range.start(), *p.range.end())))
+            .collect();
+        self.param_values = param_values;
+
+        let sockets_changed = old.is_none_or(|old| old.inputs != script.inputs || old.outputs != script.outputs);
+        self.descriptor_changed |= sockets_changed;
+        self.script = Some(script);
+    }
+}
+impl graph::Node for ScriptNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        let sockets = |labels: &[String]| {
+            labels
+                .iter()
+                .map(|label| SocketDescriptor {
+                    label: label.clone(),
+                    ..Default::default()
+                })
+                .collect()
+        };
+        match &self.script {
+            Some(script) => NodeDescriptor {
+                input_sockets: sockets(&script.inputs),
+                output_sockets: sockets(&script.outputs),
+            },
+            None => NodeDescriptor {
+                input_sockets: vec![],
+                output_sockets: vec![],
+            },
+        }
+    }
+
+    fn take_descriptor_changed(&mut self) -> bool {
+        std::mem::take(&mut self.descriptor_changed)
+    }
+}
+// by hand, since the script decides what the parameters are
+impl Params for ScriptNode {
+    fn param_descriptors(&self) -> &[ParamDescriptor] {
+        self.script.as_ref().map_or(&[], |script| &script.descriptors)
+    }
+    fn param(&self, idx: usize) -> ParamValue {
+        ParamValue::Real(self.param_values[idx])
+    }
+    fn set_param(&mut self, idx: usize, value: ParamValue) {
+        self.param_values[idx] = ParamType::from_value(value);
+    }
+}
+impl QuadioNode for ScriptNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("SCRIPT");
+        for error in [&self.compile_error, &self.runtime_error].into_iter().flatten() {
+            ui.colored_label(egui::Color32::from_rgb(0xED, 0x4C, 0x4C), error);
+        }
+        egui::CollapsingHeader::new("source").show(ui, |ui| {
+            let editor = egui::TextEdit::multiline(&mut self.source)
+                .code_editor()
+                .desired_rows(8)
+                .desired_width(320.0);
+            if ui.add(editor).changed() {
+                self.reload();
+            }
+        });
+    }
+
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        for output in outputs.iter_mut() {
+            output.fill(QuadioSample::from(0.0));
+        }
+        let Some(script) = &self.script else {
+            return;
+        };
+
+        let script_inputs: Array = inputs
+            .iter()
+            .map(|input| Dynamic::from_array(input.iter().map(|&x| Dynamic::from(x)).collect()))
+            .collect();
+        let params: Map = script
+            .params
+            .iter()
+            .zip(&self.param_values)
+            .map(|(p, &value)| (p.name.as_str().into(), Dynamic::from_float(value)))
+            .collect();
+
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        let args = (script_inputs, params, ctx.sample_rate as FLOAT);
+        let result = engine().call_fn_with_options::<Array>(options, &mut Scope::new(), &script.ast, "process", args);
+        match result {
+            Ok(script_outputs) => {
+                self.runtime_error = None;
+                for (script_output, output) in script_outputs.into_iter().zip(outputs.iter_mut()) {
+                    let Some(samples) = script_output.try_cast::<Array>() else {
+                        continue;
+                    };
+                    for (sample, out) in samples.into_iter().zip(output.iter_mut()) {
+                        *out = to_sample(sample);
+                    }
+                }
+            }
+            Err(e) => self.runtime_error = Some(e.to_string()),
+        }
+    }
+
+    fn save_state(&self) -> Option<String> {
+        Some(self.source.clone())
+    }
+
+    fn load_state(&mut self, state: &str) {
+        self.source = state.to_owned();
+        // if it doesn't compile, stay silent instead of running the default script
+        self.script = None;
+        self.param_values.clear();
+        self.reload();
+    }
+}
@@ -0,0 +1,70 @@
+//! Handing something (a scope's capture, say) from the audio thread to the
+//! UI without either side ever waiting for the other.
+//!
+//! There are three buffers: the writer fills one, the reader shows one, and
+//! the third is either waiting to be read or waiting to be reused. They go
+//! back and forth over two ring buffers, so nothing gets allocated or freed
+//! once it's running.
+
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+pub fn snapshot<T: Default>() -> (SnapshotWriter<T>, SnapshotReader<T>) {
+    let (full_tx, full_rx) = HeapRb::new(1).split();
+    let (mut free_tx, free_rx) = HeapRb::new(1).split();
+    let _ = free_tx.push(T::default());
+    (
+        SnapshotWriter {
+            back: T::default(),
+            full: full_tx,
+            free: free_rx,
+        },
+        SnapshotReader {
+            front: T::default(),
+            full: full_rx,
+            free: free_tx,
+        },
+    )
+}
+
+pub struct SnapshotWriter<T> {
+    back: T,
+    full: HeapProducer<T>,
+    free: HeapConsumer<T>,
+}
+impl<T> SnapshotWriter<T> {
+    /// What the next snapshot will be. It's whatever an earlier snapshot
+    /// was, so clear it out first.
+    pub fn back(&mut self) -> &mut T {
+        &mut self.back
+    }
+
+    /// Hands `back()` over to the reader. If the reader hasn't picked up the
+    /// last one yet, this one's dropped (and `back()` keeps it), and this
+    /// returns false.
+    pub fn publish(&mut self) -> bool {
+        let Some(fresh) = self.free.pop() else {
+            return false;
+        };
+        let filled = std::mem::replace(&mut self.back, fresh);
+        // can't be full: the buffer that would be in there is the one we just got
+        let _ = self.full.push(filled);
+        true
+    }
+}
+
+pub struct SnapshotReader<T> {
+    front: T,
+    full: HeapConsumer<T>,
+    free: HeapProducer<T>,
+}
+impl<T> SnapshotReader<T> {
+    /// The latest snapshot published, or the one before that if nothing's
+    /// been published since.
+    pub fn latest(&mut self) -> &T {
+        if let Some(newer) = self.full.pop() {
+            let older = std::mem::replace(&mut self.front, newer);
+            let _ = self.free.push(older);
+        }
+        &self.front
+    }
+}
@@ -0,0 +1,531 @@
+//! Subgraphs: a node with a whole graph inside it. `InletNode`s and
+//! `OutletNode`s inside become the subgraph's input and output sockets, in
+//! the order they were added (each remembers its place), labelled by their
+//! titles.
+
+use std::any::Any;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{AudioContext, AudioEngine};
+use crate::graph::{self, NodeDescriptor, NodeKey, NodeLayout, SocketDescriptor};
+use crate::node::QuadioNode;
+use crate::params;
+use crate::patch::Patch;
+use crate::sample::QuadioSample;
+
+/// Where a subgraph's input comes in. Only does anything inside a subgraph.
+#[derive(Default)]
+pub struct InletNode {
+    // which input socket it is; handed out by `number_ports`
+    index: Option<usize>,
+    // this block's input, put here by the subgraph
+    samples: Vec<QuadioSample>,
+}
+impl graph::Node for InletNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![],
+            output_sockets: vec![SocketDescriptor {
+                label: "Out".to_owned(),
+                ..Default::default()
+            }],
+        }
+    }
+}
+params!(InletNode {});
+impl QuadioNode for InletNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("INLET");
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, _inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        outputs[0].fill(QuadioSample::from(0.0));
+        for (x, out) in self.samples.iter().zip(outputs[0].iter_mut()) {
+            *out = *x;
+        }
+    }
+
+    fn save_state(&self) -> Option<String> {
+        self.index.map(|index| index.to_string())
+    }
+    fn load_state(&mut self, state: &str) {
+        self.index = state.parse().ok();
+    }
+}
+
+/// Where a subgraph's output goes out. Only does anything inside a subgraph.
+#[derive(Default)]
+pub struct OutletNode {
+    // which output socket it is; handed out by `number_ports`
+    index: Option<usize>,
+    // this block's output, picked up by the subgraph
+    samples: Vec<QuadioSample>,
+}
+impl graph::Node for OutletNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        NodeDescriptor {
+            input_sockets: vec![SocketDescriptor {
+                label: "In".to_owned(),
+                ..Default::default()
+            }],
+            output_sockets: vec![],
+        }
+    }
+}
+params!(OutletNode {});
+impl QuadioNode for OutletNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("OUTLET");
+    }
+
+    fn process(&mut self, _ctx: &AudioContext, inputs: &[&[QuadioSample]], _outputs: &mut [&mut [QuadioSample]]) {
+        self.samples.clear();
+        self.samples.extend_from_slice(inputs[0]);
+    }
+
+    fn save_state(&self) -> Option<String> {
+        self.index.map(|index| index.to_string())
+    }
+    fn load_state(&mut self, state: &str) {
+        self.index = state.parse().ok();
+    }
+}
+
+pub fn as_subgraph(node: &dyn QuadioNode) -> Option<&SubgraphNode> {
+    (node as &dyn Any).downcast_ref()
+}
+pub fn as_subgraph_mut(node: &mut dyn QuadioNode) -> Option<&mut SubgraphNode> {
+    (node as &mut dyn Any).downcast_mut()
+}
+
+/// Whether `node` is an inlet (or else an outlet), and its `index`; `None`
+/// if it's neither.
+fn port_index(node: &mut dyn QuadioNode) -> Option<(bool, &mut Option<usize>)> {
+    let node = node as &mut dyn Any;
+    if node.is::<InletNode>() {
+        return node.downcast_mut::<InletNode>().map(|inlet| (true, &mut inlet.index));
+    }
+    node.downcast_mut::<OutletNode>().map(|outlet| (false, &mut outlet.index))
+}
+
+/// Gives inlets and outlets that don't have a place yet (they've just been
+/// added, or came from a patch from before they had one) the next one after
+/// the rest.
+fn number_ports(graph: &mut Patch) {
+    for inlets in [true, false] {
+        let mut next = 0;
+        let mut unnumbered = false;
+        for (_, node, _) in graph.nodes_mut() {
+            match port_index(&mut **node) {
+                Some((is_inlet, Some(index))) if is_inlet == inlets => next = next.max(*index + 1),
+                Some((is_inlet, None)) if is_inlet == inlets => unnumbered = true,
+                _ => (),
+            }
+        }
+        if !unnumbered {
+            continue;
+        }
+        for (_, node, _) in graph.nodes_mut() {
+            if let Some((is_inlet, index @ None)) = port_index(&mut **node) {
+                if is_inlet == inlets {
+                    *index = Some(next);
+                    next += 1;
+                }
+            }
+        }
+    }
+}
+
+// inlet or outlet nodes, and their labels
+type Ports = Vec<(NodeKey, String)>;
+
+/// Inlets and outlets of `graph`, in socket order.
+fn ports(graph: &Patch) -> (Ports, Ports) {
+    let mut inlets = vec![];
+    let mut outlets = vec![];
+    for (key, node) in graph.nodes() {
+        let node: &dyn Any = &**node;
+        let (ports, index) = if let Some(inlet) = node.downcast_ref::<InletNode>() {
+            (&mut inlets, inlet.index)
+        } else if let Some(outlet) = node.downcast_ref::<OutletNode>() {
+            (&mut outlets, outlet.index)
+        } else {
+            continue;
+        };
+        ports.push((index.unwrap_or(usize::MAX), key));
+    }
+
+    let labelled = |mut ports: Vec<(usize, NodeKey)>, default: &str| -> Ports {
+        ports.sort();
+        ports
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, key))| {
+                let label = match &graph.layout(key).title {
+                    Some(title) => title.clone(),
+                    None => format!("{default} {}", i + 1),
+                };
+                (key, label)
+            })
+            .collect()
+    };
+    (labelled(inlets, "In"), labelled(outlets, "Out"))
+}
+
+/// What a subgraph's `save_state` looks like.
+#[derive(Deserialize)]
+struct SavedSubgraph {
+    name: String,
+    graph: Patch,
+}
+#[derive(Serialize)]
+struct SavedSubgraphRef<'a> {
+    name: &'a str,
+    graph: &'a Patch,
+}
+
+pub struct SubgraphNode {
+    /// what it's saved to the library as
+    pub name: String,
+    pub graph: Patch,
+    engine: AudioEngine,
+    // socket labels as of the last descriptor the outer graph got
+    labels: (Vec<String>, Vec<String>),
+    // the inlets and outlets, in socket order, as of `ports_generation` of
+    // the inner graph; kept so `process` doesn't have to go looking
+    inlets: Vec<NodeKey>,
+    outlets: Vec<NodeKey>,
+    ports_generation: u64,
+    // set by the Edit button, for the app to pick up with `take_open_request`
+    open_requested: bool,
+    // what happened the last time it was saved to the library
+    library_status: Option<Result<String, String>>,
+}
+impl Default for SubgraphNode {
+    fn default() -> Self {
+        // starts out as a wire from an inlet to an outlet
+        let mut graph = Patch::default();
+        let inlet = graph.add_node_with_layout(Box::<InletNode>::default() as _, NodeLayout::at([16.0, 16.0]));
+        let outlet = graph.add_node_with_layout(Box::<OutletNode>::default() as _, NodeLayout::at([256.0, 16.0]));
+        graph.connect((inlet, 0), (outlet, 0)).unwrap();
+        SubgraphNode::new("subgraph".to_owned(), graph)
+    }
+}
+impl SubgraphNode {
+    pub fn new(name: String, graph: Patch) -> SubgraphNode {
+        let mut node = SubgraphNode {
+            name,
+            labels: Default::default(),
+            inlets: vec![],
+            outlets: vec![],
+            ports_generation: 0,
+            graph,
+            engine: AudioEngine::new(48000.0, 1),
+            open_requested: false,
+            library_status: None,
+        };
+        node.labels = node.refresh_ports();
+        node
+    }
+
+    /// Re-finds the inlets and outlets, returning their labels.
+    fn refresh_ports(&mut self) -> (Vec<String>, Vec<String>) {
+        number_ports(&mut self.graph);
+        let (inlets, outlets) = ports(&self.graph);
+        self.inlets = inlets.iter().map(|&(key, _)| key).collect();
+        self.outlets = outlets.iter().map(|&(key, _)| key).collect();
+        self.ports_generation = self.graph.generation();
+        (
+            inlets.into_iter().map(|(_, label)| label).collect(),
+            outlets.into_iter().map(|(_, label)| label).collect(),
+        )
+    }
+}
+impl graph::Node for SubgraphNode {
+    fn get_descriptor(&self) -> NodeDescriptor {
+        let sockets = |labels: &[String]| {
+            labels
+                .iter()
+                .map(|label| SocketDescriptor {
+                    label: label.clone(),
+                    ..Default::default()
+                })
+                .collect()
+        };
+        NodeDescriptor {
+            input_sockets: sockets(&self.labels.0),
+            output_sockets: sockets(&self.labels.1),
+        }
+    }
+
+    fn take_descriptor_changed(&mut self) -> bool {
+        // nested subgraphs first, so changes bubble all the way up
+        self.graph.refresh_descriptors();
+
+        // titles can change without the graph's generation moving, so this
+        // always looks again
+        let labels = self.refresh_ports();
+        if labels == self.labels {
+            return false;
+        }
+        self.labels = labels;
+        true
+    }
+}
+params!(SubgraphNode {});
+impl QuadioNode for SubgraphNode {
+    fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("SUBGRAPH");
+        ui.horizontal(|ui| {
+            if ui.button("Edit").clicked() {
+                self.open_requested = true;
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(96.0));
+            if ui.button("Save").on_hover_text("save to the subgraph library").clicked() {
+                let library = SubgraphLibrary::default();
+                self.library_status = Some(
+                    library
+                        .save(&self.name, &self.graph)
+                        .map(|_| format!("saved {}", self.name))
+                        .map_err(|e| format!("{e:#}")),
+                );
+            }
+        });
+        match &self.library_status {
+            Some(Ok(msg)) => {
+                ui.label(msg);
+            }
+            Some(Err(msg)) => {
+                ui.colored_label(ui.visuals().error_fg_color, msg);
+            }
+            None => (),
+        }
+    }
+
+    fn process(&mut self, ctx: &AudioContext, inputs: &[&[QuadioSample]], outputs: &mut [&mut [QuadioSample]]) {
+        let Some(block_size) = outputs.first().map(|out| out.len()) else {
+            return;
+        };
+        if self.ports_generation != self.graph.generation() {
+            // edited without the outer graph noticing yet
+            self.refresh_ports();
+        }
+
+        for (key, input) in self.inlets.iter().zip(inputs) {
+            let inlet = (&mut **self.graph.get_node_mut(*key) as &mut dyn Any).downcast_mut::<InletNode>().unwrap();
+            inlet.samples.clear();
+            inlet.samples.extend_from_slice(input);
+        }
+
+        self.engine.run_sinks(ctx, &mut self.graph, &self.outlets, block_size);
+
+        for (key, output) in self.outlets.iter().zip(outputs.iter_mut()) {
+            let outlet = (&**self.graph.get_node(*key) as &dyn Any).downcast_ref::<OutletNode>().unwrap();
+            output.fill(QuadioSample::from(0.0));
+            for (x, out) in outlet.samples.iter().zip(output.iter_mut()) {
+                *out = *x;
+            }
+        }
+    }
+
+    fn save_state(&self) -> Option<String> {
+        let saved = SavedSubgraphRef {
+            name: &self.name,
+            graph: &self.graph,
+        };
+        match ron::to_string(&saved) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                eprintln!("couldn't save subgraph {}: {e}", self.name);
+                None
+            }
+        }
+    }
+
+    fn load_state(&mut self, state: &str) {
+        match ron::from_str::<SavedSubgraph>(state) {
+            Ok(mut saved) => {
+                for e in saved.graph.repair() {
+                    eprintln!("subgraph {} had to be repaired: {e}", saved.name);
+                }
+                *self = SubgraphNode::new(saved.name, saved.graph)
+            }
+            Err(e) => eprintln!("couldn't load subgraph: {e}"),
+        }
+    }
+}
+
+/// If a subgraph in `graph` had its Edit button clicked, which one.
+pub fn take_open_request(graph: &mut Patch) -> Option<NodeKey> {
+    graph.nodes_mut().find_map(|(key, node, _)| {
+        let subgraph = as_subgraph_mut(&mut **node)?;
+        std::mem::take(&mut subgraph.open_requested).then_some(key)
+    })
+}
+
+/// Follows `path` (subgraph nodes, each inside the last, starting from
+/// `graph`) to the graph being edited. The path is cut short where it stops
+/// leading to a subgraph, say because one was deleted.
+pub fn resolve_path<'a>(
+    graph: &'a mut Patch,
+    path: &mut Vec<NodeKey>,
+) -> &'a mut Patch {
+    let mut valid = 0;
+    let mut g: &Patch = graph;
+    for &key in path.iter() {
+        match g.contains_node(key).then(|| as_subgraph(&**g.get_node(key))).flatten() {
+            Some(subgraph) => g = &subgraph.graph,
+            None => break,
+        }
+        valid += 1;
+    }
+    path.truncate(valid);
+
+    let mut g = graph;
+    for &key in path.iter() {
+        g = &mut as_subgraph_mut(&mut **g.get_node_mut(key)).unwrap().graph;
+    }
+    g
+}
+
+/// "main > subgraph > ..." along the top of the editor, to get back out of
+/// subgraphs.
+pub fn path_ui(ui: &mut egui::Ui, main_graph: &Patch, path: &mut Vec<NodeKey>) {
+    let mut names = vec!["main".to_owned()];
+    let mut graph = main_graph;
+    for &key in path.iter() {
+        let Some(subgraph) = graph.contains_node(key).then(|| as_subgraph(&**graph.get_node(key))).flatten() else {
+            break;
+        };
+        names.push(graph.layout(key).title.clone().unwrap_or_else(|| subgraph.name.clone()));
+        graph = &subgraph.graph;
+    }
+
+    ui.horizontal(|ui| {
+        for (depth, name) in names.iter().enumerate() {
+            if depth > 0 {
+                ui.label(">");
+            }
+            let open = depth + 1 == names.len();
+            if ui.selectable_label(open, name).clicked() {
+                path.truncate(depth);
+            }
+        }
+    });
+}
+
+/// Saved subgraphs, as patch files: `<dir>/<name>.ron`.
+pub struct SubgraphLibrary {
+    dir: PathBuf,
+}
+impl Default for SubgraphLibrary {
+    fn default() -> Self {
+        SubgraphLibrary::new("subgraphs")
+    }
+}
+impl SubgraphLibrary {
+    pub fn new(dir: impl Into<PathBuf>) -> SubgraphLibrary {
+        SubgraphLibrary { dir: dir.into() }
+    }
+
+    /// Where `name` is saved. Names are kept to letters, digits, spaces,
+    /// `-` and `_`, so they can't point outside the library.
+    fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let allowed = |c: char| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_');
+        if name.trim().is_empty() || !name.chars().all(allowed) {
+            anyhow::bail!("{name:?} can't be a subgraph name; stick to letters, digits, spaces, - and _");
+        }
+        Ok(self.dir.join(format!("{name}.ron")))
+    }
+
+    /// The saved subgraphs, sorted. Empty if there aren't any (or the
+    /// directory can't be read).
+    pub fn list(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn save(&self, name: &str, graph: &Patch) -> anyhow::Result<()> {
+        let path = self.path(name)?;
+        std::fs::create_dir_all(&self.dir).with_context(|| format!("couldn't create {}", self.dir.display()))?;
+        crate::patch::save(graph, path)
+    }
+
+    pub fn load(&self, name: &str) -> anyhow::Result<SubgraphNode> {
+        let graph = crate::patch::load(self.path(name)?)?;
+        Ok(SubgraphNode::new(name.to_owned(), graph))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Node;
+
+    fn labels(subgraph: &SubgraphNode) -> Vec<String> {
+        subgraph.get_descriptor().input_sockets.into_iter().map(|s| s.label).collect()
+    }
+
+    fn add_inlet(subgraph: &mut SubgraphNode, title: &str) -> NodeKey {
+        let layout = NodeLayout {
+            title: Some(title.to_owned()),
+            ..Default::default()
+        };
+        let key = subgraph.graph.add_node_with_layout(Box::<InletNode>::default() as _, layout);
+        subgraph.take_descriptor_changed();
+        key
+    }
+
+    #[test]
+    fn ports_keep_the_order_they_were_added_in() {
+        let mut subgraph = SubgraphNode::default();
+        let b = add_inlet(&mut subgraph, "b");
+        add_inlet(&mut subgraph, "c");
+        subgraph.graph.remove_node(b);
+        // this one gets b's old slot, but still goes last
+        let d = add_inlet(&mut subgraph, "d");
+        assert_eq!(slotmap::Key::data(&d).as_ffi() as u32, slotmap::Key::data(&b).as_ffi() as u32);
+        assert_eq!(labels(&subgraph), ["In 1", "c", "d"]);
+
+        // and the order is saved with it
+        let mut outer = Patch::default();
+        let key = outer.add_node(Box::new(subgraph));
+        let loaded = crate::patch::from_str(&crate::patch::to_string(&outer).unwrap()).unwrap();
+        assert_eq!(labels(as_subgraph(&**loaded.get_node(key)).unwrap()), ["In 1", "c", "d"]);
+    }
+
+    #[test]
+    fn library_names_stay_in_the_library() {
+        let library = SubgraphLibrary::new("subgraphs");
+        for bad in ["../x", "a/b", "a\\b", "", "  ", ".hidden"] {
+            assert!(library.path(bad).is_err(), "{bad:?}");
+        }
+        assert_eq!(
+            library.path("my sub-graph_1").unwrap(),
+            PathBuf::from("subgraphs").join("my sub-graph_1.ron")
+        );
+    }
+
+    #[test]
+    fn inner_automation_plays() {
+        let mut subgraph = SubgraphNode::default();
+        subgraph.graph.automation_mut().set_playing(true);
+        let ctx = AudioContext { sample_rate: 48000.0 };
+        let input = vec![QuadioSample::from(0.0); 480];
+        let mut output = vec![QuadioSample::from(0.0); 480];
+        subgraph.process(&ctx, &[&input], &mut [&mut output]);
+        assert!((subgraph.graph.automation().position() - 0.01).abs() < 1e-9);
+    }
+}